@@ -6,7 +6,7 @@ use r_tracing::init_logging;
 use crate::commands::interact;
 
 #[cfg(feature = "api")]
-use crate::commands::{api, db, key, manage};
+use crate::commands::{api, db, key, manage, seed};
 
 #[derive(Parser)]
 #[clap(version, about, propagate_version = true)]
@@ -39,6 +39,9 @@ pub enum Commands {
     #[cfg(feature = "api")]
     #[command(name = "manage", about = "Manage keypairs through HTTP requests")]
     Manage(manage::Command),
+    #[cfg(feature = "api")]
+    #[command(name = "seed", about = "Split the database seed to unseal the API")]
+    Seed(seed::Command),
     #[cfg(feature = "interact")]
     #[command(name = "interact", about = "Interactively manage keypairs through HTTP request")]
     Interact(interact::Command),
@@ -61,6 +64,8 @@ pub async fn run() -> eyre::Result<()> {
         Commands::Key(command) => command.execute().await?,
         #[cfg(feature = "api")]
         Commands::Manage(command) => command.execute().await?,
+        #[cfg(feature = "api")]
+        Commands::Seed(command) => command.execute().await?,
         #[cfg(feature = "interact")]
         Commands::Interact(command) => command.execute().await?,
     }
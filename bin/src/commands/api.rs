@@ -20,6 +20,28 @@ pub struct Command {
     #[arg(long, value_name = "seed", env("SEED"), hide_env_values = true)]
     seed: Option<String>,
 
+    /// The key-encryption key instead of the seed: `file:<path>`, `passphrase:<salt>` with the
+    /// passphrase in `KEK_PASSPHRASE`, or `transit:<address>/<mount>/<key>` with the token in
+    /// `VAULT_TOKEN`.
+    #[arg(long, value_name = "kek", env("KEK"), hide_env_values = true, conflicts_with = "seed")]
+    kek: Option<String>,
+
+    /// The seeds or KEKs used before, to read the secrets `anita db rotate-seed` has not
+    /// re-encrypted yet, comma separated.
+    #[arg(
+        long,
+        value_name = "kek",
+        env("PREVIOUS_KEKS"),
+        hide_env_values = true,
+        value_delimiter = ','
+    )]
+    previous_keks: Vec<String>,
+
+    /// Start without the seed, key operations are refused until its shares are given to
+    /// `/sys/unseal`.
+    #[arg(long, env("SEALED"), conflicts_with_all = ["seed", "kek"])]
+    sealed: bool,
+
     /// Number of threads to use
     #[arg(short, long, default_value_t = 3000, env("PORT"))]
     port: u16,
@@ -28,11 +50,17 @@ pub struct Command {
 impl Command {
     /// Execute `api` command
     pub async fn execute(self) -> eyre::Result<()> {
-        let seed = self
-            .seed
-            .map(|s| Database::to_seed(s.as_str()).expect("Seed must be a valid hex string"));
-        let database = Database::new_with_url(self.database_url.as_str(), seed).await;
-        let _ = init_api(self.port, database).await?;
+        let kek = self.kek.or(self.seed).map(|kek| Database::to_kek(kek.as_str())).transpose()?;
+        let previous = self
+            .previous_keks
+            .iter()
+            .map(|kek| Database::to_kek(kek.as_str()))
+            .collect::<Result<_, _>>()?;
+        let database = match self.sealed {
+            true => Database::new_sealed(self.database_url.as_str(), previous).await?,
+            false => Database::new_with_url(self.database_url.as_str(), kek, previous).await?,
+        };
+        init_api(self.port, database).await?;
         Ok(())
     }
 }
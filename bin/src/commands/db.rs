@@ -1,7 +1,14 @@
 //! Database debugging tool
 
+use std::{str::FromStr, sync::Arc};
+
 use clap::{Parser, Subcommand};
-use r_storage::prelude::{get_db_version, run_migrations, Database};
+use eyre::eyre;
+use r_keys::registry;
+use r_storage::prelude::{
+    get_db_version, run_migrations, Chain, Database, DetectedBatch, EncryptionMode,
+    EncryptionTrait, MetaTrait, PlaintextCheck, RotationTrait, SecretRow, SecretTable,
+};
 
 #[derive(Debug, Parser)]
 pub struct Command {
@@ -20,6 +27,75 @@ pub enum Subcommands {
     Migration,
     /// Lists current and local database versions
     Version,
+    /// Record the seed or KEK, or that there is none, so the API and `anita key` refuse to start
+    /// with another one
+    InitSeed {
+        /// The database seed, the secrets are stored in plaintext without it or `--kek`.
+        #[arg(long, value_name = "seed", env("SEED"), hide_env_values = true)]
+        seed: Option<String>,
+
+        /// The key-encryption key instead of the seed, see `anita api --kek`
+        #[arg(
+            long,
+            value_name = "kek",
+            env("KEK"),
+            hide_env_values = true,
+            conflicts_with = "seed"
+        )]
+        kek: Option<String>,
+
+        /// Replace the seed recorded before
+        #[arg(long)]
+        force: bool,
+    },
+    /// Wrap the data keys of the stored secrets with a new seed or KEK, in batches that can be
+    /// resumed
+    RotateSeed {
+        /// The seeds or KEKs the secrets are encrypted with now, comma separated, see `anita api
+        /// --kek`
+        #[arg(
+            long,
+            value_name = "kek",
+            env("SEED"),
+            hide_env_values = true,
+            value_delimiter = ',',
+            required = true
+        )]
+        seed: Vec<String>,
+
+        /// The seed or KEK to wrap the data keys with
+        #[arg(long, value_name = "kek", env("NEW_SEED"), hide_env_values = true)]
+        new_seed: String,
+
+        /// The number of rows re-encrypted in one transaction
+        #[arg(long, default_value_t = 100)]
+        batch_size: i64,
+    },
+    /// Encrypt the secrets stored in plaintext, in batches that can be resumed
+    EncryptExisting {
+        /// The seed or KEK to encrypt the secrets with, see `anita api --kek`
+        #[arg(long, value_name = "kek", env("SEED"), hide_env_values = true)]
+        seed: String,
+
+        /// The seeds or KEKs used before, the rows not marked as encrypted that they decrypt are
+        /// marked, comma separated
+        #[arg(
+            long,
+            value_name = "kek",
+            env("PREVIOUS_KEKS"),
+            hide_env_values = true,
+            value_delimiter = ','
+        )]
+        previous_keks: Vec<String>,
+
+        /// The number of rows encrypted in one transaction
+        #[arg(long, default_value_t = 100)]
+        batch_size: i64,
+
+        /// Count the rows by encryption state without changing anything
+        #[arg(long)]
+        report: bool,
+    },
 }
 
 impl Command {
@@ -33,13 +109,151 @@ impl Command {
                 println!("database migrations complete")
             }
             Subcommands::Version {} => {
-                let database = Database::new_with_url(database_url, None).await;
+                let database = Database::connect(database_url, None).await;
                 let mut conn = database.with_conn().await.expect("could not get connection");
 
                 let version = get_db_version(&mut conn).await;
                 println!("database version {}", version);
             }
+            Subcommands::RotateSeed { seed, new_seed, batch_size } => {
+                let previous =
+                    seed.iter().map(|kek| Database::to_kek(kek)).collect::<Result<_, _>>()?;
+                let kek = Database::to_kek(&new_seed)?;
+                let database = Database::new_with_url(database_url, Some(kek), previous).await?;
+                rotate_seed(&database, batch_size).await?;
+                database.init_seed(true).await?;
+                println!("the canary is encrypted with the new seed");
+            }
+            Subcommands::EncryptExisting { seed, previous_keks, batch_size, report } => {
+                let previous = previous_keks
+                    .iter()
+                    .map(|kek| Database::to_kek(kek))
+                    .collect::<Result<_, _>>()?;
+                let database = Database::connect(database_url, Some(Database::to_kek(&seed)?))
+                    .await
+                    .with_previous_keks(previous);
+                if database.encryption_mode().await? == Some(EncryptionMode::Encrypted) {
+                    database.check_seed().await?;
+                }
+                let unknown = encrypt_existing(&database, batch_size, report).await?;
+                if report {
+                    return Ok(());
+                }
+                database.init_seed(true).await?;
+                println!("the database is encrypted with the seed");
+                if unknown > 0 {
+                    return Err(eyre!("{} rows are left as they are", unknown));
+                }
+            }
+            Subcommands::InitSeed { seed, kek, force } => {
+                let kek = kek.or(seed).map(|kek| Database::to_kek(kek.as_str())).transpose()?;
+                let database = Database::connect(database_url, kek).await;
+                let mode = database.init_seed(force).await?;
+                println!("the secrets are {}", mode);
+            }
         }
         Ok(())
     }
 }
+
+/// Re-encrypt every table, printing the progress after each batch.
+/// The rows already encrypted with the new seed are skipped, so running it again resumes it.
+async fn rotate_seed(database: &Database, batch_size: i64) -> eyre::Result<()> {
+    let mut failed = 0;
+    for table in SecretTable::ALL {
+        let total = database.count_unrotated(table).await?;
+        if total == 0 {
+            println!("{}: every row is encrypted with the new seed", table);
+            continue;
+        }
+        let (mut after, mut rotated, mut failed_ids) = (0, 0, vec![]);
+        loop {
+            let batch = database.rotate_batch(table, after, batch_size).await?;
+            let Some(last_id) = batch.last_id else { break };
+            after = last_id;
+            rotated += batch.rotated;
+            failed_ids.extend(batch.failed);
+            println!("{}: {}/{} re-encrypted", table, rotated, total);
+        }
+        if !failed_ids.is_empty() {
+            println!(
+                "{}: {} rows could not be decrypted: {:?}",
+                table,
+                failed_ids.len(),
+                failed_ids
+            );
+        }
+        failed += failed_ids.len();
+    }
+    match failed {
+        0 => Ok(()),
+        n => Err(eyre!(
+            "{} rows were not rotated, add the seeds they are encrypted with, or run `anita db \
+             encrypt-existing` if they are in plaintext",
+            n
+        )),
+    }
+}
+
+/// Encrypt the rows of every table not marked as encrypted, or only count them with `report`.
+/// Returns the number of rows neither in plaintext nor encrypted with the seeds.
+async fn encrypt_existing(
+    database: &Database,
+    batch_size: i64,
+    report: bool,
+) -> eyre::Result<usize> {
+    let check: PlaintextCheck = Arc::new(is_plaintext);
+    let mut unknown = 0;
+    for table in SecretTable::ALL {
+        let counts = database.count_by_encryption(table).await?;
+        println!(
+            "{}: {} encrypted, {} plaintext, {} unmarked",
+            table, counts.encrypted, counts.plaintext, counts.unmarked
+        );
+        if counts.plaintext + counts.unmarked == 0 {
+            continue;
+        }
+        let (mut after, mut detected) = (0, DetectedBatch::default());
+        loop {
+            let batch =
+                database.encrypt_batch(table, after, batch_size, check.clone(), report).await?;
+            let Some(last_id) = batch.last_id else { break };
+            after = last_id;
+            detected.plaintext += batch.plaintext;
+            detected.encrypted += batch.encrypted;
+            detected.unknown.extend(batch.unknown);
+            if !report {
+                println!("{}: {} encrypted", table, detected.plaintext);
+            }
+        }
+        match report {
+            true => println!(
+                "{}: {} rows to encrypt, {} unmarked rows are encrypted",
+                table, detected.plaintext, detected.encrypted
+            ),
+            false => println!("{}: {} unmarked rows are encrypted", table, detected.encrypted),
+        }
+        if !detected.unknown.is_empty() {
+            println!(
+                "{}: {} rows are neither in plaintext nor encrypted with the seeds: {:?}",
+                table,
+                detected.unknown.len(),
+                detected.unknown
+            );
+        }
+        unknown += detected.unknown.len();
+    }
+    Ok(unknown)
+}
+
+/// The keypair of a key has the pubkey of its row, the seed of a wallet is a 64 bytes BIP-39 seed.
+fn is_plaintext(row: &SecretRow) -> bool {
+    let secret = row.saved.secret.as_slice();
+    let Some(pubkey) = row.pubkey.as_deref() else { return secret.len() == 64 };
+    let Some(spec) = Chain::from_str(&row.chain).ok().and_then(|chain| registry::get(chain).ok())
+    else {
+        return false;
+    };
+    let mut keypair = spec.keypair();
+    keypair.recover_from_bytes(secret).is_ok() && keypair.pubkey() == pubkey
+}
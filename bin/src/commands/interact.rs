@@ -3,7 +3,10 @@ use dialoguer::{theme::ColorfulTheme, Input, Password, Select};
 use reqwest::{cookie::Jar, Client, Url};
 use std::sync::Arc;
 
-use crate::handlers::auth::{key_gen, key_sign, login, logout};
+use crate::{
+    handlers::auth::{key_gen, key_sign, login, logout},
+    keys::registry,
+};
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
@@ -22,11 +25,7 @@ impl Command {
             .build()?;
 
         let theme = ColorfulTheme::default();
-        let selections = &[
-            "solana",
-            // "ethereum",
-            // "bitcoin",
-        ];
+        let selections: Vec<_> = registry::chains().iter().map(|spec| spec.name).collect();
         let selection = Select::with_theme(&theme)
             .with_prompt("Chain to use")
             .default(0)
@@ -76,7 +75,8 @@ impl Command {
                     let message: String =
                         Input::with_theme(&theme).with_prompt("Message:").interact_text()?;
                     let signature =
-                        key_sign(&client, &base, chain, pubkey.as_str(), message.as_str()).await?;
+                        key_sign(&client, &base, chain, pubkey.as_str(), message.as_str(), "raw")
+                            .await?;
                     println!("{:?}", signature.to_string());
                 }
                 _ => {
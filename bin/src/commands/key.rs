@@ -1,36 +1,65 @@
+use std::{io::Write, path::PathBuf, str::FromStr, time::Duration};
+
 use clap::{Parser, Subcommand};
+use eyre::eyre;
 
+#[cfg(feature = "ethereum")]
+use crate::keys::{ethereum_tx::EthTransaction, EthereumKeyPair, KeypairStrategy};
 use crate::{
-    keys::keygen::keygen,
-    storage::{Chain, Database, KeyTrait, NewKey},
+    keys::{
+        hd,
+        keyfile::{self, KeyFormat},
+        keygen::{Fill, Search},
+        matcher, registry, shamir, CaseMode, KeyWithSecretExt, KeypairContext, Matcher, NewKeyExt,
+        Pattern, SecretString, SignScheme,
+    },
+    storage::{Chain, Database, KeyTrait, NewKey, NewWallet, WalletTrait},
 };
 
 #[derive(Parser, Debug)]
 #[command(version, about, long_about = None)]
 pub struct Command {
-    /// The database to save the keys.
-    #[arg(
-        short,
-        long,
-        value_name = "database_url",
-        env("DATABASE_URL"),
-        hide_env_values = true,
-        required = true
-    )]
-    database_url: String,
+    /// The database to save the keys, required by every subcommand but `verify`.
+    #[arg(short, long, value_name = "database_url", env("DATABASE_URL"), hide_env_values = true)]
+    database_url: Option<String>,
 
     /// The database seed.
     #[arg(long, value_name = "seed", env("SEED"), hide_env_values = true)]
     seed: Option<String>,
 
+    /// The key-encryption key instead of the seed: `file:<path>`, `passphrase:<salt>` with the
+    /// passphrase in `KEK_PASSPHRASE`, or `transit:<address>/<mount>/<key>` with the token in
+    /// `VAULT_TOKEN`.
+    #[arg(long, value_name = "kek", env("KEK"), hide_env_values = true, conflicts_with = "seed")]
+    kek: Option<String>,
+
+    /// The seeds or KEKs used before, to read the secrets `anita db rotate-seed` has not
+    /// re-encrypted yet, comma separated.
+    #[arg(
+        long,
+        value_name = "kek",
+        env("PREVIOUS_KEKS"),
+        hide_env_values = true,
+        value_delimiter = ','
+    )]
+    previous_keks: Vec<String>,
+
     /// The chain to use
-    #[clap(short, long, value_enum, default_value_t = Chain::Solana)]
+    #[clap(short, long, value_parser = registry::chain_parser(), default_value = registry::default_name())]
     chain: Chain,
 
     /// The suffix to search
     #[arg(short, long, default_value = "sol")]
     suffix: String,
 
+    /// The patterns to search instead of the suffix: `abc*`, `*xyz`, `ab*yz` or `/regex/`
+    #[arg(short, long)]
+    pattern: Vec<String>,
+
+    /// Match the patterns case-insensitively
+    #[arg(long)]
+    ignore_case: bool,
+
     #[clap(subcommand)]
     command: Subcommands,
 }
@@ -42,15 +71,141 @@ pub enum Subcommands {
     Get,
     /// New a keypair
     New {
-        /// Number of threads to use
+        /// Number of threads to use, 0 for every core
         #[arg(short, long, default_value_t = 4)]
         count: u8,
+
+        /// Give up after this many seconds
+        #[arg(short, long)]
+        timeout: Option<u64>,
     },
     /// Vanity keypairs
     Vanity {
-        /// Number of threads to use
+        /// Number of threads to use, 0 for every core
+        #[arg(short, long, default_value_t = 4)]
+        count: u8,
+    },
+    /// Grind keys for several targets in one pass until each has its count
+    Fill {
+        /// Number of threads to use, 0 for every core
         #[arg(short, long, default_value_t = 4)]
         count: u8,
+
+        /// A pattern and the number of keys wanted for it, e.g. `sol=10` or `ab*=5`
+        #[arg(short, long = "target", value_parser = parse_target, required = true)]
+        targets: Vec<(String, usize)>,
+    },
+    /// Save the root of a wallet restored from, or generated as, a BIP-39 mnemonic
+    Wallet {
+        /// The mnemonic to restore, a new one is generated if omitted
+        #[arg(long, env("MNEMONIC"), hide_env_values = true)]
+        mnemonic: Option<String>,
+
+        /// The optional BIP-39 passphrase
+        #[arg(long, env("PASSPHRASE"), hide_env_values = true)]
+        passphrase: Option<String>,
+
+        /// The derivation path template, `{index}` is the child index [default: the chain's]
+        #[arg(long)]
+        path: Option<String>,
+    },
+    /// Derive the child keypair at an index of a saved wallet
+    Derive {
+        /// The wallet id
+        #[arg(short, long)]
+        wallet: i32,
+
+        /// The child index
+        #[arg(short, long)]
+        index: u32,
+    },
+    /// Sign an Ethereum transaction offline with a saved key
+    #[cfg(feature = "ethereum")]
+    SignTx {
+        /// The pubkey of the sender
+        #[arg(long)]
+        pubkey: String,
+
+        #[command(flatten)]
+        transaction: Box<EthTransaction>,
+    },
+    /// Import a keypair from a key file, encrypted with the database seed
+    Import {
+        /// The key file, stdin if omitted
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// The key file format
+        #[arg(long, value_enum)]
+        format: KeyFormat,
+
+        /// The keystore password
+        #[arg(long, env("KEYSTORE_PASSWORD"), hide_env_values = true)]
+        password: Option<String>,
+    },
+    /// Export a saved keypair to a key file, encrypted with a password
+    Export {
+        /// The pubkey of the keypair
+        #[arg(long)]
+        pubkey: String,
+
+        /// The key file format
+        #[arg(long, value_enum, default_value_t = KeyFormat::Keystore)]
+        format: KeyFormat,
+
+        /// The keystore password
+        #[arg(long, env("KEYSTORE_PASSWORD"), hide_env_values = true)]
+        password: Option<String>,
+
+        /// The key file to write, stdout if omitted
+        #[arg(short, long)]
+        output: Option<PathBuf>,
+
+        /// Write the secret in a plaintext format
+        #[arg(long)]
+        force: bool,
+    },
+    /// Split a saved keypair into Shamir shares, printed one per line
+    Split {
+        /// The pubkey of the keypair
+        #[arg(long)]
+        pubkey: String,
+
+        /// The number of shares that rebuild the keypair
+        #[arg(short, long, default_value_t = 3)]
+        threshold: u8,
+
+        /// The number of shares
+        #[arg(long, default_value_t = 5)]
+        shares: u8,
+    },
+    /// Rebuild a keypair from Shamir shares, no database needed unless `--import`
+    Combine {
+        /// The shares, one per line, stdin if omitted
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+
+        /// Save the keypair, encrypted with the database seed
+        #[arg(long)]
+        import: bool,
+    },
+    /// Verify a signature offline, no database needed
+    Verify {
+        /// The pubkey or address of the signer
+        #[arg(long)]
+        pubkey: String,
+
+        /// The signed message
+        #[arg(short, long)]
+        message: String,
+
+        /// The signature in the chain's encoding
+        #[arg(long)]
+        signature: String,
+
+        /// How the message was hashed before signing
+        #[arg(long, value_enum, default_value_t = SignScheme::Raw)]
+        scheme: SignScheme,
     },
 }
 
@@ -60,39 +215,304 @@ impl Command {
         dotenvy::dotenv().ok();
 
         let chain = self.chain;
+        if let Subcommands::Verify { ref pubkey, ref message, ref signature, scheme } = self.command
+        {
+            let keypair = KeypairContext::create_keypair(chain)?;
+            let valid = keypair.verify_with(scheme, pubkey, message.as_bytes(), signature)?;
+            println!("valid: {}", valid);
+            return match valid {
+                true => Ok(()),
+                false => Err(eyre!("the signature does not match")),
+            };
+        }
+        if let Subcommands::Combine { ref file, import: false } = self.command {
+            let context = combine(file.as_deref())?;
+            println!("key: {}", context.keypair().secret().as_str());
+            println!("address : {}", context.keypair().address());
+            return Ok(());
+        }
+        let database_url =
+            self.database_url.ok_or_else(|| eyre!("--database-url or DATABASE_URL is required"))?;
         let suffix = self.suffix;
-        let seed = self
-            .seed
-            .map(|s| Database::to_seed(s.as_str()).expect("Seed must be a valid hex string"));
-        let database = Database::new_with_url(self.database_url.as_str(), seed).await;
+        let kek = self.kek.or(self.seed).map(|kek| Database::to_kek(kek.as_str())).transpose()?;
+        let patterns = match self.pattern.is_empty() {
+            true => vec![suffix.clone()],
+            false => self.pattern,
+        };
+        let case = match self.ignore_case {
+            true => CaseMode::Insensitive,
+            false => CaseMode::Sensitive,
+        };
+        let previous = self
+            .previous_keks
+            .iter()
+            .map(|kek| Database::to_kek(kek.as_str()))
+            .collect::<Result<_, _>>()?;
+        let database = Database::new_with_url(database_url.as_str(), kek, previous).await?;
         match self.command {
             Subcommands::Get => {
                 let key = database.get_key_by_suffix(chain, suffix.as_str()).await?;
                 println!("key: {:?}", key);
             }
-            Subcommands::New { count, .. } => {
-                let context = keygen(count, suffix.as_str(), chain);
+            Subcommands::New { count, timeout } => {
+                let matcher = Matcher::parse(chain, &patterns, case)?;
+                let (context, pattern) =
+                    search(count, matcher, timeout.map(Duration::from_secs)).await?;
                 let keypair = context.keypair();
 
-                let key = NewKey::from_keypair(keypair, Some(suffix.clone()));
+                let key = matcher::new_key(keypair, &pattern);
                 let _ = database.create_key(key).await?;
 
-                println!("key: {}", keypair.secret());
+                println!("key: {}", keypair.secret().as_str());
                 println!("address : {}", keypair.address());
+                println!("pattern : {}", pattern);
             }
-            Subcommands::Vanity { count, .. } => loop {
-                let context = keygen(count, suffix.as_str(), chain);
-                let keypair = context.keypair();
+            Subcommands::Vanity { count, .. } => {
+                let matcher = Matcher::parse(chain, &patterns, case)?;
+                loop {
+                    let (context, pattern) = search(count, matcher.clone(), None).await?;
+                    let keypair = context.keypair();
 
-                let mut key = NewKey::from_keypair(keypair, Some(suffix.clone()));
-                key.used_at = Some(chrono::Utc::now().naive_utc());
+                    let mut key = matcher::new_key(keypair, &pattern);
+                    key.used_at = Some(chrono::Utc::now().naive_utc());
 
+                    let _ = database.create_key(key).await?;
+                    println!("key: {}", keypair.secret().as_str());
+                    println!("address : {}", keypair.address());
+                    println!("pattern : {}", pattern);
+                }
+            }
+            Subcommands::Fill { count, targets } => {
+                let (patterns, quotas): (Vec<_>, Vec<_>) = targets.into_iter().unzip();
+                let matcher = Matcher::parse(chain, &patterns, case)?;
+                fill(&database, count, matcher, quotas).await?;
+            }
+            Subcommands::Wallet { mnemonic, passphrase, path } => {
+                let mnemonic = match mnemonic {
+                    Some(mnemonic) => mnemonic,
+                    None => {
+                        let mnemonic = hd::generate_mnemonic(24)?;
+                        println!("mnemonic: {}", mnemonic);
+                        mnemonic
+                    }
+                };
+                let path = match path {
+                    Some(path) => path,
+                    None => hd::default_path(chain)?.to_string(),
+                };
+                let seed = hd::mnemonic_to_seed(mnemonic.as_str(), passphrase.as_deref())?;
+
+                // make sure the path template derives before saving the root
+                let context =
+                    KeypairContext::from_seed(chain, seed.as_slice(), &hd::path_at(&path, 0))?;
+
+                let wallet = NewWallet::new(chain.to_string(), seed, path);
+                let wallet = database.create_wallet(wallet).await?;
+                println!("wallet: {}", wallet.id);
+                println!("address 0 : {}", context.keypair().address());
+            }
+            Subcommands::Derive { wallet, index } => {
+                let wallet = database
+                    .get_wallet_by_id(wallet)
+                    .await?
+                    .ok_or_else(|| eyre!("wallet {} is not found", wallet))?;
+                let chain = Chain::from_str(wallet.chain.as_str())?;
+                let path = hd::path_at(wallet.path.as_str(), index);
+
+                let context = KeypairContext::from_seed(chain, wallet.seed().as_slice(), &path)?;
+                let keypair = context.keypair();
+
+                let key = NewKey::from_keypair(keypair, None);
                 let _ = database.create_key(key).await?;
-                println!("key: {}", keypair.secret());
+                println!("path: {}", path);
+                println!("address : {}", keypair.address());
+            }
+            Subcommands::Import { file, format, password } => {
+                let contents = match file {
+                    Some(file) => std::fs::read_to_string(file)?,
+                    None => std::io::read_to_string(std::io::stdin())?,
+                };
+                let context = keyfile::import(chain, format, &contents, password.as_deref())?;
+                let keypair = context.keypair();
+
+                let key = NewKey::from_keypair(keypair, None);
+                let key = database.create_key(key).await?;
+                println!("key: {}", key.id);
+                println!("address : {}", keypair.address());
+            }
+            Subcommands::Export { pubkey, format, password, output, force } => {
+                if !format.is_encrypted() && !force {
+                    return Err(eyre!(
+                        "{:?} writes the secret in plaintext, use --force to allow it",
+                        format
+                    ));
+                }
+                let key = database
+                    .get_secret_by_pubkey(chain, pubkey.as_str())
+                    .await?
+                    .ok_or_else(|| eyre!("key {} is not found", pubkey))?;
+                let keypair = key.keypair()?;
+                let contents = keyfile::export(keypair.as_ref(), format, password.as_deref())?;
+
+                match output {
+                    Some(output) => write_private(&output, contents.as_bytes())?,
+                    None => println!("{}", contents),
+                }
+            }
+            Subcommands::Split { pubkey, threshold, shares } => {
+                let key = database
+                    .get_secret_by_pubkey(chain, pubkey.as_str())
+                    .await?
+                    .ok_or_else(|| eyre!("key {} is not found", pubkey))?;
+                let keypair = key.keypair()?;
+                for share in shamir::split(keypair.as_ref(), threshold, shares)? {
+                    println!("{}", share.as_str());
+                }
+            }
+            Subcommands::Combine { file, .. } => {
+                let context = combine(file.as_deref())?;
+                let keypair = context.keypair();
+
+                let key = NewKey::from_keypair(keypair, None);
+                let key = database.create_key(key).await?;
+                println!("key: {}", key.id);
                 println!("address : {}", keypair.address());
-            },
+            }
+            Subcommands::Verify { .. } => unreachable!("verified without the database"),
+            #[cfg(feature = "ethereum")]
+            Subcommands::SignTx { pubkey, transaction } => {
+                let key = database
+                    .get_secret_by_pubkey(Chain::Ethereum, pubkey.as_str())
+                    .await?
+                    .ok_or_else(|| eyre!("key {} is not found", pubkey))?;
+                let mut keypair = EthereumKeyPair::new();
+                keypair.recover_from_bytes(key.secret().as_slice())?;
+                key.check_keypair(&keypair)?;
+
+                let signed = transaction.sign(&keypair)?;
+                println!("raw : {}", signed.raw);
+                println!("hash : {}", signed.hash);
+            }
         }
 
         Ok(())
     }
 }
+
+/// Rebuild a keypair from the shares in the file, one per line.
+fn combine(file: Option<&std::path::Path>) -> eyre::Result<KeypairContext> {
+    let contents = SecretString::new(match file {
+        Some(file) => std::fs::read_to_string(file)?,
+        None => std::io::read_to_string(std::io::stdin())?,
+    });
+    let shares: Vec<_> =
+        contents.as_str().lines().map(str::trim).filter(|line| !line.is_empty()).collect();
+    Ok(shamir::combine(&shares)?)
+}
+
+/// Write a key file readable by its owner only.
+fn write_private(path: &std::path::Path, contents: &[u8]) -> eyre::Result<()> {
+    let mut options = std::fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)?.write_all(contents)?;
+    Ok(())
+}
+
+/// How often the progress line is refreshed.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(250);
+
+/// Run a vanity search, rendering its progress on stderr until it ends or Ctrl-C.
+async fn search(
+    count: u8,
+    matcher: Matcher,
+    timeout: Option<Duration>,
+) -> eyre::Result<(KeypairContext, Pattern)> {
+    let search = Search::start(count, matcher, timeout)?;
+
+    let token = search.cancellation_token();
+    let interrupt = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            token.cancel();
+        }
+    });
+
+    let found = loop {
+        match search.try_result() {
+            Ok(Some(found)) => break Ok(found),
+            Ok(None) => {
+                eprint!("\r\x1b[2K{}", search.stats());
+                std::io::stderr().flush()?;
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    eprintln!("\r\x1b[2K{}", search.stats());
+    interrupt.abort();
+
+    Ok(found?)
+}
+
+/// How many found keys are inserted at once.
+const FILL_BATCH_SIZE: usize = 64;
+
+/// Parse a `PATTERN=COUNT` target.
+fn parse_target(s: &str) -> Result<(String, usize), String> {
+    let (pattern, count) =
+        s.rsplit_once('=').ok_or_else(|| format!("expected PATTERN=COUNT, got `{s}`"))?;
+    let count = count.parse::<usize>().map_err(|e| format!("invalid count `{count}`: {e}"))?;
+    Ok((pattern.to_string(), count))
+}
+
+/// Fill the quota of every pattern in one search, saving the keys in batches as they come.
+async fn fill(
+    database: &Database,
+    count: u8,
+    matcher: Matcher,
+    quotas: Vec<usize>,
+) -> eyre::Result<()> {
+    let mut fill = Fill::start(count, matcher, quotas, None)?;
+
+    let token = fill.cancellation_token();
+    let interrupt = tokio::spawn(async move {
+        if tokio::signal::ctrl_c().await.is_ok() {
+            token.cancel();
+        }
+    });
+
+    let mut pending = vec![];
+    let mut saved = 0;
+    let result = loop {
+        match fill.try_next() {
+            Ok(Some((context, pattern))) => {
+                pending.push(matcher::new_key(context.keypair(), &pattern));
+                if pending.len() >= FILL_BATCH_SIZE {
+                    saved += database.create_keys(std::mem::take(&mut pending)).await?.len();
+                }
+            }
+            Ok(None) if fill.is_done() => break Ok(()),
+            Ok(None) => {
+                if !pending.is_empty() {
+                    saved += database.create_keys(std::mem::take(&mut pending)).await?.len();
+                }
+                eprint!("\r\x1b[2K{}, {} saved", fill.stats(), saved);
+                std::io::stderr().flush()?;
+                tokio::time::sleep(PROGRESS_INTERVAL).await;
+            }
+            Err(e) => break Err(e),
+        }
+    };
+    // keep what was found even if the search was interrupted
+    saved += database.create_keys(pending).await?.len();
+    eprintln!("\r\x1b[2K{}, {} saved", fill.stats(), saved);
+    interrupt.abort();
+
+    for (pattern, missing) in fill.remaining() {
+        if missing > 0 {
+            println!("pattern {} : {} missing", pattern, missing);
+        }
+    }
+    Ok(result?)
+}
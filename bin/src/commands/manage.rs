@@ -5,6 +5,7 @@ use clap::{Parser, Subcommand};
 
 use crate::{
     handlers::auth::{key_gen, key_sign, login, logout},
+    keys::{registry, SignScheme},
     storage::Chain,
 };
 
@@ -12,7 +13,7 @@ use crate::{
 #[command(version, about, long_about = None)]
 pub struct Command {
     /// The chain to use
-    #[clap(short, long, value_enum, default_value_t = Chain::Solana)]
+    #[clap(short, long, value_parser = registry::chain_parser(), default_value = registry::default_name())]
     chain: Chain,
 
     /// The remote server host
@@ -49,9 +50,13 @@ pub enum Subcommands {
         #[arg(short, long)]
         pubkey: String,
 
-        /// The message to sign
+        /// The message to sign, the EIP-712 JSON payload for `typed-data`
         #[arg(short, long)]
         message: String,
+
+        /// How the message is hashed before signing
+        #[arg(long, value_enum, default_value_t = SignScheme::Raw)]
+        scheme: SignScheme,
     },
 }
 
@@ -80,11 +85,16 @@ impl Command {
                 let key = key_gen(&client, &base, chain.as_str()).await?;
                 println!("keygen: {:?}", key.to_string());
             }
-            Subcommands::Sign { pubkey, message, .. } => {
-                let message = message;
-                let data =
-                    key_sign(&client, &base, chain.as_str(), pubkey.as_str(), message.as_str())
-                        .await?;
+            Subcommands::Sign { pubkey, message, scheme } => {
+                let data = key_sign(
+                    &client,
+                    &base,
+                    chain.as_str(),
+                    pubkey.as_str(),
+                    message.as_str(),
+                    scheme.as_ref(),
+                )
+                .await?;
                 println!("signature: {:?}", data.to_string());
             }
         }
@@ -10,5 +10,8 @@ pub mod key;
 #[cfg(feature = "api")]
 pub mod manage;
 
+#[cfg(feature = "api")]
+pub mod seed;
+
 #[cfg(feature = "interact")]
 pub mod interact;
@@ -0,0 +1,68 @@
+//! Shares of the database seed, for sealed API servers
+
+use std::path::PathBuf;
+
+use clap::{Parser, Subcommand};
+use eyre::eyre;
+
+use crate::{
+    keys::{shamir, SecretString},
+    storage::Database,
+};
+
+#[derive(Debug, Parser)]
+pub struct Command {
+    #[clap(subcommand)]
+    command: Subcommands,
+}
+
+#[derive(Subcommand, Debug)]
+/// `anita seed` subcommands
+pub enum Subcommands {
+    /// Split the seed into Shamir shares for `/sys/unseal`, printed one per line
+    Split {
+        /// The database seed.
+        #[arg(long, value_name = "seed", env("SEED"), hide_env_values = true)]
+        seed: String,
+
+        /// The number of shares that unseal the API
+        #[arg(short, long, default_value_t = 3)]
+        threshold: u8,
+
+        /// The number of shares
+        #[arg(long, default_value_t = 5)]
+        shares: u8,
+    },
+    /// Rebuild the seed from its shares, for the commands that take `--seed`
+    Combine {
+        /// The shares, one per line, stdin if omitted
+        #[arg(short, long)]
+        file: Option<PathBuf>,
+    },
+}
+
+impl Command {
+    /// Execute `seed` command
+    pub async fn execute(self) -> eyre::Result<()> {
+        match self.command {
+            Subcommands::Split { seed, threshold, shares } => {
+                let seed = Database::to_seed(SecretString::new(seed).as_str())
+                    .map_err(|e| eyre!("invalid seed: {}", e))?;
+                for share in shamir::split_seed(&seed, threshold, shares)? {
+                    println!("{}", share.as_str());
+                }
+            }
+            Subcommands::Combine { file } => {
+                let contents = SecretString::new(match file {
+                    Some(file) => std::fs::read_to_string(file)?,
+                    None => std::io::read_to_string(std::io::stdin())?,
+                });
+                let shares: Vec<_> =
+                    contents.as_str().lines().map(str::trim).filter(|l| !l.is_empty()).collect();
+                let seed = shamir::combine_seed(&shares)?;
+                println!("{}", SecretString::new(hex::encode(seed.as_slice())).as_str());
+            }
+        }
+        Ok(())
+    }
+}
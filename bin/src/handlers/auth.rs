@@ -69,11 +69,12 @@ pub async fn key_sign(
     chain: &str,
     pubkey: &str,
     message: &str,
+    scheme: &str,
 ) -> Result<serde_json::Value> {
     let url = base.join("keys/sign")?;
     let resp = client
         .post(url)
-        .json(&json!({ "chain": chain, "pubkey": pubkey, "message": message }))
+        .json(&json!({ "chain": chain, "pubkey": pubkey, "message": message, "scheme": scheme }))
         .send()
         .await
         .map_err(|_e| anyhow!("failed to build clint, please check you host"))?;
@@ -123,9 +124,9 @@ mod tests {
 
         let base = Url::parse("http://127.0.0.1:8080").expect("Failed to parse url");
 
-        let _ = login(&client, &base, email, password).await?;
+        login(&client, &base, email, password).await?;
 
-        let _ = logout(&client, &base).await?;
+        logout(&client, &base).await?;
         Ok(())
     }
 
@@ -180,6 +181,7 @@ mod tests {
             chain.to_string().as_str(),
             key["pubkey"].as_str().unwrap(),
             message.as_str(),
+            "raw",
         )
         .await?;
         println!("signed result : {:?}", result.to_string());
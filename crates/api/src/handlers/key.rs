@@ -1,7 +1,14 @@
-use std::str::FromStr;
+use std::time::Duration;
 
 use actix_identity::Identity;
 use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder};
+use base64::{engine::general_purpose::STANDARD as BASE64, Engine};
+#[cfg(feature = "ethereum")]
+use r_keys::{ethereum_tx::EthTransaction, EthereumKeyPair, KeypairStrategy};
+use r_keys::{
+    keygen::Search, matcher, registry, CaseMode, KeyWithSecretExt, Matcher, NewKeyExt, SignScheme,
+    SignedMessage,
+};
 use serde::{Deserialize, Serialize};
 
 use crate::{
@@ -36,9 +43,45 @@ pub async fn get_suffix_key(
     Ok(HttpResponse::Ok().json(key))
 }
 
+/// The threads a vanity search may use per request.
+const KEYGEN_THREADS: u8 = 2;
+
+/// How long a vanity search may run per request.
+const KEYGEN_TIMEOUT: Duration = Duration::from_secs(60);
+
 #[derive(Debug, Deserialize)]
 pub struct KeyGenRequest {
     chain: Chain,
+    /// Vanity patterns: `abc*`, `*xyz`, `ab*yz` or `/regex/`, a random key if empty.
+    #[serde(default)]
+    patterns: Vec<String>,
+    #[serde(default)]
+    case: CaseMode,
+}
+
+#[derive(Debug, Serialize)]
+pub struct ChainResponse {
+    chain: &'static str,
+    curve: String,
+    encoding: String,
+    schemes: Vec<String>,
+}
+
+/// The supported chains, with the curve, address encoding and signing schemes of each.
+#[tracing::instrument]
+#[get("/chains")]
+pub async fn get_chains() -> actix_web::Result<impl Responder, SrvError> {
+    let chains: Vec<_> = registry::chains()
+        .iter()
+        .map(|spec| ChainResponse {
+            chain: spec.name,
+            curve: spec.curve.to_string(),
+            encoding: spec.encoding.to_string(),
+            schemes: spec.schemes.iter().map(|scheme| scheme.to_string()).collect(),
+        })
+        .collect();
+
+    Ok(HttpResponse::Ok().json(chains))
 }
 
 #[tracing::instrument(skip(db, identity))]
@@ -64,11 +107,24 @@ pub async fn key_gen(
     identity: Identity,
 ) -> actix_web::Result<impl Responder, SrvError> {
     let _identity = identity;
+    let body = body.into_inner();
     let chain = body.chain;
+    // fail before the search, not after it
+    db.check_unsealed()?;
 
-    let context = KeypairContext::from_chain(chain);
-    let keypair = context.keypair();
-    let key = NewKey::from_keypair(keypair, None);
+    let key = if body.patterns.is_empty() {
+        let context = KeypairContext::from_chain(chain)?;
+        NewKey::from_keypair(context.keypair(), None)
+    } else {
+        // validate before moving to the blocking pool
+        let matcher = Matcher::parse(chain, &body.patterns, body.case)?;
+        web::block(move || {
+            Search::start(KEYGEN_THREADS, matcher, Some(KEYGEN_TIMEOUT))?
+                .wait()
+                .map(|(context, pattern)| matcher::new_key(context.keypair(), &pattern))
+        })
+        .await??
+    };
     let saved = db.create_key(key).await?;
 
     Ok(HttpResponse::Ok().json(saved))
@@ -78,7 +134,10 @@ pub async fn key_gen(
 pub struct KeySignRequest {
     chain: Chain,
     pubkey: String,
+    /// The text to sign, or the EIP-712 JSON payload with the `typed-data` scheme.
     message: String,
+    #[serde(default)]
+    scheme: SignScheme,
 }
 
 #[derive(Debug, Deserialize, Serialize)]
@@ -102,12 +161,8 @@ pub async fn key_sign(
         .get_secret_by_pubkey(body.chain, body.pubkey.as_str())
         .await?
         .ok_or_else(|| SrvErrorKind::Http(StatusCode::BAD_REQUEST, "Key not found".to_string()))?;
-    let chain = Chain::from_str(&key.key.chain)
-        .map_err(|e| SrvErrorKind::Http(StatusCode::BAD_REQUEST, e.to_string()))?;
-
-    let keypair = KeypairContext::create_keypair(chain);
     let message = body.message.as_bytes();
-    let signature = key.sign(keypair, message)?;
+    let signature = key.keypair()?.sign_with(body.scheme, message)?;
 
     Ok(HttpResponse::Ok().json(KeySignResponse {
         signature,
@@ -115,3 +170,143 @@ pub async fn key_sign(
         pubkey: key.key.pubkey,
     }))
 }
+
+#[derive(Debug, Deserialize)]
+pub struct SignatureItem {
+    /// The pubkey or address of the signer.
+    pubkey: String,
+    message: String,
+    signature: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(untagged)]
+pub enum Signatures {
+    Many { signatures: Vec<SignatureItem> },
+    One(SignatureItem),
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeyVerifyRequest {
+    chain: Chain,
+    #[serde(default)]
+    scheme: SignScheme,
+    #[serde(flatten)]
+    signatures: Signatures,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeyVerifyResponse {
+    /// Every signature matches.
+    valid: bool,
+    results: Vec<bool>,
+}
+
+/// Verify one signature, or a batch of `signatures`, without touching any secret.
+#[tracing::instrument]
+#[post("/verify")]
+pub async fn key_verify(
+    body: web::Json<KeyVerifyRequest>,
+) -> actix_web::Result<impl Responder, SrvError> {
+    let body = body.into_inner();
+    let items = match body.signatures {
+        Signatures::Many { signatures } => signatures,
+        Signatures::One(item) => vec![item],
+    };
+    let keypair = KeypairContext::create_keypair(body.chain)?;
+
+    let results = web::block(move || {
+        let signed: Vec<_> = items
+            .iter()
+            .map(|item| SignedMessage {
+                public: item.pubkey.as_str(),
+                message: item.message.as_bytes(),
+                signature: item.signature.as_str(),
+            })
+            .collect();
+        keypair.verify_batch(body.scheme, &signed)
+    })
+    .await??;
+
+    Ok(HttpResponse::Ok().json(KeyVerifyResponse { valid: results.iter().all(|ok| *ok), results }))
+}
+
+#[cfg(feature = "ethereum")]
+#[derive(Debug, Deserialize)]
+pub struct KeySignEthTransactionRequest {
+    /// The pubkey of the sender.
+    pubkey: String,
+    transaction: EthTransaction,
+}
+
+/// Sign an Ethereum transaction built from the fields in the request.
+#[cfg(feature = "ethereum")]
+#[tracing::instrument(skip(db, identity))]
+#[post("/sign-eth-transaction")]
+pub async fn key_sign_eth_transaction(
+    identity: Identity,
+    db: web::Data<Database>,
+    body: web::Json<KeySignEthTransactionRequest>,
+) -> actix_web::Result<impl Responder, SrvError> {
+    let _identity = identity;
+    let body = body.into_inner();
+
+    let key = db
+        .get_secret_by_pubkey(Chain::Ethereum, body.pubkey.as_str())
+        .await?
+        .ok_or_else(|| SrvErrorKind::Http(StatusCode::BAD_REQUEST, "Key not found".to_string()))?;
+    let mut keypair = EthereumKeyPair::new();
+    keypair.recover_from_bytes(key.secret().as_slice())?;
+    key.check_keypair(&keypair)?;
+
+    let signed = body.transaction.sign(&keypair)?;
+    Ok(HttpResponse::Ok().json(signed))
+}
+
+#[derive(Debug, Deserialize)]
+pub struct KeySignTransactionRequest {
+    chain: Chain,
+    /// The pooled keys co-signing the transaction, in any order.
+    pubkeys: Vec<String>,
+    /// The base64 serialized transaction.
+    transaction: String,
+}
+
+#[derive(Debug, Deserialize, Serialize)]
+pub struct KeySignTransactionResponse {
+    transaction: String,
+    pubkeys: Vec<String>,
+}
+
+#[tracing::instrument(skip(db, identity))]
+#[post("/sign-transaction")]
+pub async fn key_sign_transaction(
+    identity: Identity,
+    db: web::Data<Database>,
+    body: web::Json<KeySignTransactionRequest>,
+) -> actix_web::Result<impl Responder, SrvError> {
+    let _identity = identity;
+    let body = body.into_inner();
+    if body.pubkeys.is_empty() {
+        return Err(SrvErrorKind::Http(
+            StatusCode::BAD_REQUEST,
+            "No pubkeys to sign with".to_string(),
+        )
+        .into());
+    }
+
+    let mut transaction = BASE64
+        .decode(body.transaction.as_bytes())
+        .map_err(|e| SrvErrorKind::Http(StatusCode::BAD_REQUEST, e.to_string()))?;
+    for pubkey in body.pubkeys.iter() {
+        let key = db.get_secret_by_pubkey(body.chain, pubkey.as_str()).await?.ok_or_else(|| {
+            SrvErrorKind::Http(StatusCode::BAD_REQUEST, format!("Key not found: {pubkey}"))
+        })?;
+        transaction = key.keypair()?.sign_transaction(transaction.as_slice())?;
+    }
+
+    Ok(HttpResponse::Ok().json(KeySignTransactionResponse {
+        transaction: BASE64.encode(transaction),
+        pubkeys: body.pubkeys,
+    }))
+}
@@ -0,0 +1,168 @@
+use std::sync::{Mutex, PoisonError};
+
+use actix_web::{get, http::StatusCode, post, web, HttpResponse, Responder};
+use r_keys::shamir::ShareSet;
+use serde::{Deserialize, Serialize};
+
+use crate::{info, tracing, Database, SrvError, SrvErrorKind};
+
+/// The seed shares received so far, shared by the workers.
+pub type Unsealing = Mutex<ShareSet>;
+
+#[derive(Debug, Deserialize)]
+pub struct UnsealRequest {
+    /// A share of the seed, from `anita seed split`.
+    #[serde(default)]
+    share: Option<String>,
+    /// Drop the shares received so far, before adding `share`.
+    #[serde(default)]
+    reset: bool,
+}
+
+#[derive(Debug, Serialize, PartialEq)]
+pub struct SealStatus {
+    sealed: bool,
+    /// The number of shares received.
+    progress: usize,
+    /// The number of shares needed, known after the first one.
+    threshold: Option<u8>,
+}
+
+impl SealStatus {
+    fn new(db: &Database, shares: &ShareSet) -> Self {
+        SealStatus { sealed: db.is_sealed(), progress: shares.len(), threshold: shares.threshold() }
+    }
+}
+
+/// Whether the database is sealed, and how far unsealing is.
+#[tracing::instrument(skip(db, unsealing))]
+#[get("/seal-status")]
+pub async fn get_seal_status(
+    db: web::Data<Database>,
+    unsealing: web::Data<Unsealing>,
+) -> actix_web::Result<impl Responder, SrvError> {
+    let shares = unsealing.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(HttpResponse::Ok().json(SealStatus::new(&db, &shares)))
+}
+
+/// Add a share of the seed, the database is unsealed once the threshold is reached.
+/// Shares that do not rebuild the seed, or rebuild another seed than the database's, start the
+/// unsealing over, and so does `{"reset": true}`.
+#[tracing::instrument(skip(db, unsealing, body))]
+#[post("/unseal")]
+pub async fn unseal(
+    db: web::Data<Database>,
+    unsealing: web::Data<Unsealing>,
+    body: web::Json<UnsealRequest>,
+) -> actix_web::Result<impl Responder, SrvError> {
+    if !db.is_sealed() {
+        return Err(SrvErrorKind::Http(
+            StatusCode::BAD_REQUEST,
+            "the database is not sealed".to_string(),
+        )
+        .into());
+    }
+    let complete = {
+        let mut shares = unsealing.lock().unwrap_or_else(PoisonError::into_inner);
+        if body.reset {
+            *shares = ShareSet::default();
+            info!("the unsealing is reset");
+        }
+        match &body.share {
+            Some(share) => shares.add_seed(share)?,
+            None if body.reset => {}
+            None => {
+                return Err(SrvErrorKind::Http(
+                    StatusCode::BAD_REQUEST,
+                    "a share or a reset is needed".to_string(),
+                )
+                .into())
+            }
+        }
+        shares.is_complete().then(|| std::mem::take(&mut *shares))
+    };
+
+    if let Some(complete) = complete {
+        db.unseal(complete.seed()?).await?;
+        info!("the database is unsealed");
+    }
+    let shares = unsealing.lock().unwrap_or_else(PoisonError::into_inner);
+    Ok(HttpResponse::Ok().json(SealStatus::new(&db, &shares)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use actix_web::{test, App};
+    use r_keys::{shamir::split_seed, SecretBytes};
+
+    /// A sealed database, the pool connects lazily and the shares below are refused before the
+    /// database is used.
+    async fn sealed() -> Database {
+        Database::connect("postgres://localhost:1/anita", None).await.sealed()
+    }
+
+    fn post(body: serde_json::Value) -> test::TestRequest {
+        test::TestRequest::post().uri("/unseal").set_json(body)
+    }
+
+    #[actix_web::test]
+    async fn test_unseal_wrong_length_seed() {
+        let app = App::new()
+            .app_data(web::Data::new(sealed().await))
+            .app_data(web::Data::new(Unsealing::default()))
+            .service(unseal);
+        let app = test::init_service(app).await;
+
+        let shares = split_seed(&SecretBytes::new(vec![7; 16]), 2, 2).unwrap();
+        for share in shares {
+            let status = test::call_service(
+                &app,
+                post(serde_json::json!({ "share": share.as_str() })).to_request(),
+            )
+            .await
+            .status();
+            assert_eq!(status, StatusCode::BAD_REQUEST);
+        }
+    }
+
+    #[actix_web::test]
+    async fn test_unseal_reset() {
+        let unsealing = web::Data::new(Unsealing::default());
+        let app = App::new()
+            .app_data(web::Data::new(sealed().await))
+            .app_data(unsealing.clone())
+            .service(unseal);
+        let app = test::init_service(app).await;
+
+        let stray = split_seed(&SecretBytes::new(vec![7; 32]), 3, 3).unwrap();
+        let shares = split_seed(&SecretBytes::new(vec![8; 32]), 2, 2).unwrap();
+        let stray = serde_json::json!({ "share": stray[0].as_str() });
+        assert_eq!(
+            test::call_service(&app, post(stray).to_request()).await.status(),
+            StatusCode::OK
+        );
+        let share = serde_json::json!({ "share": shares[0].as_str() });
+        assert_eq!(
+            test::call_service(&app, post(share.clone()).to_request()).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+
+        assert_eq!(
+            test::call_service(&app, post(serde_json::json!({})).to_request()).await.status(),
+            StatusCode::BAD_REQUEST
+        );
+        assert_eq!(
+            test::call_service(&app, post(serde_json::json!({ "reset": true })).to_request())
+                .await
+                .status(),
+            StatusCode::OK
+        );
+        assert!(unsealing.lock().unwrap().is_empty());
+        assert_eq!(
+            test::call_service(&app, post(share).to_request()).await.status(),
+            StatusCode::OK
+        );
+        assert_eq!(unsealing.lock().unwrap().threshold(), Some(2));
+    }
+}
@@ -38,14 +38,29 @@ pub async fn init_api(port: u16, database: Database) -> std::io::Result<()> {
     let addr = format!("0.0.0.0:{}", port);
 
     let session_key = get_session_key_from_env();
+    let unsealing = web::Data::new(handlers::sys::Unsealing::default());
+    if database.is_sealed() {
+        warn!("the database is sealed, unseal it through /sys/unseal");
+    }
     let srv: actix_web::dev::Server = HttpServer::new(move || {
         let session_mw =
             SessionMiddleware::builder(CookieSessionStore::default(), session_key.clone())
                 // disable secure cookie for local testing
                 .cookie_secure(false)
                 .build();
+        let keys = web::scope("/keys")
+            .service(handlers::key::get_suffix_key)
+            .service(handlers::key::get_chains)
+            .service(handlers::key::get_key)
+            .service(handlers::key::key_gen)
+            .service(handlers::key::key_sign)
+            .service(handlers::key::key_verify)
+            .service(handlers::key::key_sign_transaction);
+        #[cfg(feature = "ethereum")]
+        let keys = keys.service(handlers::key::key_sign_eth_transaction);
         App::new()
             .app_data(web::Data::new(database.clone()))
+            .app_data(unsealing.clone())
             .wrap(RequestTracing::new())
             .wrap(RequestMetrics::default())
             .wrap(TracingLogger::default())
@@ -60,12 +75,11 @@ pub async fn init_api(port: u16, database: Database) -> std::io::Result<()> {
                 web::scope("/auth").service(handlers::auth::login).service(handlers::auth::logout),
             )
             .service(
-                web::scope("/keys")
-                    .service(handlers::key::get_suffix_key)
-                    .service(handlers::key::get_key)
-                    .service(handlers::key::key_gen)
-                    .service(handlers::key::key_sign),
+                web::scope("/sys")
+                    .service(handlers::sys::get_seal_status)
+                    .service(handlers::sys::unseal),
             )
+            .service(keys)
     })
     .disable_signals()
     .bind(addr)?
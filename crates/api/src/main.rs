@@ -15,15 +15,31 @@ async fn main() {
         .unwrap_or_else(|_| "8080".to_string())
         .parse::<u16>()
         .expect("PORT must be a number");
-    let seed = std::env::var("SEED").ok();
-    let seed =
-        seed.map(|s| Database::to_seed(s.as_str()).expect("Seed must be a valid hex string"));
+    // the seed, or a KEK from `file:`, `passphrase:` or `transit:`
+    let kek = std::env::var("KEK").or_else(|_| std::env::var("SEED")).ok();
+    let kek =
+        kek.map(|kek| Database::to_kek(kek.as_str()).expect("KEK must be a valid seed or KEK"));
 
     let database_url = std::env::var("DATABASE_URL").expect("DATABASE_URL must be set");
     debug!(target: "init", "Initializing database...");
-    let database = Database::new_with_url(&database_url, seed).await;
+    // the seeds or KEKs before a rotation, comma separated
+    let previous = std::env::var("PREVIOUS_KEKS")
+        .map(|keks| {
+            keks.split(',')
+                .map(|kek| Database::to_kek(kek.trim()).expect("KEK must be a valid seed or KEK"))
+                .collect()
+        })
+        .unwrap_or_default();
+    // start without the seed, it is given through `/sys/unseal`
+    let database = if std::env::var("SEALED").is_ok_and(|sealed| sealed == "true") {
+        assert!(kek.is_none(), "SEED and KEK must not be set with SEALED");
+        Database::new_sealed(&database_url, previous).await
+    } else {
+        Database::new_with_url(&database_url, kek, previous).await
+    };
+    let database = database.expect("the seed does not match the database");
     debug!(target: "init", "Database connected.");
 
-    let _api = r_api::init_api(port, database).await.expect("could not start api server");
+    r_api::init_api(port, database).await.expect("could not start api server");
     drop(guard);
 }
@@ -1,6 +1,8 @@
 use actix_web::http::StatusCode;
 use serde_json::json;
 
+use r_storage::DatabaseError;
+
 use crate::{SrvError, SrvErrorKind};
 
 impl actix_web::error::ResponseError for SrvError {
@@ -10,6 +12,30 @@ impl actix_web::error::ResponseError for SrvError {
             SrvErrorKind::InvalidEmailOrPassword => StatusCode::BAD_REQUEST,
             SrvErrorKind::ValidationError(_) => StatusCode::BAD_REQUEST,
             SrvErrorKind::NotFound(_) => StatusCode::NOT_FOUND,
+            SrvErrorKind::DatabaseError(DatabaseError::InvalidPattern(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            SrvErrorKind::DatabaseError(DatabaseError::SearchTimeout(_)) => {
+                StatusCode::REQUEST_TIMEOUT
+            }
+            SrvErrorKind::DatabaseError(DatabaseError::UnsupportedOperation(..)) => {
+                StatusCode::BAD_REQUEST
+            }
+            SrvErrorKind::DatabaseError(DatabaseError::TransactionError(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            SrvErrorKind::DatabaseError(DatabaseError::InvalidMessage(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            SrvErrorKind::DatabaseError(DatabaseError::InvalidSignature(_)) => {
+                StatusCode::BAD_REQUEST
+            }
+            SrvErrorKind::DatabaseError(DatabaseError::InvalidShare(_)) => StatusCode::BAD_REQUEST,
+            SrvErrorKind::DatabaseError(DatabaseError::Sealed) => StatusCode::SERVICE_UNAVAILABLE,
+            SrvErrorKind::DatabaseError(DatabaseError::SeedMismatch(_)) => StatusCode::BAD_REQUEST,
+            SrvErrorKind::DatabaseError(DatabaseError::KekError(_)) => {
+                StatusCode::SERVICE_UNAVAILABLE
+            }
             SrvErrorKind::Any(_) => StatusCode::INTERNAL_SERVER_ERROR,
             SrvErrorKind::IoError(_) => StatusCode::INTERNAL_SERVER_ERROR,
             _ => StatusCode::INTERNAL_SERVER_ERROR,
@@ -0,0 +1,51 @@
+use criterion::{black_box, criterion_group, criterion_main, BatchSize, Criterion, Throughput};
+use solana_sdk::signature::{Keypair, Signer};
+
+use r_keys::{
+    grind::{AddressBuf, Grinder, SecretBatch, BATCH_SIZE},
+    CaseMode, Chain, KeypairContext, Matcher,
+};
+
+/// Compare one batch of candidates generated as keygen used to, a solana-sdk `Keypair` with its
+/// base58 pubkey or an Ethereum keypair strategy, against the raw bytes grinder.
+fn bench_candidates(c: &mut Criterion) {
+    for (chain, pattern) in [(Chain::Solana, "zzzz"), (Chain::Ethereum, "ffff")] {
+        let matcher = Matcher::parse(chain, &[pattern], CaseMode::Sensitive).unwrap();
+        let grinder = Grinder::from_chain(chain).unwrap();
+
+        let mut group = c.benchmark_group(format!("keygen/{chain}"));
+        group.throughput(Throughput::Elements(BATCH_SIZE as u64));
+
+        group.bench_function("baseline", |b| {
+            b.iter(|| {
+                for _ in 0..BATCH_SIZE {
+                    let address = match chain {
+                        Chain::Solana => Keypair::new().pubkey().to_string(),
+                        _ => KeypairContext::from_chain(chain).unwrap().keypair().address(),
+                    };
+                    black_box(matcher.find(address.as_str()));
+                }
+            })
+        });
+
+        group.bench_function("grinder", |b| {
+            let mut address = AddressBuf::default();
+            b.iter_batched_ref(
+                SecretBatch::default,
+                |batch| {
+                    batch.refill();
+                    for secret in batch.iter() {
+                        if grinder.encode(secret, &mut address) {
+                            black_box(matcher.find(address.as_str()));
+                        }
+                    }
+                },
+                BatchSize::SmallInput,
+            )
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, bench_candidates);
+criterion_main!(benches);
@@ -0,0 +1,554 @@
+use std::fmt;
+
+use base64::{engine::general_purpose::STANDARD, Engine};
+use bech32::{segwit, Fe32, Hrp};
+use libsecp256k1::{Message, PublicKey, RecoveryId, SecretKey, Signature};
+use ripemd::Ripemd160;
+use sha2::{Digest, Sha256};
+
+use crate::{
+    hd::Curve,
+    matcher::Alphabet,
+    registry::{AddressEncoding, ChainSpec},
+    secp256k1::random_secret,
+    Chain, DatabaseError, KeypairStrategy, SecretBytes, SecretString, SignScheme,
+};
+
+/// Network parameters of a Bitcoin-family chain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Network {
+    pub chain: Chain,
+    /// Human readable part of segwit addresses, `None` if the chain has no segwit.
+    pub hrp: Option<&'static str>,
+    /// Version byte of P2PKH addresses.
+    pub pubkey_hash: u8,
+    /// Version byte of WIF encoded secrets.
+    pub wif: u8,
+    /// Prefix of the signed message digest.
+    pub message_prefix: &'static str,
+}
+
+impl Network {
+    pub const BITCOIN: Network = Network {
+        chain: Chain::Bitcoin,
+        hrp: Some("bc"),
+        pubkey_hash: 0x00,
+        wif: 0x80,
+        message_prefix: "Bitcoin Signed Message:\n",
+    };
+
+    pub const TESTNET: Network = Network {
+        chain: Chain::BitcoinTestnet,
+        hrp: Some("tb"),
+        pubkey_hash: 0x6f,
+        wif: 0xef,
+        message_prefix: "Bitcoin Signed Message:\n",
+    };
+
+    pub const SIGNET: Network = Network { chain: Chain::BitcoinSignet, ..Network::TESTNET };
+
+    pub const REGTEST: Network =
+        Network { chain: Chain::BitcoinRegtest, hrp: Some("bcrt"), ..Network::TESTNET };
+
+    pub const LITECOIN: Network = Network {
+        chain: Chain::Litecoin,
+        hrp: Some("ltc"),
+        pubkey_hash: 0x30,
+        wif: 0xb0,
+        message_prefix: "Litecoin Signed Message:\n",
+    };
+
+    pub const DOGECOIN: Network = Network {
+        chain: Chain::Dogecoin,
+        hrp: None,
+        pubkey_hash: 0x1e,
+        wif: 0x9e,
+        message_prefix: "Dogecoin Signed Message:\n",
+    };
+
+    /// The registry entry of the network, the head is the start of its generated addresses.
+    const fn spec(
+        self,
+        name: &'static str,
+        head: &'static str,
+        factory: fn() -> Box<dyn KeypairStrategy>,
+    ) -> ChainSpec {
+        let (encoding, alphabet) = match self.hrp {
+            // witness version 0 is `q`
+            Some(_) => (AddressEncoding::Bech32, Alphabet::Bech32),
+            None => (AddressEncoding::Base58Check, Alphabet::Base58),
+        };
+        ChainSpec {
+            chain: self.chain,
+            name,
+            curve: Curve::Secp256k1,
+            encoding,
+            alphabet,
+            head,
+            schemes: &[SignScheme::Raw],
+            factory,
+        }
+    }
+
+    /// Get the network parameters of a chain.
+    pub fn from_chain(chain: Chain) -> Option<Network> {
+        match chain {
+            Chain::Bitcoin => Some(Network::BITCOIN),
+            Chain::BitcoinTestnet => Some(Network::TESTNET),
+            Chain::BitcoinSignet => Some(Network::SIGNET),
+            Chain::BitcoinRegtest => Some(Network::REGTEST),
+            Chain::Litecoin => Some(Network::LITECOIN),
+            Chain::Dogecoin => Some(Network::DOGECOIN),
+            _ => None,
+        }
+    }
+
+    /// Encode the address of the given type for a compressed public key.
+    pub fn address(
+        &self,
+        pubkey: &[u8; 33],
+        address_type: AddressType,
+    ) -> Result<String, DatabaseError> {
+        match address_type {
+            AddressType::P2pkh => Ok(self.p2pkh_address(pubkey)),
+            AddressType::P2wpkh => self.segwit_address(segwit::VERSION_0, &hash160(pubkey)),
+            AddressType::P2tr => {
+                let internal: [u8; 32] = pubkey[1..].try_into().expect("x-only key is 32 bytes");
+                self.segwit_address(segwit::VERSION_1, &taproot_output_key(&internal)?)
+            }
+        }
+    }
+
+    /// Encode the P2PKH address of a compressed or uncompressed public key.
+    pub fn p2pkh_address(&self, pubkey: &[u8]) -> String {
+        let mut payload = vec![self.pubkey_hash];
+        payload.extend_from_slice(&hash160(pubkey));
+        bs58::encode(payload).with_check().into_string()
+    }
+
+    fn segwit_address(&self, version: Fe32, program: &[u8]) -> Result<String, DatabaseError> {
+        let hrp = self.hrp.ok_or_else(|| {
+            DatabaseError::SecretError(format!("{} has no segwit addresses", self.chain))
+        })?;
+        let hrp = Hrp::parse(hrp).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        segwit::encode(hrp, version, program).map_err(|e| DatabaseError::SecretError(e.to_string()))
+    }
+
+    /// The address type stored for new keys, native segwit when the network supports it.
+    pub fn default_address_type(&self) -> AddressType {
+        match self.hrp {
+            Some(_) => AddressType::P2wpkh,
+            None => AddressType::P2pkh,
+        }
+    }
+}
+
+/// The address form derived from the public key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddressType {
+    /// Legacy base58check pay-to-pubkey-hash.
+    P2pkh,
+    /// Native segwit v0 pay-to-witness-pubkey-hash, bech32 encoded.
+    P2wpkh,
+    /// Taproot key path spend (BIP-86), bech32m encoded.
+    P2tr,
+}
+
+pub struct BitcoinKeyPair {
+    secret: SecretKey,
+    network: Network,
+    address_type: AddressType,
+}
+
+impl BitcoinKeyPair {
+    pub fn new(network: Network) -> Self {
+        let address_type = network.default_address_type();
+        BitcoinKeyPair { secret: random_secret(), network, address_type }
+    }
+
+    /// Create a keypair deriving the given address type.
+    /// Returns an error if the network cannot encode it, such as segwit on Dogecoin.
+    pub fn with_address_type(
+        network: Network,
+        address_type: AddressType,
+    ) -> Result<Self, DatabaseError> {
+        if address_type != AddressType::P2pkh && network.hrp.is_none() {
+            return Err(DatabaseError::SecretError(format!(
+                "{:?} addresses are not supported on {}",
+                address_type, network.chain
+            )));
+        }
+        Ok(BitcoinKeyPair { secret: random_secret(), network, address_type })
+    }
+
+    /// Create a keypair from a WIF secret.
+    pub fn from_wif(network: Network, wif: &str) -> Result<Self, DatabaseError> {
+        let mut keypair = Self::new(network);
+        keypair.recover_secret(wif)?;
+        Ok(keypair)
+    }
+
+    pub fn network(&self) -> Network {
+        self.network
+    }
+
+    pub fn address_type(&self) -> AddressType {
+        self.address_type
+    }
+
+    fn public(&self) -> PublicKey {
+        PublicKey::from_secret_key(&self.secret)
+    }
+
+    /// Encode the secret as WIF for a compressed public key.
+    pub fn to_wif(&self) -> SecretString {
+        let mut payload = SecretBytes::new(Vec::with_capacity(34));
+        payload.expose_mut().push(self.network.wif);
+        payload.expose_mut().extend_from_slice(&self.secret.serialize());
+        payload.expose_mut().push(0x01);
+        SecretString::new(bs58::encode(payload.as_slice()).with_check().into_string())
+    }
+
+    /// Derive the address of the given type.
+    pub fn address_of(&self, address_type: AddressType) -> Result<String, DatabaseError> {
+        self.network.address(&self.public().serialize_compressed(), address_type)
+    }
+}
+
+impl fmt::Debug for BitcoinKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "BitcoinKeyPair({})", self.address())
+    }
+}
+
+/// RIPEMD160(SHA256(data)).
+pub fn hash160(data: &[u8]) -> [u8; 20] {
+    Ripemd160::digest(Sha256::digest(data)).into()
+}
+
+/// SHA256(SHA256(data)).
+pub fn sha256d(data: &[u8]) -> [u8; 32] {
+    Sha256::digest(Sha256::digest(data)).into()
+}
+
+/// BIP-340 tagged hash.
+pub fn tagged_hash(tag: &str, data: &[u8]) -> [u8; 32] {
+    let tag = Sha256::digest(tag.as_bytes());
+    Sha256::new().chain_update(tag).chain_update(tag).chain_update(data).finalize().into()
+}
+
+/// Tweak an x-only internal key into the BIP-86 taproot output key, without a script tree.
+pub fn taproot_output_key(internal: &[u8; 32]) -> Result<[u8; 32], DatabaseError> {
+    let mut even = [0u8; 33];
+    even[0] = 0x02;
+    even[1..].copy_from_slice(internal);
+
+    let mut output = PublicKey::parse_compressed(&even)
+        .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+    let tweak = SecretKey::parse(&tagged_hash("TapTweak", internal))
+        .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+    output.tweak_add_assign(&tweak).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+
+    let mut key = [0u8; 32];
+    key.copy_from_slice(&output.serialize_compressed()[1..]);
+    Ok(key)
+}
+
+/// Append the bitcoin variable length integer encoding of `n`.
+fn write_varint(buf: &mut Vec<u8>, n: usize) {
+    match n {
+        0..=0xfc => buf.push(n as u8),
+        0xfd..=0xffff => {
+            buf.push(0xfd);
+            buf.extend_from_slice(&(n as u16).to_le_bytes());
+        }
+        _ => {
+            buf.push(0xfe);
+            buf.extend_from_slice(&(n as u32).to_le_bytes());
+        }
+    }
+}
+
+/// The digest signed by `signmessage`: SHA256d(varint(prefix) || prefix || varint(message) || message).
+pub fn message_digest(prefix: &str, message: &[u8]) -> [u8; 32] {
+    let mut buf = Vec::with_capacity(prefix.len() + message.len() + 10);
+    write_varint(&mut buf, prefix.len());
+    buf.extend_from_slice(prefix.as_bytes());
+    write_varint(&mut buf, message.len());
+    buf.extend_from_slice(message);
+    sha256d(&buf)
+}
+
+/// The Bitcoin-family chains, keygen produces P2WPKH addresses where segwit exists.
+pub const SPECS: &[ChainSpec] = &[
+    Network::BITCOIN.spec("bitcoin", "bc1q", || Box::new(BitcoinKeyPair::new(Network::BITCOIN))),
+    Network::TESTNET
+        .spec("bitcoin-testnet", "tb1q", || Box::new(BitcoinKeyPair::new(Network::TESTNET))),
+    Network::SIGNET
+        .spec("bitcoin-signet", "tb1q", || Box::new(BitcoinKeyPair::new(Network::SIGNET))),
+    Network::REGTEST
+        .spec("bitcoin-regtest", "bcrt1q", || Box::new(BitcoinKeyPair::new(Network::REGTEST))),
+    Network::LITECOIN
+        .spec("litecoin", "ltc1q", || Box::new(BitcoinKeyPair::new(Network::LITECOIN))),
+    Network::DOGECOIN.spec("dogecoin", "", || Box::new(BitcoinKeyPair::new(Network::DOGECOIN))),
+];
+
+impl KeypairStrategy for BitcoinKeyPair {
+    fn chain(&self) -> Chain {
+        self.network.chain
+    }
+
+    fn generate(&mut self) {
+        self.secret = random_secret();
+    }
+
+    /// recover the keypair from a compressed WIF secret of the same network
+    fn recover_secret(&mut self, secret: &str) -> Result<(), DatabaseError> {
+        let payload = bs58::decode(secret)
+            .with_check(None)
+            .into_vec()
+            .map(SecretBytes::new)
+            .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        let payload = payload.as_slice();
+        if payload.len() != 34 || payload[33] != 0x01 {
+            return Err(DatabaseError::SecretError(
+                "only compressed WIF secrets are supported".to_string(),
+            ));
+        }
+        if payload[0] != self.network.wif {
+            return Err(DatabaseError::SecretError(format!(
+                "WIF version {:#04x} does not match {}",
+                payload[0], self.network.chain
+            )));
+        }
+        self.recover_from_bytes(&payload[1..33])
+    }
+
+    fn recover_from_bytes(&mut self, bytes: &[u8]) -> Result<(), DatabaseError> {
+        let secret =
+            SecretKey::parse_slice(bytes).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        self.secret = secret;
+        Ok(())
+    }
+
+    fn to_vec(&self) -> SecretBytes {
+        SecretBytes::from_slice(&self.secret.serialize())
+    }
+
+    fn secret(&self) -> SecretString {
+        self.to_wif()
+    }
+
+    /// the compressed public key, hex encoded
+    fn pubkey(&self) -> String {
+        hex::encode(self.public().serialize_compressed())
+    }
+
+    fn address(&self) -> String {
+        self.address_of(self.address_type).expect("address type is supported by the network")
+    }
+
+    /// sign the message like `signmessage`, return the base64 encoded compact signature
+    fn sign(&self, message: &[u8]) -> Result<String, DatabaseError> {
+        let digest = Message::parse(&message_digest(self.network.message_prefix, message));
+        let (signature, recovery_id) = libsecp256k1::sign(&digest, &self.secret);
+
+        // header byte: 27 + recovery id + 4 for a compressed public key
+        let mut bytes = vec![31 + recovery_id.serialize()];
+        bytes.extend_from_slice(&signature.serialize());
+        Ok(STANDARD.encode(bytes))
+    }
+
+    /// verify a `signmessage` signature against a hex pubkey or an address of the network
+    fn verify(&self, public: &str, message: &[u8], signature: &str) -> Result<bool, DatabaseError> {
+        let bytes = STANDARD
+            .decode(signature)
+            .map_err(|e| DatabaseError::InvalidSignature(format!("`{signature}`: {e}")))?;
+        // BIP-137 headers: 27-30 uncompressed, 31-34 compressed, 35-38 P2SH-P2WPKH, 39-42 P2WPKH
+        let header = match bytes.as_slice() {
+            [header @ 27..=42, ..] if bytes.len() == 65 => header - 27,
+            _ => return Err(DatabaseError::InvalidSignature(format!("`{signature}`"))),
+        };
+        let recovery_id = RecoveryId::parse(header % 4)
+            .map_err(|e| DatabaseError::InvalidSignature(e.to_string()))?;
+        let sig = Signature::parse_standard_slice(&bytes[1..])
+            .map_err(|e| DatabaseError::InvalidSignature(e.to_string()))?;
+
+        let digest = Message::parse(&message_digest(self.network.message_prefix, message));
+        let Ok(signer) = libsecp256k1::recover(&digest, &sig, &recovery_id) else {
+            return Ok(false);
+        };
+        if let Some(pubkey) = hex::decode(public).ok().filter(|b| b.len() == 33 || b.len() == 65) {
+            let pubkey = PublicKey::parse_slice(&pubkey, None)
+                .map_err(|e| DatabaseError::InvalidSignature(format!("pubkey `{public}`: {e}")))?;
+            return Ok(pubkey == signer);
+        }
+
+        // an uncompressed key only has a legacy address
+        if header < 4 {
+            return Ok(self.network.p2pkh_address(&signer.serialize()) == public);
+        }
+        let address_types: &[AddressType] = match header {
+            4..=7 => &[AddressType::P2pkh, AddressType::P2wpkh, AddressType::P2tr],
+            // P2SH-P2WPKH addresses are not supported
+            8..=11 => &[],
+            _ => &[AddressType::P2wpkh, AddressType::P2tr],
+        };
+        let compressed = signer.serialize_compressed();
+        let matched = address_types
+            .iter()
+            .copied()
+            .filter_map(|address_type| {
+                self.network.address(&compressed, address_type).ok().map(|a| (address_type, a))
+            })
+            // bech32 is case-insensitive, base58 is not
+            .any(|(address_type, address)| match address_type {
+                AddressType::P2pkh => address == public,
+                _ => address.eq_ignore_ascii_case(public),
+            });
+        Ok(matched)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // the secret key 1, whose public key is the generator point
+    const WIF: &str = "KwDiBf89QgGbjEhKnhXJuH7LrciVrZi3qYjgd9M7rFU73sVHnoWn";
+
+    #[test]
+    fn test_addresses() {
+        let keypair = BitcoinKeyPair::from_wif(Network::BITCOIN, WIF).unwrap();
+        assert_eq!(
+            keypair.pubkey(),
+            "0279be667ef9dcbbac55a06295ce870b07029bfcdb2dce28d959f2815b16f81798"
+        );
+        assert_eq!(keypair.address(), "bc1qw508d6qejxtdg4y5r3zarvary0c5xw7kv8f3t4");
+        assert_eq!(
+            keypair.address_of(AddressType::P2pkh).unwrap(),
+            "1BgGZ9tcN4rm9KBzDn7KprQz87SZ26SAMH"
+        );
+        assert!(keypair.address_of(AddressType::P2tr).unwrap().starts_with("bc1p"));
+
+        let mut testnet = BitcoinKeyPair::new(Network::TESTNET);
+        testnet.recover_from_bytes(keypair.to_vec().as_slice()).unwrap();
+        assert_eq!(testnet.address(), "tb1qw508d6qejxtdg4y5r3zarvary0c5xw7kxpjzsx");
+        assert_eq!(testnet.chain(), Chain::BitcoinTestnet);
+
+        let mut regtest = BitcoinKeyPair::new(Network::REGTEST);
+        regtest.recover_from_bytes(keypair.to_vec().as_slice()).unwrap();
+        assert!(regtest.address().starts_with("bcrt1q"));
+    }
+
+    #[test]
+    fn test_taproot_output_key() {
+        // test vector from https://github.com/bitcoin/bips/blob/master/bip-0086.mediawiki
+        let internal: [u8; 32] =
+            hex::decode("cc8a4bc64d897bddc5fbc2f670f7a8ba0b386779106cf1223c6fc5d7cd6fc115")
+                .unwrap()
+                .try_into()
+                .unwrap();
+        let output = taproot_output_key(&internal).unwrap();
+        assert_eq!(
+            hex::encode(output),
+            "a60869f0dbcf1dc659c9cecbaf8050135ea9e8cdc487053f1dc6880949dc684c"
+        );
+
+        let hrp = Hrp::parse("bc").unwrap();
+        assert_eq!(
+            segwit::encode(hrp, segwit::VERSION_1, &output).unwrap(),
+            "bc1p5cyxnuxmeuwuvkwfem96lqzszd02n6xdcjrs20cac6yqjjwudpxqkedrcr"
+        );
+    }
+
+    #[test]
+    fn test_wif() {
+        let keypair = BitcoinKeyPair::from_wif(Network::BITCOIN, WIF).unwrap();
+        assert_eq!(keypair.secret().as_str(), WIF);
+        assert_eq!(keypair.to_vec().as_slice()[31], 1);
+
+        // a mainnet secret cannot be imported into testnet
+        assert!(BitcoinKeyPair::from_wif(Network::TESTNET, WIF).is_err());
+        // uncompressed WIF secrets are rejected
+        assert!(BitcoinKeyPair::from_wif(
+            Network::BITCOIN,
+            "5HpHagT65TZzG1PH3CSu63k8DbpvD8s5ip4nEB3kEsreAnchuDf"
+        )
+        .is_err());
+
+        for network in [Network::LITECOIN, Network::DOGECOIN, Network::SIGNET] {
+            let keypair = BitcoinKeyPair::new(network);
+            let recovered = BitcoinKeyPair::from_wif(network, keypair.secret().as_str()).unwrap();
+            assert_eq!(recovered.address(), keypair.address());
+        }
+    }
+
+    #[test]
+    fn test_network_versions() {
+        let litecoin = BitcoinKeyPair::new(Network::LITECOIN);
+        assert!(litecoin.address().starts_with("ltc1q"));
+        assert!(litecoin.address_of(AddressType::P2pkh).unwrap().starts_with('L'));
+
+        let dogecoin = BitcoinKeyPair::new(Network::DOGECOIN);
+        assert_eq!(dogecoin.address_type(), AddressType::P2pkh);
+        assert!(dogecoin.address().starts_with('D'));
+        assert!(dogecoin.address_of(AddressType::P2wpkh).is_err());
+        assert!(BitcoinKeyPair::with_address_type(Network::DOGECOIN, AddressType::P2tr).is_err());
+
+        let taproot =
+            BitcoinKeyPair::with_address_type(Network::SIGNET, AddressType::P2tr).unwrap();
+        assert!(taproot.address().starts_with("tb1p"));
+    }
+
+    #[test]
+    fn test_sign() {
+        let keypair = BitcoinKeyPair::new(Network::BITCOIN);
+        let message = b"hello";
+        let signature = STANDARD.decode(keypair.sign(message).unwrap()).unwrap();
+        assert_eq!(signature.len(), 65);
+
+        let digest = Message::parse(&message_digest(Network::BITCOIN.message_prefix, message));
+        let sig = libsecp256k1::Signature::parse_standard_slice(&signature[1..]).unwrap();
+        let recovery_id = libsecp256k1::RecoveryId::parse(signature[0] - 31).unwrap();
+        let recovered = libsecp256k1::recover(&digest, &sig, &recovery_id).unwrap();
+        assert_eq!(hex::encode(recovered.serialize_compressed()), keypair.pubkey());
+    }
+
+    #[test]
+    fn test_verify() {
+        let keypair = BitcoinKeyPair::from_wif(Network::BITCOIN, WIF).unwrap();
+        let signature = keypair.sign(b"hello").unwrap();
+        for public in [
+            keypair.pubkey(),
+            keypair.address(),
+            keypair.address_of(AddressType::P2pkh).unwrap(),
+            keypair.address_of(AddressType::P2tr).unwrap(),
+        ] {
+            assert!(keypair.verify(&public, b"hello", &signature).unwrap(), "{public}");
+            assert!(!keypair.verify(&public, b"world", &signature).unwrap(), "{public}");
+        }
+
+        // the header must match the kind of address
+        let with_header = |offset: i8| {
+            let mut bytes = STANDARD.decode(&signature).unwrap();
+            bytes[0] = bytes[0].wrapping_add_signed(offset);
+            STANDARD.encode(bytes)
+        };
+        let (p2pkh, p2wpkh) = (keypair.address_of(AddressType::P2pkh).unwrap(), keypair.address());
+        let uncompressed = with_header(-4);
+        assert!(!keypair.verify(&p2pkh, b"hello", &uncompressed).unwrap());
+        assert!(!keypair.verify(&p2wpkh, b"hello", &uncompressed).unwrap());
+        let nested = with_header(4);
+        assert!(!keypair.verify(&p2pkh, b"hello", &nested).unwrap());
+        assert!(!keypair.verify(&p2wpkh, b"hello", &nested).unwrap());
+        let segwit = with_header(8);
+        assert!(!keypair.verify(&p2pkh, b"hello", &segwit).unwrap());
+        assert!(keypair.verify(&p2wpkh, b"hello", &segwit).unwrap());
+
+        let other = BitcoinKeyPair::new(Network::BITCOIN);
+        assert!(!other.verify(&other.address(), b"hello", &signature).unwrap());
+        assert!(keypair.verify(&keypair.address(), b"hello", "aGVsbG8=").is_err());
+    }
+}
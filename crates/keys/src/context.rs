@@ -1,4 +1,4 @@
-use crate::{Chain, DatabaseError, KeypairStrategy, SolanaKeyPair};
+use crate::{hd, registry, Chain, DatabaseError, KeypairStrategy};
 
 /// A context for generating and signing keypairs.
 pub struct KeypairContext {
@@ -8,29 +8,52 @@ pub struct KeypairContext {
 
 /// A context for generating and signing keypairs.
 impl KeypairContext {
-    pub fn create_keypair(chain: Chain) -> Box<dyn KeypairStrategy> {
-        match chain {
-            Chain::Solana => Box::new(SolanaKeyPair::new()),
-            _ => Box::new(SolanaKeyPair::new()),
-        }
+    /// Create a random keypair for the chain.
+    /// Returns an error if the chain has no keypair strategy.
+    pub fn create_keypair(chain: Chain) -> Result<Box<dyn KeypairStrategy>, DatabaseError> {
+        Ok(registry::get(chain)?.keypair())
     }
 
     /// Create a new keypair context.
-    pub fn from_chain(chain: Chain) -> Self {
-        let keypair = Self::create_keypair(chain);
-        KeypairContext { keypair, chain }
+    pub fn from_chain(chain: Chain) -> Result<Self, DatabaseError> {
+        let keypair = Self::create_keypair(chain)?;
+        Ok(KeypairContext { keypair, chain })
     }
 
     /// Create a new keypair context with secret.
     pub fn from_secret(chain: Chain, secret: &str) -> Result<Self, DatabaseError> {
-        let mut keypair = Self::create_keypair(chain);
+        let mut keypair = Self::create_keypair(chain)?;
         keypair.recover_secret(secret)?;
         Ok(KeypairContext { keypair, chain })
     }
 
+    /// Create a keypair context from the raw secret bytes of the chain's keypair.
+    pub fn from_bytes(chain: Chain, bytes: &[u8]) -> Result<Self, DatabaseError> {
+        let mut keypair = Self::create_keypair(chain)?;
+        keypair.recover_from_bytes(bytes)?;
+        Ok(KeypairContext { keypair, chain })
+    }
+
+    /// Create a keypair context from a BIP-39 seed and a derivation path.
+    pub fn from_seed(chain: Chain, seed: &[u8], path: &str) -> Result<Self, DatabaseError> {
+        let secret = hd::derive_secret(chain, seed, path)?;
+        Self::from_bytes(chain, secret.as_slice())
+    }
+
+    /// Create a keypair context from a BIP-39 mnemonic, an optional passphrase and a derivation path.
+    pub fn from_mnemonic(
+        chain: Chain,
+        phrase: &str,
+        passphrase: Option<&str>,
+        path: &str,
+    ) -> Result<Self, DatabaseError> {
+        let seed = hd::mnemonic_to_seed(phrase, passphrase)?;
+        Self::from_seed(chain, seed.as_slice(), path)
+    }
+
     /// Get the chain.
     pub fn chain(&self) -> Chain {
-        self.chain.clone()
+        self.chain
     }
 
     pub fn recover_keypair(&mut self, secret: &str) -> Result<(), DatabaseError> {
@@ -46,14 +69,14 @@ impl KeypairContext {
     /// you can call methods on it to perform operations related to generating or using
     /// keypairs. The specific methods available depend on the exact implementation
     /// of the `KeypairStrategy` trait.
-    pub fn keypair(&self) -> &Box<dyn KeypairStrategy> {
-        &self.keypair
+    pub fn keypair(&self) -> &dyn KeypairStrategy {
+        self.keypair.as_ref()
     }
 
     /// Sign a message with a secret key and return the signature.
     /// The secret key is not stored in the context and the keypair will not be changed.
     pub fn sign(&self, secret: &[u8], message: &[u8]) -> Result<String, DatabaseError> {
-        let mut keypair = Self::create_keypair(self.chain);
+        let mut keypair = Self::create_keypair(self.chain)?;
         keypair.recover_from_bytes(secret)?;
         keypair.sign(message)
     }
@@ -63,73 +86,171 @@ impl KeypairContext {
 mod tests {
 
     use super::*;
+    #[cfg(feature = "solana")]
+    use crate::SolanaKeyPair;
+    use crate::{NewKey, NewKeyExt};
+    #[cfg(feature = "solana")]
     use solana_sdk::signature::Keypair;
 
     #[test]
+    #[cfg(feature = "solana")]
     fn test_keypair_context() {
-        let context = KeypairContext::from_chain(Chain::Solana);
+        let context = KeypairContext::from_chain(Chain::Solana).unwrap();
         let keypair = context.keypair();
         assert_eq!(keypair.chain(), Chain::Solana);
     }
 
     #[test]
+    #[cfg(feature = "solana")]
     fn test_keypair_context_sign() {
-        let context = KeypairContext::from_chain(Chain::Solana);
+        let context = KeypairContext::from_chain(Chain::Solana).unwrap();
         let keypair = context.keypair();
         let message = b"hello";
         let signature = keypair.sign(message).unwrap();
-        assert_eq!(signature.len(), 88);
+        // base58 of 64 bytes is 87 or 88 characters
+        let decoded = solana_sdk::bs58::decode(signature).into_vec().unwrap();
+        assert_eq!(decoded.len(), 64);
     }
 
     #[test]
+    #[cfg(feature = "solana")]
     fn test_keypair_context_recover_keypair() {
         let keypair = Keypair::new();
-        let solana_kp = SolanaKeyPair::from_secret(keypair.to_base58_string().as_str());
+        let solana_kp = SolanaKeyPair::from_secret(keypair.to_base58_string().as_str()).unwrap();
 
         {
-            let mut context = KeypairContext::from_chain(Chain::Solana);
+            let mut context = KeypairContext::from_chain(Chain::Solana).unwrap();
             let inner = context.keypair.as_mut();
             assert_ne!(inner.secret(), solana_kp.secret());
 
             inner.recover_from_bytes(keypair.to_bytes().as_slice()).unwrap();
 
             assert_eq!(context.keypair().secret(), solana_kp.secret());
-            assert_eq!(context.keypair().secret(), keypair.to_base58_string());
+            assert_eq!(context.keypair().secret().as_str(), keypair.to_base58_string());
         }
 
         {
-            let mut context = KeypairContext::from_chain(Chain::Solana);
+            let mut context = KeypairContext::from_chain(Chain::Solana).unwrap();
             assert_ne!(context.keypair().secret(), solana_kp.secret());
 
             context.recover_keypair(keypair.to_base58_string().as_str()).unwrap();
 
             assert_eq!(context.keypair().secret(), solana_kp.secret());
-            assert_eq!(context.keypair().secret(), keypair.to_base58_string());
+            assert_eq!(context.keypair().secret().as_str(), keypair.to_base58_string());
         }
     }
 
     #[test]
+    #[cfg(feature = "solana")]
     fn test_keypair_context_from_secret() {
         let keypair = Keypair::new();
-        let solana_kp = SolanaKeyPair::from_secret(keypair.to_base58_string().as_str());
+        let solana_kp = SolanaKeyPair::from_secret(keypair.to_base58_string().as_str()).unwrap();
         let context =
             KeypairContext::from_secret(Chain::Solana, keypair.to_base58_string().as_str())
                 .unwrap();
         assert_eq!(context.keypair().secret(), solana_kp.secret());
-        assert_eq!(context.keypair().secret(), keypair.to_base58_string());
+        assert_eq!(context.keypair().secret().as_str(), keypair.to_base58_string());
 
         let context =
             KeypairContext::from_secret(Chain::Solana, solana_kp.secret().as_str()).unwrap();
         assert_eq!(context.keypair().secret(), solana_kp.secret());
-        assert_eq!(context.keypair().secret(), keypair.to_base58_string());
+        assert_eq!(context.keypair().secret().as_str(), keypair.to_base58_string());
 
-        let mut context = KeypairContext::from_chain(Chain::Solana);
+        let mut context = KeypairContext::from_chain(Chain::Solana).unwrap();
         let inner = context.keypair.as_mut();
         assert_ne!(inner.secret(), solana_kp.secret());
 
         inner.recover_from_bytes(keypair.to_bytes().as_slice()).unwrap();
 
         assert_eq!(context.keypair().secret(), solana_kp.secret());
-        assert_eq!(context.keypair().secret(), keypair.to_base58_string());
+        assert_eq!(context.keypair().secret().as_str(), keypair.to_base58_string());
+    }
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_keypair_context_ethereum() {
+        let context = KeypairContext::from_chain(Chain::Ethereum).unwrap();
+        let keypair = context.keypair();
+        assert_eq!(keypair.chain(), Chain::Ethereum);
+        assert!(keypair.address().starts_with("0x"));
+
+        let recovered =
+            KeypairContext::from_secret(Chain::Ethereum, keypair.secret().as_str()).unwrap();
+        assert_eq!(recovered.keypair().address(), keypair.address());
+
+        let signature = context.sign(keypair.to_vec().as_slice(), b"hello").unwrap();
+        assert_eq!(signature, keypair.sign(b"hello").unwrap());
+    }
+
+    #[test]
+    #[cfg(feature = "bitcoin")]
+    fn test_keypair_context_bitcoin() {
+        let context = KeypairContext::from_chain(Chain::Bitcoin).unwrap();
+        let keypair = context.keypair();
+        assert_eq!(keypair.chain(), Chain::Bitcoin);
+        assert!(keypair.address().starts_with("bc1q"));
+
+        let recovered =
+            KeypairContext::from_secret(Chain::Bitcoin, keypair.secret().as_str()).unwrap();
+        assert_eq!(recovered.keypair().address(), keypair.address());
+
+        let context = KeypairContext::from_chain(Chain::Dogecoin).unwrap();
+        assert!(context.keypair().address().starts_with('D'));
+    }
+
+    const PHRASE: &str = "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_keypair_context_from_mnemonic_ethereum() {
+        let path = hd::path_at(hd::default_path(Chain::Ethereum).unwrap(), 0);
+        let context = KeypairContext::from_mnemonic(Chain::Ethereum, PHRASE, None, &path).unwrap();
+        assert_eq!(context.keypair().address(), "0x9858EfFD232B4033E47d90003D41EC34EcaEda94");
+    }
+
+    #[test]
+    #[cfg(feature = "bitcoin")]
+    fn test_keypair_context_from_mnemonic_bitcoin() {
+        let path = hd::path_at(hd::default_path(Chain::Bitcoin).unwrap(), 0);
+        let context = KeypairContext::from_mnemonic(Chain::Bitcoin, PHRASE, None, &path).unwrap();
+        assert_eq!(context.keypair().address(), "bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu");
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_keypair_context_from_mnemonic_solana() {
+        let phrase = PHRASE;
+        // the solana keypair is rebuilt from the derived secret and public key
+        let path = hd::path_at(hd::default_path(Chain::Solana).unwrap(), 0);
+        let context = KeypairContext::from_mnemonic(Chain::Solana, phrase, None, &path).unwrap();
+        let seed = hd::mnemonic_to_seed(phrase, None).unwrap();
+        let derived = hd::derive_ed25519(seed.as_slice(), &path).unwrap();
+        assert_eq!(context.keypair().to_vec().as_slice(), derived.as_slice());
+
+        let other = KeypairContext::from_mnemonic(Chain::Solana, phrase, Some("secret"), &path);
+        assert_ne!(other.unwrap().keypair().pubkey(), context.keypair().pubkey());
+    }
+
+    #[test]
+    fn test_secret_is_not_formatted() {
+        for chain in registry::chains().iter().map(|spec| spec.chain) {
+            let context = KeypairContext::from_chain(chain).unwrap();
+            let keypair = context.keypair();
+            let secret = keypair.secret();
+            let bytes = keypair.to_vec();
+            let key = NewKey::from_keypair(keypair, None);
+
+            let formatted = format!("{:?} {:?} {:?} {:#?}", secret, bytes, key, key);
+            assert!(!formatted.contains(secret.as_str()), "{formatted}");
+            assert!(!formatted.contains(&hex::encode(bytes.as_slice())), "{formatted}");
+            assert!(!formatted.contains(&format!("{:?}", bytes.expose())), "{formatted}");
+            assert!(formatted.contains(&keypair.address()));
+        }
+    }
+
+    #[test]
+    fn test_keypair_context_unknown_chain() {
+        assert!(KeypairContext::create_keypair(Chain::Unknown).is_err());
+        assert!(KeypairContext::from_chain(Chain::Unknown).is_err());
     }
 }
@@ -0,0 +1,339 @@
+//! EIP-191 and EIP-712 message hashing.
+//!
+//! See <https://eips.ethereum.org/EIPS/eip-191> and <https://eips.ethereum.org/EIPS/eip-712>.
+use std::collections::{BTreeMap, BTreeSet};
+
+use serde::Deserialize;
+use serde_json::{Map, Value};
+
+use crate::{ethereum::keccak256, DatabaseError};
+
+const DOMAIN_TYPE: &str = "EIP712Domain";
+
+/// The fields of the domain type, in the order EIP-712 lists them.
+const DOMAIN_FIELDS: [(&str, &str); 5] = [
+    ("name", "string"),
+    ("version", "string"),
+    ("chainId", "uint256"),
+    ("verifyingContract", "address"),
+    ("salt", "bytes32"),
+];
+
+/// Hash a message as `personal_sign` does.
+pub fn hash_personal_message(message: &[u8]) -> [u8; 32] {
+    let mut data = format!("\x19Ethereum Signed Message:\n{}", message.len()).into_bytes();
+    data.extend_from_slice(message);
+    keccak256(&data)
+}
+
+#[derive(Debug, Clone, Deserialize)]
+pub struct TypedField {
+    pub name: String,
+    #[serde(rename = "type")]
+    pub kind: String,
+}
+
+/// The `eth_signTypedData_v4` payload.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct TypedData {
+    #[serde(default)]
+    pub types: BTreeMap<String, Vec<TypedField>>,
+    pub primary_type: String,
+    pub domain: Map<String, Value>,
+    #[serde(default)]
+    pub message: Value,
+}
+
+impl TypedData {
+    pub fn from_json(json: &[u8]) -> Result<Self, DatabaseError> {
+        let mut data: TypedData = serde_json::from_slice(json).map_err(invalid)?;
+        // derive the domain type from the fields present when it is not declared
+        data.types.entry(DOMAIN_TYPE.to_string()).or_insert_with(|| {
+            DOMAIN_FIELDS
+                .iter()
+                .filter(|(name, _)| data.domain.contains_key(*name))
+                .map(|(name, kind)| TypedField { name: name.to_string(), kind: kind.to_string() })
+                .collect()
+        });
+        Ok(data)
+    }
+
+    /// The digest to sign: `keccak256(0x1901 || domainSeparator || hashStruct(message))`.
+    pub fn digest(&self) -> Result<[u8; 32], DatabaseError> {
+        let mut data = vec![0x19, 0x01];
+        data.extend_from_slice(&self.domain_separator()?);
+        if self.primary_type != DOMAIN_TYPE {
+            data.extend_from_slice(&self.hash_struct(&self.primary_type, &self.message)?);
+        }
+        Ok(keccak256(&data))
+    }
+
+    pub fn domain_separator(&self) -> Result<[u8; 32], DatabaseError> {
+        self.hash_struct(DOMAIN_TYPE, &Value::Object(self.domain.clone()))
+    }
+
+    pub fn hash_struct(&self, kind: &str, value: &Value) -> Result<[u8; 32], DatabaseError> {
+        Ok(keccak256(&self.encode_data(kind, value)?))
+    }
+
+    /// The type with its referenced struct types appended in alphabetical order.
+    pub fn encode_type(&self, kind: &str) -> Result<String, DatabaseError> {
+        let mut deps = BTreeSet::new();
+        self.dependencies(kind, &mut deps)?;
+        deps.remove(kind);
+
+        let mut encoded = String::new();
+        for name in std::iter::once(kind).chain(deps.iter().map(String::as_str)) {
+            let fields = self.fields(name)?;
+            let fields: Vec<String> =
+                fields.iter().map(|field| format!("{} {}", field.kind, field.name)).collect();
+            encoded.push_str(&format!("{name}({})", fields.join(",")));
+        }
+        Ok(encoded)
+    }
+
+    fn fields(&self, kind: &str) -> Result<&Vec<TypedField>, DatabaseError> {
+        self.types.get(kind).ok_or_else(|| invalid(format!("undefined type `{kind}`")))
+    }
+
+    fn dependencies(&self, kind: &str, deps: &mut BTreeSet<String>) -> Result<(), DatabaseError> {
+        if deps.contains(kind) {
+            return Ok(());
+        }
+        deps.insert(kind.to_string());
+        for field in self.fields(kind)? {
+            let base = base_type(&field.kind);
+            if self.types.contains_key(base) {
+                self.dependencies(base, deps)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn encode_data(&self, kind: &str, value: &Value) -> Result<Vec<u8>, DatabaseError> {
+        let object =
+            value.as_object().ok_or_else(|| invalid(format!("`{kind}` is not an object")))?;
+        let mut data = keccak256(self.encode_type(kind)?.as_bytes()).to_vec();
+        for field in self.fields(kind)? {
+            let value = object.get(&field.name).unwrap_or(&Value::Null);
+            data.extend_from_slice(&self.encode_value(&field.kind, value)?);
+        }
+        Ok(data)
+    }
+
+    fn encode_value(&self, kind: &str, value: &Value) -> Result<[u8; 32], DatabaseError> {
+        if let Some(item) = kind.strip_suffix(']').and_then(|s| s.rsplit_once('[')).map(|s| s.0) {
+            let items =
+                value.as_array().ok_or_else(|| invalid(format!("`{kind}` is not an array")))?;
+            let mut data = Vec::with_capacity(items.len() * 32);
+            for value in items {
+                data.extend_from_slice(&self.encode_value(item, value)?);
+            }
+            return Ok(keccak256(&data));
+        }
+        if self.types.contains_key(kind) {
+            return self.hash_struct(kind, value);
+        }
+        encode_atomic(kind, value)
+    }
+}
+
+/// Strip the array suffixes of a type, `Person[][2]` is `Person`.
+fn base_type(kind: &str) -> &str {
+    kind.split('[').next().unwrap_or(kind)
+}
+
+fn encode_atomic(kind: &str, value: &Value) -> Result<[u8; 32], DatabaseError> {
+    let mut word = [0u8; 32];
+    match kind {
+        "string" => {
+            let s = value.as_str().ok_or_else(|| invalid(format!("`{value}` is not a string")))?;
+            return Ok(keccak256(s.as_bytes()));
+        }
+        "bytes" => return Ok(keccak256(&hex_value(value)?)),
+        "bool" => {
+            word[31] = match value {
+                Value::Bool(b) => *b as u8,
+                Value::String(s) if s == "true" || s == "false" => (s == "true") as u8,
+                _ => return Err(invalid(format!("`{value}` is not a bool"))),
+            };
+        }
+        "address" => {
+            let bytes = hex_value(value)?;
+            if bytes.len() != 20 {
+                return Err(invalid(format!("`{value}` is not an address")));
+            }
+            word[12..].copy_from_slice(&bytes);
+        }
+        _ if kind.starts_with("bytes") => {
+            let size: usize = kind[5..].parse().map_err(|_| invalid(format!("type `{kind}`")))?;
+            let bytes = hex_value(value)?;
+            if !(1..=32).contains(&size) || bytes.len() != size {
+                return Err(invalid(format!("`{value}` is not a {kind}")));
+            }
+            word[..size].copy_from_slice(&bytes);
+        }
+        _ if kind.starts_with("uint") || kind.starts_with("int") => {
+            word = encode_integer(kind, value)?;
+        }
+        _ => return Err(invalid(format!("undefined type `{kind}`"))),
+    }
+    Ok(word)
+}
+
+/// Encode a JSON number, decimal or `0x` hex string as a 256-bit two's complement word.
+fn encode_integer(kind: &str, value: &Value) -> Result<[u8; 32], DatabaseError> {
+    let text = match value {
+        Value::Number(n) => n.to_string(),
+        Value::String(s) => s.trim().to_string(),
+        _ => return Err(invalid(format!("`{value}` is not a {kind}"))),
+    };
+    let (negative, digits) = match text.strip_prefix('-') {
+        Some(digits) if kind.starts_with("int") => (true, digits),
+        Some(_) => return Err(invalid(format!("`{text}` is not a {kind}"))),
+        None => (false, text.as_str()),
+    };
+
+    let mut word = [0u8; 32];
+    let (radix, digits) = match digits.strip_prefix("0x") {
+        Some(hex) => (16, hex),
+        None => (10, digits),
+    };
+    if digits.is_empty() {
+        return Err(invalid(format!("`{text}` is not a {kind}")));
+    }
+    for c in digits.chars() {
+        let digit =
+            c.to_digit(radix).ok_or_else(|| invalid(format!("`{text}` is not a {kind}")))?;
+        // word = word * radix + digit
+        let mut carry = digit;
+        for byte in word.iter_mut().rev() {
+            let v = *byte as u32 * radix + carry;
+            *byte = v as u8;
+            carry = v >> 8;
+        }
+        if carry != 0 {
+            return Err(invalid(format!("`{text}` overflows {kind}")));
+        }
+    }
+    if negative {
+        // two's complement
+        let mut carry = 1u16;
+        for byte in word.iter_mut().rev() {
+            let v = (!*byte) as u16 + carry;
+            *byte = v as u8;
+            carry = v >> 8;
+        }
+    }
+    Ok(word)
+}
+
+fn hex_value(value: &Value) -> Result<Vec<u8>, DatabaseError> {
+    let s = value.as_str().ok_or_else(|| invalid(format!("`{value}` is not hex")))?;
+    Ok(hex::decode(s.trim_start_matches("0x"))?)
+}
+
+fn invalid<E: ToString>(e: E) -> DatabaseError {
+    DatabaseError::InvalidMessage(e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{EthereumKeyPair, KeypairStrategy, SignScheme};
+
+    // the example from https://eips.ethereum.org/EIPS/eip-712
+    const MAIL: &str = r#"{
+        "types": {
+            "EIP712Domain": [
+                {"name": "name", "type": "string"},
+                {"name": "version", "type": "string"},
+                {"name": "chainId", "type": "uint256"},
+                {"name": "verifyingContract", "type": "address"}
+            ],
+            "Person": [
+                {"name": "name", "type": "string"},
+                {"name": "wallet", "type": "address"}
+            ],
+            "Mail": [
+                {"name": "from", "type": "Person"},
+                {"name": "to", "type": "Person"},
+                {"name": "contents", "type": "string"}
+            ]
+        },
+        "primaryType": "Mail",
+        "domain": {
+            "name": "Ether Mail",
+            "version": "1",
+            "chainId": 1,
+            "verifyingContract": "0xCcCCccccCCCCcCCCCCCcCcCccCcCCCcCcccccccC"
+        },
+        "message": {
+            "from": {"name": "Cow", "wallet": "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826"},
+            "to": {"name": "Bob", "wallet": "0xbBbBBBBbbBBBbbbBbbBbbbbBBbBbbbbBbBbbBBbB"},
+            "contents": "Hello, Bob!"
+        }
+    }"#;
+
+    #[test]
+    fn test_typed_data_digest() {
+        let data = TypedData::from_json(MAIL.as_bytes()).unwrap();
+        assert_eq!(
+            data.encode_type("Mail").unwrap(),
+            "Mail(Person from,Person to,string contents)Person(string name,address wallet)"
+        );
+        assert_eq!(
+            hex::encode(data.domain_separator().unwrap()),
+            "f2cee375fa42b42143804025fc449deafd50cc031ca257e0b194a650a912090f"
+        );
+        assert_eq!(
+            hex::encode(data.digest().unwrap()),
+            "be609aee343fb3c4b28e1df9e632fca64fcfaede20f02e86244efddf30957bd2"
+        );
+
+        // the domain type is derived when it is not declared
+        let mut json: Value = serde_json::from_str(MAIL).unwrap();
+        json["types"].as_object_mut().unwrap().remove(DOMAIN_TYPE);
+        let derived = TypedData::from_json(json.to_string().as_bytes()).unwrap();
+        assert_eq!(derived.digest().unwrap(), data.digest().unwrap());
+    }
+
+    #[test]
+    fn test_sign_typed_data() {
+        // keccak256("cow") signs the example mail
+        let keypair = EthereumKeyPair::from_secret(
+            "0xc85ef7d79691fe79573b1a7064c19c1a9819ebdbd1faaab1a8ec92344438aaf4",
+        )
+        .unwrap();
+        assert_eq!(keypair.address(), "0xCD2a3d9F938E13CD947Ec05AbC7FE734Df8DD826");
+        assert_eq!(
+            keypair.sign_with(SignScheme::TypedData, MAIL.as_bytes()).unwrap(),
+            "0x4355c47d63924e8a72e509b65029052eb6c299d53a04e167c5775fd466751c9d\
+             07299936d304c153f6443dfa05f40ff007d72911b6f72307f996231605b91562\
+             1c"
+        );
+    }
+
+    #[test]
+    fn test_encode_integer() {
+        let word = encode_integer("uint256", &Value::from(258)).unwrap();
+        assert_eq!(&word[30..], &[1, 2]);
+        assert_eq!(encode_integer("uint256", &Value::from("0x0102")).unwrap(), word);
+        assert_eq!(encode_integer("int8", &Value::from(-1)).unwrap(), [0xffu8; 32]);
+        assert!(encode_integer("uint8", &Value::from(-1)).is_err());
+        assert!(encode_integer("uint256", &Value::from("1".repeat(80))).is_err());
+    }
+
+    #[test]
+    fn test_invalid_typed_data() {
+        let mut json: Value = serde_json::from_str(MAIL).unwrap();
+        json["message"]["to"]["wallet"] = Value::from("0x1234");
+        assert!(TypedData::from_json(json.to_string().as_bytes()).unwrap().digest().is_err());
+
+        json["primaryType"] = Value::from("Letter");
+        assert!(TypedData::from_json(json.to_string().as_bytes()).unwrap().digest().is_err());
+        assert!(TypedData::from_json(b"hello").is_err());
+    }
+}
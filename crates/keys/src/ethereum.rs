@@ -0,0 +1,292 @@
+use std::fmt;
+
+use libsecp256k1::{Message, PublicKey, RecoveryId, SecretKey, Signature};
+use sha3::{Digest, Keccak256};
+
+use crate::{
+    eip712::{hash_personal_message, TypedData},
+    hd::Curve,
+    matcher::Alphabet,
+    registry::{AddressEncoding, ChainSpec},
+    secp256k1::random_secret,
+    Chain, DatabaseError, KeypairStrategy, SecretBytes, SecretString, SignScheme,
+};
+
+pub struct EthereumKeyPair(SecretKey);
+
+impl EthereumKeyPair {
+    pub fn new() -> Self {
+        EthereumKeyPair(random_secret())
+    }
+
+    pub fn from_secret(s: &str) -> Result<Self, DatabaseError> {
+        let mut keypair = Self::new();
+        keypair.recover_secret(s)?;
+        Ok(keypair)
+    }
+
+    fn public(&self) -> PublicKey {
+        PublicKey::from_secret_key(&self.0)
+    }
+
+    /// sign a 32 bytes digest
+    pub(crate) fn sign_hash(&self, digest: &[u8; 32]) -> (Signature, RecoveryId) {
+        libsecp256k1::sign(&Message::parse(digest), &self.0)
+    }
+
+    /// sign a 32 bytes digest, return the hex encoded r || s || v
+    fn sign_digest(&self, digest: &[u8; 32]) -> String {
+        let (signature, recovery_id) = self.sign_hash(digest);
+
+        let mut bytes = signature.serialize().to_vec();
+        bytes.push(recovery_id.serialize() + 27);
+        format!("0x{}", hex::encode(bytes))
+    }
+}
+
+impl Default for EthereumKeyPair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl fmt::Debug for EthereumKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "EthereumKeyPair({})", self.address())
+    }
+}
+
+pub fn keccak256(data: &[u8]) -> [u8; 32] {
+    Keccak256::digest(data).into()
+}
+
+/// The digest signed for a message under the scheme.
+fn message_digest(scheme: SignScheme, message: &[u8]) -> Result<[u8; 32], DatabaseError> {
+    match scheme {
+        SignScheme::Raw => Ok(keccak256(message)),
+        SignScheme::Personal => Ok(hash_personal_message(message)),
+        SignScheme::TypedData => TypedData::from_json(message)?.digest(),
+        SignScheme::OffChain => {
+            Err(DatabaseError::UnsupportedOperation(Chain::Ethereum, scheme.to_string()))
+        }
+    }
+}
+
+/// Derive the EIP-55 checksummed address of an uncompressed public key.
+pub fn to_checksum_address(pubkey: &PublicKey) -> String {
+    // skip the 0x04 prefix of the uncompressed encoding
+    let hash = keccak256(&pubkey.serialize()[1..]);
+    let lower = hex::encode(&hash[12..]);
+    checksum(lower.as_str())
+}
+
+/// Apply the EIP-55 mixed-case checksum to a lowercase hex address without the `0x` prefix.
+fn checksum(lower: &str) -> String {
+    let mut hex = lower.as_bytes().to_vec();
+    apply_checksum(hex.as_mut_slice());
+    format!("0x{}", String::from_utf8_lossy(&hex))
+}
+
+/// Apply the EIP-55 mixed-case checksum in place to lowercase hex digits.
+pub(crate) fn apply_checksum(hex: &mut [u8]) {
+    let hash = keccak256(hex);
+    for (i, c) in hex.iter_mut().enumerate() {
+        let nibble = (hash[i / 2] >> (if i % 2 == 0 { 4 } else { 0 })) & 0x0f;
+        if c.is_ascii_alphabetic() && nibble >= 8 {
+            c.make_ascii_uppercase();
+        }
+    }
+}
+
+pub const SPEC: ChainSpec = ChainSpec {
+    chain: Chain::Ethereum,
+    name: "ethereum",
+    curve: Curve::Secp256k1,
+    encoding: AddressEncoding::Eip55,
+    alphabet: Alphabet::Hex,
+    head: "0x",
+    schemes: &[SignScheme::Raw, SignScheme::Personal, SignScheme::TypedData],
+    factory: || Box::new(EthereumKeyPair::new()),
+};
+
+impl KeypairStrategy for EthereumKeyPair {
+    fn chain(&self) -> Chain {
+        Chain::Ethereum
+    }
+
+    fn generate(&mut self) {
+        self.0 = random_secret();
+    }
+
+    /// recover the keypair from a hex secret, with or without the `0x` prefix
+    fn recover_secret(&mut self, secret: &str) -> Result<(), DatabaseError> {
+        let bytes = SecretBytes::new(hex::decode(secret.trim_start_matches("0x"))?);
+        self.recover_from_bytes(bytes.as_slice())
+    }
+
+    fn recover_from_bytes(&mut self, bytes: &[u8]) -> Result<(), DatabaseError> {
+        let secret =
+            SecretKey::parse_slice(bytes).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        self.0 = secret;
+        Ok(())
+    }
+
+    fn to_vec(&self) -> SecretBytes {
+        SecretBytes::from_slice(&self.0.serialize())
+    }
+
+    fn secret(&self) -> SecretString {
+        SecretString::new(format!("0x{}", hex::encode(self.0.serialize())))
+    }
+
+    /// the uncompressed public key, hex encoded with the `0x04` prefix
+    fn pubkey(&self) -> String {
+        format!("0x{}", hex::encode(self.public().serialize()))
+    }
+
+    fn address(&self) -> String {
+        to_checksum_address(&self.public())
+    }
+
+    /// sign the keccak256 digest of the message, return the hex encoded r || s || v
+    fn sign(&self, message: &[u8]) -> Result<String, DatabaseError> {
+        Ok(self.sign_digest(&keccak256(message)))
+    }
+
+    /// `personal_sign` prefixes the message, typed data is the EIP-712 JSON payload
+    fn sign_with(&self, scheme: SignScheme, message: &[u8]) -> Result<String, DatabaseError> {
+        Ok(self.sign_digest(&message_digest(scheme, message)?))
+    }
+
+    fn verify(&self, public: &str, message: &[u8], signature: &str) -> Result<bool, DatabaseError> {
+        self.verify_with(SignScheme::Raw, public, message, signature)
+    }
+
+    /// recover the signer of the hex r || s || v, compare it to a `0x04` pubkey or an address
+    fn verify_with(
+        &self,
+        scheme: SignScheme,
+        public: &str,
+        message: &[u8],
+        signature: &str,
+    ) -> Result<bool, DatabaseError> {
+        let bytes = hex::decode(signature.trim_start_matches("0x"))
+            .map_err(|e| DatabaseError::InvalidSignature(format!("`{signature}`: {e}")))?;
+        if bytes.len() != 65 {
+            return Err(DatabaseError::InvalidSignature(format!("`{signature}` is not 65 bytes")));
+        }
+        let signature = Signature::parse_standard_slice(&bytes[..64])
+            .map_err(|e| DatabaseError::InvalidSignature(e.to_string()))?;
+        let recovery_id = RecoveryId::parse(bytes[64].checked_sub(27).unwrap_or(bytes[64]))
+            .map_err(|e| DatabaseError::InvalidSignature(e.to_string()))?;
+
+        let digest = Message::parse(&message_digest(scheme, message)?);
+        let Ok(signer) = libsecp256k1::recover(&digest, &signature, &recovery_id) else {
+            return Ok(false);
+        };
+        if public.len() == 42 {
+            return Ok(to_checksum_address(&signer).eq_ignore_ascii_case(public));
+        }
+        let public = hex::decode(public.trim_start_matches("0x"))
+            .ok()
+            .and_then(|bytes| PublicKey::parse_slice(&bytes, None).ok())
+            .ok_or_else(|| DatabaseError::InvalidSignature(format!("pubkey `{public}`")))?;
+        Ok(public == signer)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // well-known test vector (hardhat account #0)
+    const SECRET: &str = "0xac0974bec39a17e36ba4a6b4d238ff944bacb478cbed5efcae784d7bf4f2ff80";
+    const ADDRESS: &str = "0xf39Fd6e51aad88F6F4ce6aB8827279cffFb92266";
+
+    #[test]
+    fn test_checksum() {
+        // test vectors from https://eips.ethereum.org/EIPS/eip-55
+        for address in [
+            "0x5aAeb6053F3E94C9b9A09f33669435E7Ef1BeAed",
+            "0xfB6916095ca1df60bB79Ce92cE3Ea74c37c5d359",
+            "0xdbF03B407c01E7cD3CBea99509d93f8DDDC8C6FB",
+            "0xD1220A0cf47c7B9Be7A2E6BA89F429762e7b9aDb",
+        ] {
+            let lower = address.trim_start_matches("0x").to_ascii_lowercase();
+            assert_eq!(checksum(lower.as_str()), address);
+        }
+    }
+
+    #[test]
+    fn test_generator() {
+        let strategy = EthereumKeyPair::from_secret(SECRET).unwrap();
+        assert_eq!(strategy.secret().as_str(), SECRET);
+        assert_eq!(strategy.address(), ADDRESS);
+        assert_eq!(strategy.pubkey().len(), 2 + 65 * 2);
+
+        let mut recovered = EthereumKeyPair::new();
+        assert_ne!(recovered.address(), ADDRESS);
+        recovered.recover_from_bytes(strategy.to_vec().as_slice()).unwrap();
+        assert_eq!(recovered.address(), ADDRESS);
+
+        recovered.recover_secret(SECRET.trim_start_matches("0x")).unwrap();
+        assert_eq!(recovered.address(), ADDRESS);
+    }
+
+    #[test]
+    fn test_sign() {
+        let strategy = EthereumKeyPair::from_secret(SECRET).unwrap();
+        let message = b"hello";
+        let signature = strategy.sign(message).unwrap();
+        let bytes = hex::decode(signature.trim_start_matches("0x")).unwrap();
+        assert_eq!(bytes.len(), 65);
+
+        let digest = Message::parse(&keccak256(message));
+        let sig = libsecp256k1::Signature::parse_standard_slice(&bytes[..64]).unwrap();
+        let recovery_id = libsecp256k1::RecoveryId::parse_rpc(bytes[64]).unwrap();
+        let recovered = libsecp256k1::recover(&digest, &sig, &recovery_id).unwrap();
+        assert_eq!(to_checksum_address(&recovered), ADDRESS);
+    }
+
+    #[test]
+    fn test_personal_sign() {
+        let strategy = EthereumKeyPair::from_secret(SECRET).unwrap();
+        let signature = strategy.sign_with(SignScheme::Personal, b"hello").unwrap();
+        assert_ne!(signature, strategy.sign(b"hello").unwrap());
+        let bytes = hex::decode(signature.trim_start_matches("0x")).unwrap();
+
+        let digest = Message::parse(&hash_personal_message(b"hello"));
+        let sig = libsecp256k1::Signature::parse_standard_slice(&bytes[..64]).unwrap();
+        let recovery_id = libsecp256k1::RecoveryId::parse_rpc(bytes[64]).unwrap();
+        let recovered = libsecp256k1::recover(&digest, &sig, &recovery_id).unwrap();
+        assert_eq!(to_checksum_address(&recovered), ADDRESS);
+    }
+
+    #[test]
+    fn test_verify() {
+        let strategy = EthereumKeyPair::from_secret(SECRET).unwrap();
+        let pubkey = strategy.pubkey();
+        for scheme in [SignScheme::Raw, SignScheme::Personal] {
+            let signature = strategy.sign_with(scheme, b"hello").unwrap();
+            assert!(strategy.verify_with(scheme, ADDRESS, b"hello", &signature).unwrap());
+            assert!(strategy.verify_with(scheme, &pubkey, b"hello", &signature).unwrap());
+            assert!(!strategy.verify_with(scheme, ADDRESS, b"world", &signature).unwrap());
+        }
+        let signature = strategy.sign(b"hello").unwrap();
+        assert!(!strategy
+            .verify_with(SignScheme::Personal, ADDRESS, b"hello", &signature)
+            .unwrap());
+        assert!(!strategy.verify(&EthereumKeyPair::new().address(), b"hello", &signature).unwrap());
+        assert!(strategy.verify(ADDRESS, b"hello", "0x1234").is_err());
+        assert!(strategy.verify("0xzz", b"hello", &signature).is_err());
+    }
+
+    #[test]
+    fn test_invalid_secret() {
+        let mut strategy = EthereumKeyPair::new();
+        assert!(strategy.recover_from_bytes(&[0u8; 32]).is_err());
+        assert!(strategy.recover_from_bytes(&[1u8; 31]).is_err());
+        assert!(strategy.recover_secret("0xzz").is_err());
+    }
+}
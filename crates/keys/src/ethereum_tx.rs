@@ -0,0 +1,381 @@
+//! Offline Ethereum transactions: legacy (EIP-155), EIP-2930 and EIP-1559.
+//!
+//! Every field is supplied by the caller, nothing is looked up on a node.
+use std::{fmt, str::FromStr};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{ethereum::keccak256, DatabaseError, EthereumKeyPair};
+
+/// The transaction envelope.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Deserialize, clap::ValueEnum)]
+#[serde(rename_all = "lowercase")]
+pub enum TxType {
+    Legacy,
+    Eip2930,
+    #[default]
+    Eip1559,
+}
+
+/// An unsigned integer given as a JSON number, a decimal or a `0x` hex string.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct Quantity(pub u128);
+
+impl FromStr for Quantity {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let value = match s.strip_prefix("0x") {
+            Some(hex) => u128::from_str_radix(hex, 16),
+            None => s.parse(),
+        };
+        value.map(Quantity).map_err(|e| format!("invalid quantity `{s}`: {e}"))
+    }
+}
+
+impl fmt::Display for Quantity {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}", self.0)
+    }
+}
+
+impl<'de> Deserialize<'de> for Quantity {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(untagged)]
+        enum Raw {
+            Number(u64),
+            Text(String),
+        }
+        match Raw::deserialize(deserializer)? {
+            Raw::Number(n) => Ok(Quantity(n as u128)),
+            Raw::Text(s) => s.parse().map_err(serde::de::Error::custom),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Default, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "camelCase")]
+pub struct AccessListItem {
+    pub address: String,
+    #[serde(default)]
+    pub storage_keys: Vec<String>,
+}
+
+/// An unsigned transaction, as JSON or as `anita key sign-tx` options.
+#[derive(Debug, Clone, Default, Deserialize, clap::Args)]
+#[serde(rename_all = "camelCase")]
+pub struct EthTransaction {
+    /// The transaction type
+    #[serde(default, rename = "type")]
+    #[arg(long = "type", value_enum, default_value_t = TxType::Eip1559)]
+    pub kind: TxType,
+    /// The EIP-155 chain id
+    #[arg(long)]
+    pub chain_id: Quantity,
+    /// The sender's transaction count
+    #[serde(default)]
+    #[arg(long, default_value = "0")]
+    pub nonce: Quantity,
+    /// The gas the transaction may use
+    #[arg(long)]
+    pub gas_limit: Quantity,
+    /// The gas price of legacy and EIP-2930 transactions
+    #[serde(default)]
+    #[arg(long)]
+    pub gas_price: Option<Quantity>,
+    /// The fee cap of EIP-1559 transactions
+    #[serde(default)]
+    #[arg(long)]
+    pub max_fee_per_gas: Option<Quantity>,
+    /// The priority fee of EIP-1559 transactions
+    #[serde(default)]
+    #[arg(long)]
+    pub max_priority_fee_per_gas: Option<Quantity>,
+    /// The recipient, omitted to create a contract
+    #[serde(default)]
+    #[arg(long)]
+    pub to: Option<String>,
+    /// The value in wei
+    #[serde(default)]
+    #[arg(long, default_value = "0")]
+    pub value: Quantity,
+    /// The hex encoded call data
+    #[serde(default)]
+    #[arg(long, default_value = "0x")]
+    pub data: String,
+    #[serde(default)]
+    #[arg(skip)]
+    pub access_list: Vec<AccessListItem>,
+}
+
+/// A signed transaction ready for `eth_sendRawTransaction`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct SignedTransaction {
+    pub raw: String,
+    pub hash: String,
+}
+
+impl EthTransaction {
+    /// The hash the sender signs.
+    pub fn signing_hash(&self) -> Result<[u8; 32], DatabaseError> {
+        let mut fields = self.fields()?;
+        if self.kind == TxType::Legacy {
+            // EIP-155 replay protection
+            fields.extend([rlp_uint(self.chain_id.0), rlp_uint(0), rlp_uint(0)]);
+        }
+        Ok(keccak256(&self.envelope(&fields)))
+    }
+
+    /// Sign the transaction, return the raw transaction and its hash.
+    pub fn sign(&self, keypair: &EthereumKeyPair) -> Result<SignedTransaction, DatabaseError> {
+        let (signature, recovery_id) = keypair.sign_hash(&self.signing_hash()?);
+        let signature = signature.serialize();
+        let recovery_id = recovery_id.serialize() as u128;
+
+        let mut fields = self.fields()?;
+        let v = match self.kind {
+            TxType::Legacy => self
+                .chain_id
+                .0
+                .checked_mul(2)
+                .and_then(|v| v.checked_add(35 + recovery_id))
+                .ok_or_else(|| {
+                    DatabaseError::TransactionError(format!(
+                        "chain id {} is too large",
+                        self.chain_id
+                    ))
+                })?,
+            _ => recovery_id,
+        };
+        fields.extend([rlp_uint(v), rlp_word(&signature[..32]), rlp_word(&signature[32..])]);
+
+        let raw = self.envelope(&fields);
+        Ok(SignedTransaction {
+            raw: format!("0x{}", hex::encode(&raw)),
+            hash: format!("0x{}", hex::encode(keccak256(&raw))),
+        })
+    }
+
+    /// The RLP encoded fields before the signature.
+    fn fields(&self) -> Result<Vec<Vec<u8>>, DatabaseError> {
+        let to = match self.to.as_deref() {
+            Some(to) => decode_hex(to, Some(20))?,
+            None => vec![],
+        };
+        let data = decode_hex(&self.data, None)?;
+        let common =
+            [rlp_uint(self.gas_limit.0), rlp_bytes(&to), rlp_uint(self.value.0), rlp_bytes(&data)];
+
+        let fields = match self.kind {
+            TxType::Legacy => [
+                vec![rlp_uint(self.nonce.0), rlp_uint(required(self.gas_price, "gasPrice")?)],
+                common.to_vec(),
+            ]
+            .concat(),
+            TxType::Eip2930 => [
+                vec![
+                    rlp_uint(self.chain_id.0),
+                    rlp_uint(self.nonce.0),
+                    rlp_uint(required(self.gas_price, "gasPrice")?),
+                ],
+                common.to_vec(),
+                vec![self.access_list()?],
+            ]
+            .concat(),
+            TxType::Eip1559 => [
+                vec![
+                    rlp_uint(self.chain_id.0),
+                    rlp_uint(self.nonce.0),
+                    rlp_uint(required(self.max_priority_fee_per_gas, "maxPriorityFeePerGas")?),
+                    rlp_uint(required(self.max_fee_per_gas, "maxFeePerGas")?),
+                ],
+                common.to_vec(),
+                vec![self.access_list()?],
+            ]
+            .concat(),
+        };
+        Ok(fields)
+    }
+
+    fn access_list(&self) -> Result<Vec<u8>, DatabaseError> {
+        let items = self
+            .access_list
+            .iter()
+            .map(|item| {
+                let keys = item
+                    .storage_keys
+                    .iter()
+                    .map(|key| decode_hex(key, Some(32)).map(|key| rlp_bytes(&key)))
+                    .collect::<Result<Vec<_>, _>>()?;
+                let address = decode_hex(&item.address, Some(20))?;
+                Ok(rlp_list(&[rlp_bytes(&address), rlp_list(&keys)]))
+            })
+            .collect::<Result<Vec<_>, DatabaseError>>()?;
+        Ok(rlp_list(&items))
+    }
+
+    /// Legacy transactions are a bare list, typed ones are prefixed with the type byte.
+    fn envelope(&self, fields: &[Vec<u8>]) -> Vec<u8> {
+        let list = rlp_list(fields);
+        match self.kind {
+            TxType::Legacy => list,
+            TxType::Eip2930 => [vec![0x01], list].concat(),
+            TxType::Eip1559 => [vec![0x02], list].concat(),
+        }
+    }
+}
+
+fn required(value: Option<Quantity>, name: &str) -> Result<u128, DatabaseError> {
+    value.map(|q| q.0).ok_or_else(|| DatabaseError::TransactionError(format!("missing `{name}`")))
+}
+
+fn decode_hex(s: &str, len: Option<usize>) -> Result<Vec<u8>, DatabaseError> {
+    let bytes = hex::decode(s.trim_start_matches("0x"))
+        .map_err(|e| DatabaseError::TransactionError(format!("`{s}`: {e}")))?;
+    match len {
+        Some(len) if bytes.len() != len => {
+            Err(DatabaseError::TransactionError(format!("`{s}` is not {len} bytes")))
+        }
+        _ => Ok(bytes),
+    }
+}
+
+fn rlp_length(len: usize, offset: u8) -> Vec<u8> {
+    if len < 56 {
+        return vec![offset + len as u8];
+    }
+    let len = len.to_be_bytes();
+    let len = &len[len.iter().position(|b| *b != 0).unwrap_or(len.len())..];
+    [vec![offset + 55 + len.len() as u8], len.to_vec()].concat()
+}
+
+fn rlp_bytes(bytes: &[u8]) -> Vec<u8> {
+    match bytes {
+        [b] if *b < 0x80 => vec![*b],
+        _ => [rlp_length(bytes.len(), 0x80), bytes.to_vec()].concat(),
+    }
+}
+
+/// A big-endian integer without leading zeros.
+fn rlp_word(bytes: &[u8]) -> Vec<u8> {
+    rlp_bytes(&bytes[bytes.iter().position(|b| *b != 0).unwrap_or(bytes.len())..])
+}
+
+fn rlp_uint(value: u128) -> Vec<u8> {
+    rlp_word(&value.to_be_bytes())
+}
+
+fn rlp_list(items: &[Vec<u8>]) -> Vec<u8> {
+    let payload = items.concat();
+    [rlp_length(payload.len(), 0xc0), payload].concat()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use libsecp256k1::{Message, RecoveryId, Signature};
+
+    use super::*;
+    use crate::{ethereum::to_checksum_address, KeypairStrategy};
+
+    fn transfer(kind: TxType) -> EthTransaction {
+        EthTransaction {
+            kind,
+            chain_id: Quantity(1),
+            nonce: Quantity(9),
+            gas_limit: Quantity(21000),
+            gas_price: Some(Quantity(20_000_000_000)),
+            max_fee_per_gas: Some(Quantity(30_000_000_000)),
+            max_priority_fee_per_gas: Some(Quantity(1_000_000_000)),
+            to: Some("0x3535353535353535353535353535353535353535".to_string()),
+            value: Quantity(1_000_000_000_000_000_000),
+            data: "0x".to_string(),
+            access_list: vec![],
+        }
+    }
+
+    #[test]
+    fn test_rlp() {
+        assert_eq!(rlp_bytes(b"dog"), [0x83, b'd', b'o', b'g']);
+        assert_eq!(rlp_uint(0), [0x80]);
+        assert_eq!(rlp_uint(15), [0x0f]);
+        assert_eq!(rlp_uint(1024), [0x82, 0x04, 0x00]);
+        assert_eq!(rlp_list(&[]), [0xc0]);
+        let long = rlp_bytes(&[0xaa; 56]);
+        assert_eq!(&long[..2], &[0xb8, 56]);
+    }
+
+    #[test]
+    fn test_sign_legacy() {
+        // the example from https://eips.ethereum.org/EIPS/eip-155
+        let keypair = EthereumKeyPair::from_secret(&"46".repeat(32)).unwrap();
+        let tx = transfer(TxType::Legacy);
+        assert_eq!(
+            hex::encode(tx.signing_hash().unwrap()),
+            "daf5a779ae972f972197303d7b574746c7ef83eadac0f2791ad23db92e4c8e53"
+        );
+        let signed = tx.sign(&keypair).unwrap();
+        assert_eq!(
+            signed.raw,
+            "0xf86c098504a817c800825208943535353535353535353535353535353535353535880de0b6b3a7\
+             6400008025a028ef61340bd939bc2195fe537567866003e1a15d3c71ff63e1590620aa636276a067\
+             cbe9d8997f761aecb703304b3800ccf555c9f3dc64214b297fb1966a3b6d83"
+        );
+        assert_eq!(signed.hash.len(), 66);
+    }
+
+    #[test]
+    fn test_sign_typed() {
+        let keypair = EthereumKeyPair::from_secret(&"46".repeat(32)).unwrap();
+        for (kind, prefix) in [(TxType::Eip2930, "0x01"), (TxType::Eip1559, "0x02")] {
+            let mut tx = transfer(kind);
+            tx.access_list = vec![AccessListItem {
+                address: "0x3535353535353535353535353535353535353535".to_string(),
+                storage_keys: vec![format!("0x{}", "00".repeat(32))],
+            }];
+            let signed = tx.sign(&keypair).unwrap();
+            assert!(signed.raw.starts_with(prefix), "{kind:?}");
+
+            // y parity, r and s close the list
+            let raw = hex::decode(&signed.raw[2..]).unwrap();
+            let (r, s) = (&raw[raw.len() - 66..raw.len() - 33], &raw[raw.len() - 32..]);
+            let signature = Signature::parse_standard_slice(&[&r[1..], s].concat()).unwrap();
+            let recovery_id = RecoveryId::parse(raw[raw.len() - 67]).unwrap();
+            let digest = Message::parse(&tx.signing_hash().unwrap());
+            let sender = libsecp256k1::recover(&digest, &signature, &recovery_id).unwrap();
+            assert_eq!(to_checksum_address(&sender), keypair.address());
+        }
+    }
+
+    #[test]
+    fn test_invalid_transaction() {
+        let keypair = EthereumKeyPair::new();
+        let mut tx = transfer(TxType::Eip1559);
+        tx.max_fee_per_gas = None;
+        assert!(tx.sign(&keypair).is_err());
+
+        let mut tx = transfer(TxType::Legacy);
+        tx.to = Some("0x1234".to_string());
+        assert!(tx.sign(&keypair).is_err());
+
+        let mut tx = transfer(TxType::Legacy);
+        tx.chain_id = Quantity(u128::MAX / 2);
+        assert!(matches!(tx.sign(&keypair), Err(DatabaseError::TransactionError(_))));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let tx: EthTransaction = serde_json::from_str(
+            r#"{"chainId": 1, "nonce": "0x9", "gasLimit": 21000, "maxFeePerGas": "30000000000",
+                "maxPriorityFeePerGas": "0x3b9aca00", "value": "1000000000000000000"}"#,
+        )
+        .unwrap();
+        assert_eq!(tx.kind, TxType::Eip1559);
+        assert_eq!(tx.nonce, Quantity(9));
+        assert_eq!(tx.max_priority_fee_per_gas, Some(Quantity(1_000_000_000)));
+        assert_eq!(tx.value, Quantity(1_000_000_000_000_000_000));
+        assert!(tx.to.is_none());
+    }
+}
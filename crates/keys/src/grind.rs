@@ -0,0 +1,251 @@
+//! Raw key grinding for vanity searches.
+//!
+//! A [`Grinder`] turns 32 random bytes straight into the address text of a chain,
+//! in a stack buffer, skipping the keypair strategies and their allocations. Only
+//! the winning secret is turned into a [`KeypairContext`].
+use std::fmt;
+
+#[cfg(feature = "bitcoin")]
+use bech32::{segwit, Hrp};
+#[cfg(feature = "solana")]
+use ed25519_dalek::{PublicKey as Ed25519PublicKey, SecretKey as Ed25519SecretKey};
+#[cfg(any(feature = "ethereum", feature = "bitcoin"))]
+use libsecp256k1::{PublicKey, SecretKey};
+use rand::{rngs::OsRng, RngCore};
+
+#[cfg(feature = "bitcoin")]
+use crate::bitcoin::{hash160, Network};
+#[cfg(feature = "ethereum")]
+use crate::ethereum::{apply_checksum, keccak256};
+#[cfg(feature = "solana")]
+use crate::SecretBytes;
+use crate::{
+    registry::{self, AddressEncoding},
+    Chain, DatabaseError, KeypairContext,
+};
+
+/// The length of the secrets drawn from the RNG.
+pub const SECRET_LEN: usize = 32;
+
+/// The number of secrets drawn from the RNG at once.
+pub const BATCH_SIZE: usize = 64;
+
+/// Enough room for the longest address of any supported chain.
+const ADDRESS_CAPACITY: usize = 128;
+
+/// An address encoded on the stack.
+pub struct AddressBuf {
+    bytes: [u8; ADDRESS_CAPACITY],
+    len: usize,
+}
+
+impl AddressBuf {
+    pub fn as_str(&self) -> &str {
+        // only ever holds ASCII from the encoders
+        std::str::from_utf8(&self.bytes[..self.len]).unwrap_or_default()
+    }
+
+    fn clear(&mut self) {
+        self.len = 0;
+    }
+}
+
+impl Default for AddressBuf {
+    fn default() -> Self {
+        AddressBuf { bytes: [0u8; ADDRESS_CAPACITY], len: 0 }
+    }
+}
+
+impl fmt::Write for AddressBuf {
+    fn write_str(&mut self, s: &str) -> fmt::Result {
+        let end = self.len + s.len();
+        if end > ADDRESS_CAPACITY {
+            return Err(fmt::Error);
+        }
+        self.bytes[self.len..end].copy_from_slice(s.as_bytes());
+        self.len = end;
+        Ok(())
+    }
+}
+
+/// Secrets drawn from the OS RNG a batch at a time.
+pub struct SecretBatch([u8; SECRET_LEN * BATCH_SIZE]);
+
+impl SecretBatch {
+    pub fn refill(&mut self) {
+        OsRng.fill_bytes(&mut self.0);
+    }
+
+    pub fn iter(&self) -> impl Iterator<Item = &[u8; SECRET_LEN]> {
+        self.0.chunks_exact(SECRET_LEN).filter_map(|secret| secret.try_into().ok())
+    }
+}
+
+impl Default for SecretBatch {
+    fn default() -> Self {
+        SecretBatch([0u8; SECRET_LEN * BATCH_SIZE])
+    }
+}
+
+/// Encodes the address of a raw secret, in the format keygen produces for the chain.
+#[derive(Debug, Clone, Copy)]
+pub enum Grinder {
+    /// base58 ed25519 public keys
+    #[cfg(feature = "solana")]
+    Solana,
+    /// EIP-55 checksummed keccak256 addresses
+    #[cfg(feature = "ethereum")]
+    Ethereum,
+    /// bech32 P2WPKH addresses
+    #[cfg(feature = "bitcoin")]
+    Segwit(Hrp),
+    /// base58check P2PKH addresses with the version byte
+    #[cfg(feature = "bitcoin")]
+    Base58Check(u8),
+}
+
+impl Grinder {
+    pub fn from_chain(chain: Chain) -> Result<Self, DatabaseError> {
+        #[cfg(feature = "bitcoin")]
+        let network = || Network::from_chain(chain).ok_or(DatabaseError::UnsupportedChain(chain));
+        match registry::get(chain)?.encoding {
+            #[cfg(feature = "solana")]
+            AddressEncoding::Base58 => Ok(Grinder::Solana),
+            #[cfg(feature = "ethereum")]
+            AddressEncoding::Eip55 => Ok(Grinder::Ethereum),
+            #[cfg(feature = "bitcoin")]
+            AddressEncoding::Bech32 => {
+                let hrp = network()?.hrp.ok_or(DatabaseError::UnsupportedChain(chain))?;
+                Hrp::parse(hrp)
+                    .map(Grinder::Segwit)
+                    .map_err(|e| DatabaseError::SecretError(e.to_string()))
+            }
+            #[cfg(feature = "bitcoin")]
+            AddressEncoding::Base58Check => Ok(Grinder::Base58Check(network()?.pubkey_hash)),
+            // encodings of chains built without their feature
+            #[allow(unreachable_patterns)]
+            _ => Err(DatabaseError::UnsupportedChain(chain)),
+        }
+    }
+
+    /// Encode the address of the secret into the buffer, `false` if the secret is
+    /// not a valid key.
+    pub fn encode(&self, secret: &[u8; SECRET_LEN], address: &mut AddressBuf) -> bool {
+        address.clear();
+        match self {
+            #[cfg(feature = "solana")]
+            Grinder::Solana => {
+                let Ok(secret) = Ed25519SecretKey::from_bytes(secret) else {
+                    return false;
+                };
+                let public = Ed25519PublicKey::from(&secret);
+                encode_base58(bs58::encode(public.as_bytes()), address)
+            }
+            #[cfg(feature = "ethereum")]
+            Grinder::Ethereum => {
+                let Some(public) = secp256k1_public(secret) else {
+                    return false;
+                };
+                // skip the 0x04 prefix of the uncompressed encoding
+                let hash = keccak256(&public.serialize()[1..]);
+                address.bytes[..2].copy_from_slice(b"0x");
+                let hex = &mut address.bytes[2..42];
+                if hex::encode_to_slice(&hash[12..], hex).is_err() {
+                    return false;
+                }
+                apply_checksum(hex);
+                address.len = 42;
+                true
+            }
+            #[cfg(feature = "bitcoin")]
+            Grinder::Segwit(hrp) => {
+                let Some(public) = secp256k1_public(secret) else {
+                    return false;
+                };
+                let program = hash160(&public.serialize_compressed());
+                segwit::encode_lower_to_fmt_unchecked(address, *hrp, segwit::VERSION_0, &program)
+                    .is_ok()
+            }
+            #[cfg(feature = "bitcoin")]
+            Grinder::Base58Check(version) => {
+                let Some(public) = secp256k1_public(secret) else {
+                    return false;
+                };
+                let hash = hash160(&public.serialize_compressed());
+                encode_base58(bs58::encode(hash).with_check_version(*version), address)
+            }
+        }
+    }
+
+    /// Build the keypair of a secret the grinder accepted.
+    pub fn context(
+        &self,
+        chain: Chain,
+        secret: &[u8; SECRET_LEN],
+    ) -> Result<KeypairContext, DatabaseError> {
+        match self {
+            // solana keypairs are stored as the secret followed by the public key
+            #[cfg(feature = "solana")]
+            Grinder::Solana => {
+                let secret = Ed25519SecretKey::from_bytes(secret)
+                    .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+                let mut bytes = SecretBytes::new(Vec::with_capacity(64));
+                bytes.expose_mut().extend_from_slice(secret.as_bytes());
+                bytes.expose_mut().extend_from_slice(Ed25519PublicKey::from(&secret).as_bytes());
+                KeypairContext::from_bytes(chain, bytes.as_slice())
+            }
+            #[allow(unreachable_patterns)]
+            _ => KeypairContext::from_bytes(chain, secret),
+        }
+    }
+}
+
+#[cfg(any(feature = "ethereum", feature = "bitcoin"))]
+fn secp256k1_public(secret: &[u8; SECRET_LEN]) -> Option<PublicKey> {
+    SecretKey::parse(secret).ok().map(|secret| PublicKey::from_secret_key(&secret))
+}
+
+#[cfg(any(feature = "solana", feature = "bitcoin"))]
+fn encode_base58<I: AsRef<[u8]>>(
+    encoder: bs58::encode::EncodeBuilder<'_, I>,
+    address: &mut AddressBuf,
+) -> bool {
+    match encoder.onto(&mut address.bytes[..]) {
+        Ok(len) => {
+            address.len = len;
+            true
+        }
+        Err(_) => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_grinder_matches_strategies() {
+        let mut batch = SecretBatch::default();
+        batch.refill();
+
+        for chain in crate::registry::chains().iter().map(|spec| spec.chain) {
+            let grinder = Grinder::from_chain(chain).unwrap();
+            let mut address = AddressBuf::default();
+            for secret in batch.iter().take(4) {
+                assert!(grinder.encode(secret, &mut address));
+                let context = grinder.context(chain, secret).unwrap();
+                assert_eq!(address.as_str(), context.keypair().address(), "{chain:?}");
+            }
+        }
+        assert!(Grinder::from_chain(Chain::Unknown).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_invalid_secp256k1_secret() {
+        let mut address = AddressBuf::default();
+        assert!(!Grinder::Ethereum.encode(&[0u8; SECRET_LEN], &mut address));
+        assert!(!Grinder::Ethereum.encode(&[0xffu8; SECRET_LEN], &mut address));
+    }
+}
@@ -0,0 +1,203 @@
+//! BIP-39 mnemonics and hierarchical deterministic key derivation.
+//!
+//! ed25519 chains derive with [SLIP-10](https://github.com/satoshilabs/slips/blob/master/slip-0010.md),
+//! which only supports hardened indexes, secp256k1 chains derive with
+//! [BIP-32](https://github.com/bitcoin/bips/blob/master/bip-0032.mediawiki).
+use std::str::FromStr;
+
+use bip39::Mnemonic;
+use derivation_path::{ChildIndex, DerivationPath};
+use ed25519_dalek_bip32::ExtendedSecretKey;
+use hmac::{Hmac, Mac};
+use libsecp256k1::{PublicKey, SecretKey};
+use sha2::Sha512;
+
+use crate::{registry, Chain, DatabaseError, SecretBytes};
+
+type HmacSha512 = Hmac<Sha512>;
+
+/// The placeholder replaced by the child index in a derivation path template.
+pub const INDEX_PLACEHOLDER: &str = "{index}";
+
+/// The elliptic curve a chain signs with.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, strum::Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum Curve {
+    Ed25519,
+    Secp256k1,
+}
+
+impl Curve {
+    pub fn from_chain(chain: Chain) -> Result<Self, DatabaseError> {
+        Ok(registry::get(chain)?.curve)
+    }
+}
+
+/// The default derivation path template of a chain, `{index}` is the child index.
+///
+/// Solana follows Phantom and `solana-keygen` (`m/44'/501'/n'/0'`), Bitcoin-family chains
+/// use BIP-84 native segwit paths except Dogecoin which has no segwit.
+pub fn default_path(chain: Chain) -> Result<&'static str, DatabaseError> {
+    match chain {
+        Chain::Solana => Ok("m/44'/501'/{index}'/0'"),
+        Chain::Ethereum => Ok("m/44'/60'/0'/0/{index}"),
+        Chain::Bitcoin => Ok("m/84'/0'/0'/0/{index}"),
+        Chain::BitcoinTestnet | Chain::BitcoinSignet | Chain::BitcoinRegtest => {
+            Ok("m/84'/1'/0'/0/{index}")
+        }
+        Chain::Litecoin => Ok("m/84'/2'/0'/0/{index}"),
+        Chain::Dogecoin => Ok("m/44'/3'/0'/0/{index}"),
+        Chain::Unknown => Err(DatabaseError::UnsupportedChain(chain)),
+    }
+}
+
+/// Fill the child index into a derivation path template.
+pub fn path_at(template: &str, index: u32) -> String {
+    template.replace(INDEX_PLACEHOLDER, index.to_string().as_str())
+}
+
+/// Parse a derivation path, both `'` and `h` mark hardened indexes.
+pub fn parse_path(path: &str) -> Result<DerivationPath, DatabaseError> {
+    let normalized = path.trim().replace(['h', 'H'], "'");
+    DerivationPath::from_str(normalized.as_str())
+        .map_err(|e| DatabaseError::SecretError(format!("invalid derivation path `{path}`: {e}")))
+}
+
+/// Generate a new english mnemonic with the given number of words.
+pub fn generate_mnemonic(word_count: usize) -> Result<String, DatabaseError> {
+    let mnemonic =
+        Mnemonic::generate(word_count).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+    Ok(mnemonic.to_string())
+}
+
+/// Validate an english mnemonic and stretch it into the 64 bytes BIP-39 seed.
+pub fn mnemonic_to_seed(
+    phrase: &str,
+    passphrase: Option<&str>,
+) -> Result<SecretBytes, DatabaseError> {
+    let mnemonic =
+        Mnemonic::parse(phrase).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+    Ok(SecretBytes::from_slice(&mnemonic.to_seed(passphrase.unwrap_or_default())))
+}
+
+/// SLIP-10 ed25519 derivation, returns the secret key followed by the public key.
+pub fn derive_ed25519(seed: &[u8], path: &str) -> Result<[u8; 64], DatabaseError> {
+    let path = parse_path(path)?;
+    let extended = ExtendedSecretKey::from_seed(seed)
+        .and_then(|master| master.derive(&path))
+        .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+
+    let mut bytes = [0u8; 64];
+    bytes[..32].copy_from_slice(extended.secret_key.as_bytes());
+    bytes[32..].copy_from_slice(extended.public_key().as_bytes());
+    Ok(bytes)
+}
+
+/// BIP-32 secp256k1 derivation of the private key at the path.
+pub fn derive_secp256k1(seed: &[u8], path: &str) -> Result<SecretKey, DatabaseError> {
+    let path = parse_path(path)?;
+    let (mut secret, mut chain_code) = split(hmac_sha512(b"Bitcoin seed", &[seed]))?;
+
+    for index in path.path() {
+        let data = match index {
+            ChildIndex::Hardened(_) => {
+                let mut data = vec![0u8];
+                data.extend_from_slice(&secret.serialize());
+                data
+            }
+            ChildIndex::Normal(_) => {
+                PublicKey::from_secret_key(&secret).serialize_compressed().to_vec()
+            }
+        };
+        let (tweak, next_chain_code) =
+            split(hmac_sha512(&chain_code, &[&data, &index.to_bits().to_be_bytes()]))?;
+        secret.tweak_add_assign(&tweak).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        chain_code = next_chain_code;
+    }
+    Ok(secret)
+}
+
+/// Derive the secret bytes at the path in the form the chain's keypair recovers from.
+pub fn derive_secret(chain: Chain, seed: &[u8], path: &str) -> Result<SecretBytes, DatabaseError> {
+    match Curve::from_chain(chain)? {
+        Curve::Ed25519 => Ok(SecretBytes::from_slice(&derive_ed25519(seed, path)?)),
+        Curve::Secp256k1 => Ok(SecretBytes::from_slice(&derive_secp256k1(seed, path)?.serialize())),
+    }
+}
+
+fn hmac_sha512(key: &[u8], data: &[&[u8]]) -> [u8; 64] {
+    let mut mac = HmacSha512::new_from_slice(key).expect("HMAC accepts keys of any size");
+    for chunk in data {
+        mac.update(chunk);
+    }
+    mac.finalize().into_bytes().into()
+}
+
+/// Split an HMAC output into the secret key and the chain code.
+fn split(bytes: [u8; 64]) -> Result<(SecretKey, [u8; 32]), DatabaseError> {
+    let secret = SecretKey::parse_slice(&bytes[..32])
+        .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+    let mut chain_code = [0u8; 32];
+    chain_code.copy_from_slice(&bytes[32..]);
+    Ok((secret, chain_code))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    const PHRASE: &str =
+        "abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon abandon about";
+
+    #[test]
+    fn test_mnemonic_to_seed() {
+        // test vector from https://github.com/trezor/python-mnemonic/blob/master/vectors.json
+        let seed = mnemonic_to_seed(PHRASE, Some("TREZOR")).unwrap();
+        assert_eq!(
+            hex::encode(seed.as_slice()),
+            "c55257c360c07c72029aebc1b53c05ed0362ada38ead3e3e9efa3708e53495531f09a6987599d18264c1e1c92f2cf141630c7a3c4ab7c81b2f001698e7463b04"
+        );
+
+        assert!(mnemonic_to_seed("abandon abandon abandon", None).is_err());
+        assert!(mnemonic_to_seed(generate_mnemonic(24).unwrap().as_str(), None).is_ok());
+    }
+
+    #[test]
+    fn test_derive_secp256k1() {
+        // test vector 1 from BIP-32
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let master = derive_secp256k1(&seed, "m").unwrap();
+        assert_eq!(
+            hex::encode(master.serialize()),
+            "e8f32e723decf4051aefac8e2c93c9c5b214313817cdb01a1494b917c8436b35"
+        );
+        let child = derive_secp256k1(&seed, "m/0'/1").unwrap();
+        assert_eq!(
+            hex::encode(child.serialize()),
+            "3c6cb8d0f6a264c91ea8b5030fadaa8e538b020f0a387421a12de9319dc93368"
+        );
+        assert_eq!(derive_secp256k1(&seed, "m/0h/1").unwrap(), child);
+    }
+
+    #[test]
+    fn test_derive_ed25519() {
+        // test vector 1 from SLIP-10
+        let seed = hex::decode("000102030405060708090a0b0c0d0e0f").unwrap();
+        let child = derive_ed25519(&seed, "m/0'").unwrap();
+        assert_eq!(
+            hex::encode(&child[..32]),
+            "68e0fe46dfb67e368c75379acec591dad19df3cde26e63b93a8e704f1dade7a3"
+        );
+        // ed25519 has no public derivation
+        assert!(derive_ed25519(&seed, "m/0").is_err());
+    }
+
+    #[test]
+    fn test_default_path() {
+        let path = default_path(Chain::Solana).unwrap();
+        assert_eq!(path_at(path, 3), "m/44'/501'/3'/0'");
+        assert!(default_path(Chain::Unknown).is_err());
+        assert!(parse_path("44'/0'").is_err());
+    }
+}
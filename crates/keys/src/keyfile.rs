@@ -0,0 +1,141 @@
+//! Key files of other wallets, for `anita key import` and `anita key export`.
+//!
+//! Every format carries the bytes of [`KeypairStrategy::to_vec`], the 64 bytes keypair of
+//! Solana or the 32 bytes secret of the secp256k1 chains.
+use crate::{
+    keystore::Keystore, Chain, DatabaseError, KeypairContext, KeypairStrategy, SecretBytes,
+};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, clap::ValueEnum)]
+pub enum KeyFormat {
+    /// A JSON byte array, like `solana-keygen` writes
+    Json,
+    /// A base58 string, like Phantom exports
+    Base58,
+    /// A hex string, with or without `0x`
+    Hex,
+    /// An Ethereum V3 keystore, encrypted with a password
+    Keystore,
+}
+
+impl KeyFormat {
+    /// Whether the secret is protected in this format.
+    pub fn is_encrypted(&self) -> bool {
+        matches!(self, KeyFormat::Keystore)
+    }
+}
+
+/// Read a keypair of the chain from the contents of a key file.
+pub fn import(
+    chain: Chain,
+    format: KeyFormat,
+    contents: &str,
+    password: Option<&str>,
+) -> Result<KeypairContext, DatabaseError> {
+    let contents = contents.trim();
+    let bytes = match format {
+        KeyFormat::Json => serde_json::from_str::<SecretBytes>(contents)
+            .map_err(|e| DatabaseError::SecretError(format!("not a JSON byte array: {e}")))?,
+        KeyFormat::Base58 => bs58::decode(contents)
+            .into_vec()
+            .map(SecretBytes::new)
+            .map_err(|e| DatabaseError::SecretError(e.to_string()))?,
+        KeyFormat::Hex => SecretBytes::new(hex::decode(contents.trim_start_matches("0x"))?),
+        KeyFormat::Keystore => {
+            let keystore: Keystore = serde_json::from_str(contents)
+                .map_err(|e| DatabaseError::SecretError(format!("not a keystore: {e}")))?;
+            keystore.decrypt(password.ok_or_else(missing_password)?)?
+        }
+    };
+    KeypairContext::from_bytes(chain, bytes.as_slice())
+}
+
+/// Write a keypair in the format, the password is required by encrypted formats.
+pub fn export(
+    keypair: &dyn KeypairStrategy,
+    format: KeyFormat,
+    password: Option<&str>,
+) -> Result<String, DatabaseError> {
+    let bytes = keypair.to_vec();
+    match format {
+        KeyFormat::Json => serde_json::to_string(bytes.expose())
+            .map_err(|e| DatabaseError::SecretError(e.to_string())),
+        KeyFormat::Base58 => Ok(bs58::encode(bytes.as_slice()).into_string()),
+        KeyFormat::Hex => Ok(hex::encode(bytes.as_slice())),
+        KeyFormat::Keystore => {
+            // geth writes the address lowercase without `0x`
+            let address = match keypair.chain() {
+                Chain::Ethereum => keypair.address().trim_start_matches("0x").to_lowercase(),
+                _ => keypair.address(),
+            };
+            let keystore = Keystore::encrypt(
+                bytes.as_slice(),
+                password.ok_or_else(missing_password)?,
+                Some(address),
+            )?;
+            serde_json::to_string_pretty(&keystore)
+                .map_err(|e| DatabaseError::SecretError(e.to_string()))
+        }
+    }
+}
+
+fn missing_password() -> DatabaseError {
+    DatabaseError::SecretError("a password is required for keystore files".to_string())
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_plaintext_formats() {
+        for chain in crate::registry::chains().iter().map(|spec| spec.chain) {
+            let context = KeypairContext::from_chain(chain).unwrap();
+            let keypair = context.keypair();
+            for format in [KeyFormat::Json, KeyFormat::Base58, KeyFormat::Hex] {
+                let contents = export(keypair, format, None).unwrap();
+                let imported = import(chain, format, &contents, None).unwrap();
+                assert_eq!(imported.keypair().address(), keypair.address(), "{format:?}");
+            }
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_solana_cli_file() {
+        let context = KeypairContext::from_chain(Chain::Solana).unwrap();
+        let bytes = context.keypair().to_vec();
+        let contents = format!("{:?}\n", bytes.expose()).replace(' ', "");
+
+        let imported = import(Chain::Solana, KeyFormat::Json, &contents, None).unwrap();
+        assert_eq!(imported.keypair().pubkey(), context.keypair().pubkey());
+        // the base58 of the 64 bytes is the secret Solana wallets show
+        let base58 = context.keypair().secret();
+        let imported = import(Chain::Solana, KeyFormat::Base58, base58.as_str(), None).unwrap();
+        assert_eq!(imported.keypair().pubkey(), context.keypair().pubkey());
+
+        assert!(import(Chain::Solana, KeyFormat::Json, "[1, 2, 3]", None).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_keystore() {
+        let context = KeypairContext::from_chain(Chain::Ethereum).unwrap();
+        let keypair = context.keypair();
+        let keystore = Keystore::encrypt_with_cost(
+            keypair.to_vec().as_slice(),
+            "hunter2",
+            Some(keypair.address()),
+            10,
+        )
+        .unwrap();
+        let contents = serde_json::to_string(&keystore).unwrap();
+
+        let imported = import(Chain::Ethereum, KeyFormat::Keystore, &contents, Some("hunter2"));
+        assert_eq!(imported.unwrap().keypair().address(), keypair.address());
+        assert!(import(Chain::Ethereum, KeyFormat::Keystore, &contents, Some("nope")).is_err());
+        assert!(import(Chain::Ethereum, KeyFormat::Keystore, &contents, None).is_err());
+        assert!(export(keypair, KeyFormat::Keystore, None).is_err());
+    }
+}
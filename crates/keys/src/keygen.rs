@@ -1,73 +1,498 @@
-use rayon::prelude::*;
-use std::sync::{
-    atomic::{AtomicBool, Ordering},
-    mpsc, Arc,
+use rayon::{ThreadPool, ThreadPoolBuilder};
+use std::{
+    fmt,
+    sync::{
+        atomic::{AtomicBool, AtomicU64, AtomicUsize, Ordering},
+        mpsc, Arc,
+    },
+    time::{Duration, Instant},
 };
 
-use r_storage::prelude::Chain;
+use r_storage::prelude::DatabaseError;
 use r_tracing::tracing::info;
 
-use crate::KeypairContext;
+use crate::{
+    grind::{AddressBuf, Grinder, SecretBatch, BATCH_SIZE},
+    matcher::{Matcher, Pattern},
+    KeypairContext,
+};
 
-/// key blockchain generator.
+/// key blockchain generator, returns the keypair and the pattern its address satisfies.
 ///
 /// # Examples
 ///
 /// ```
-/// use r_keys::{Chain, KeypairContext, keygen::keygen};
+/// use r_keys::{CaseMode, Chain, Matcher, keygen::keygen};
+/// # #[cfg(feature = "solana")] {
 /// let num_threads = 4;
-/// let target_suffix = "p";
-/// let context = keygen(num_threads, target_suffix, Chain::Solana);
+/// let matcher = Matcher::parse(Chain::Solana, &["p"], CaseMode::Sensitive).unwrap();
+/// let (context, pattern) = keygen(num_threads, &matcher).unwrap();
 /// let keypair = context.keypair();
-/// assert!(keypair.pubkey().ends_with(target_suffix));
+/// assert!(keypair.pubkey().ends_with("p"));
+/// assert_eq!(pattern.to_string(), "*p");
+/// # }
 /// ```
-pub fn keygen(num_threads: u8, target_suffix: &str, chain: Chain) -> KeypairContext {
-    info!(
-        "Searching for addresses ending with {} and using {} threads",
-        target_suffix, num_threads
-    );
-    let found = Arc::new(AtomicBool::new(false));
-    let target_suffix = target_suffix.to_string();
-    let (sender, receiver) = mpsc::channel();
-
-    (0..num_threads).into_par_iter().for_each(|_| {
-        let sender = sender.clone();
-
-        while !found.load(Ordering::Relaxed) {
-            let context = KeypairContext::from_chain(chain.clone());
-            let keypair = context.keypair();
-            let pubkey = keypair.pubkey();
-            if pubkey.ends_with(&target_suffix) {
-                if sender.send(context).is_ok() {
-                    found
-                        .compare_exchange(false, true, Ordering::SeqCst, Ordering::Relaxed)
-                        .expect("Try to exchange the found failed");
-                    break;
+pub fn keygen(
+    num_threads: u8,
+    matcher: &Matcher,
+) -> Result<(KeypairContext, Pattern), DatabaseError> {
+    Search::start(num_threads, matcher.clone(), None)?.wait()
+}
+
+/// Stops a running search from any thread.
+#[derive(Debug, Clone, Default)]
+pub struct CancellationToken(Arc<AtomicBool>);
+
+impl CancellationToken {
+    pub fn cancel(&self) {
+        self.0.store(true, Ordering::Relaxed);
+    }
+
+    pub fn is_cancelled(&self) -> bool {
+        self.0.load(Ordering::Relaxed)
+    }
+}
+
+/// A snapshot of the progress of a search.
+#[derive(Debug, Clone, Copy)]
+pub struct SearchStats {
+    pub attempts: u64,
+    pub elapsed: Duration,
+    /// The attempts a match needs on average, `None` if it cannot be estimated.
+    pub expected_attempts: Option<f64>,
+}
+
+impl SearchStats {
+    pub fn attempts_per_second(&self) -> f64 {
+        let elapsed = self.elapsed.as_secs_f64();
+        if elapsed > 0.0 {
+            self.attempts as f64 / elapsed
+        } else {
+            0.0
+        }
+    }
+
+    /// The time a match takes on average at the current rate.
+    pub fn expected_time(&self) -> Option<Duration> {
+        let rate = self.attempts_per_second();
+        match self.expected_attempts {
+            Some(expected) if rate > 0.0 => Duration::try_from_secs_f64(expected / rate).ok(),
+            _ => None,
+        }
+    }
+}
+
+impl fmt::Display for SearchStats {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} attempts, {:.0}/s, {:.1}s elapsed",
+            self.attempts,
+            self.attempts_per_second(),
+            self.elapsed.as_secs_f64()
+        )?;
+        match self.expected_time() {
+            Some(expected) => write!(f, ", ~{:.1}s expected", expected.as_secs_f64()),
+            None => write!(f, ", expected time unknown"),
+        }
+    }
+}
+
+/// The number of matches still wanted, per slot of patterns.
+struct Quotas {
+    remaining: Vec<AtomicUsize>,
+    /// The slot of each pattern.
+    slots: Vec<usize>,
+}
+
+impl Quotas {
+    /// Take one match of the pattern, `false` if its slot is already full.
+    fn claim(&self, pattern: usize) -> bool {
+        self.remaining[self.slots[pattern]]
+            .fetch_update(Ordering::SeqCst, Ordering::SeqCst, |n| n.checked_sub(1))
+            .is_ok()
+    }
+
+    fn is_met(&self) -> bool {
+        self.remaining.iter().all(|n| n.load(Ordering::SeqCst) == 0)
+    }
+}
+
+/// A match, with the index of its pattern in the matcher.
+type Found = (KeypairContext, usize, Pattern);
+
+/// The threads grinding keys and reporting the matches their quotas accept.
+struct Workers {
+    token: CancellationToken,
+    timed_out: Arc<AtomicBool>,
+    timeout: Option<Duration>,
+    attempts: Arc<AtomicU64>,
+    started: Instant,
+    expected_attempts: Option<f64>,
+    receiver: mpsc::Receiver<Found>,
+    _pool: ThreadPool,
+}
+
+impl Workers {
+    fn start(
+        num_threads: u8,
+        matcher: Matcher,
+        quotas: Quotas,
+        expected_attempts: Option<f64>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, DatabaseError> {
+        let chain = matcher.chain();
+        // fail fast if the chain has no keypair strategy
+        KeypairContext::create_keypair(chain)?;
+        let grinder = Grinder::from_chain(chain)?;
+
+        info!(
+            "Searching for {} addresses matching {} and using {} threads",
+            chain,
+            matcher.patterns().map(|p| p.to_string()).collect::<Vec<_>>().join(", "),
+            num_threads
+        );
+        let pool = ThreadPoolBuilder::new()
+            .num_threads(num_threads as usize)
+            .thread_name(|i| format!("keygen-{i}"))
+            .build()
+            .map_err(|e| DatabaseError::SearchError(e.to_string()))?;
+
+        let started = Instant::now();
+        let deadline = timeout.map(|timeout| started + timeout);
+        let matcher = Arc::new(matcher);
+        let quotas = Arc::new(quotas);
+        let token = CancellationToken::default();
+        let timed_out = Arc::new(AtomicBool::new(false));
+        let attempts = Arc::new(AtomicU64::new(0));
+        let (sender, receiver) = mpsc::channel();
+
+        for _ in 0..pool.current_num_threads() {
+            let matcher = matcher.clone();
+            let quotas = quotas.clone();
+            let token = token.clone();
+            let timed_out = timed_out.clone();
+            let attempts = attempts.clone();
+            let sender = sender.clone();
+
+            pool.spawn(move || {
+                let mut batch = SecretBatch::default();
+                let mut address = AddressBuf::default();
+
+                while !token.is_cancelled() && !quotas.is_met() {
+                    if deadline.is_some_and(|deadline| Instant::now() >= deadline) {
+                        timed_out.store(true, Ordering::Relaxed);
+                        token.cancel();
+                        break;
+                    }
+                    batch.refill();
+                    for secret in batch.iter() {
+                        if !grinder.encode(secret, &mut address) {
+                            continue;
+                        }
+                        // a key fills the first target with room left, it is built before a
+                        // slot is claimed so a claimed slot is never lost
+                        let mut matches = matcher.matches(address.as_str()).peekable();
+                        if matches.peek().is_none() {
+                            continue;
+                        }
+                        let Ok(context) = grinder.context(chain, secret) else {
+                            continue;
+                        };
+                        if let Some((i, pattern)) = matches.find(|(i, _)| quotas.claim(*i)) {
+                            let _ = sender.send((context, i, pattern.clone()));
+                        }
+                    }
+                    // counted per batch to keep the counter off the hot path
+                    attempts.fetch_add(BATCH_SIZE as u64, Ordering::Relaxed);
                 }
+            });
+        }
+
+        Ok(Workers {
+            token,
+            timed_out,
+            timeout,
+            attempts,
+            started,
+            expected_attempts,
+            receiver,
+            _pool: pool,
+        })
+    }
+
+    fn stats(&self) -> SearchStats {
+        SearchStats {
+            attempts: self.attempts.load(Ordering::Relaxed),
+            elapsed: self.started.elapsed(),
+            expected_attempts: self.expected_attempts,
+        }
+    }
+
+    fn try_recv(&self) -> Result<Option<Found>, DatabaseError> {
+        match self.receiver.try_recv() {
+            Ok(found) => Ok(Some(found)),
+            Err(mpsc::TryRecvError::Empty) => Ok(None),
+            Err(mpsc::TryRecvError::Disconnected) => Err(self.stopped()),
+        }
+    }
+
+    /// Why the workers stopped before their quotas were met.
+    fn stopped(&self) -> DatabaseError {
+        match self.timeout {
+            Some(timeout) if self.timed_out.load(Ordering::Relaxed) => {
+                DatabaseError::SearchTimeout(timeout)
             }
+            _ if self.token.is_cancelled() => DatabaseError::SearchCancelled,
+            _ => DatabaseError::SearchError("every search thread stopped".to_string()),
+        }
+    }
+}
+
+impl Drop for Workers {
+    fn drop(&mut self) {
+        self.token.cancel();
+    }
+}
+
+/// A vanity search for the first address matching any pattern, running in the background.
+///
+/// Dropping the handle cancels the search.
+pub struct Search(Workers);
+
+impl Search {
+    /// Start searching on `num_threads` threads, or every core if `0`, and stop after
+    /// the timeout if any.
+    pub fn start(
+        num_threads: u8,
+        matcher: Matcher,
+        timeout: Option<Duration>,
+    ) -> Result<Self, DatabaseError> {
+        // a single slot shared by every pattern
+        let quotas = Quotas {
+            remaining: vec![AtomicUsize::new(1)],
+            slots: vec![0; matcher.patterns().count()],
+        };
+        let expected_attempts = matcher.expected_attempts();
+        Workers::start(num_threads, matcher, quotas, expected_attempts, timeout).map(Search)
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.0.stats()
+    }
+
+    /// A token to cancel the search from elsewhere.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.0.token.clone()
+    }
+
+    pub fn cancel(&self) {
+        self.0.token.cancel();
+    }
+
+    /// Poll for the match without blocking.
+    pub fn try_result(&self) -> Result<Option<(KeypairContext, Pattern)>, DatabaseError> {
+        Ok(self.0.try_recv()?.map(|(context, _, pattern)| (context, pattern)))
+    }
+
+    /// Block until the match is found, or the search is cancelled or timed out.
+    pub fn wait(self) -> Result<(KeypairContext, Pattern), DatabaseError> {
+        let (context, _, pattern) = self.0.receiver.recv().map_err(|_| self.0.stopped())?;
+        Ok((context, pattern))
+    }
+}
+
+/// A vanity search filling a quota of matches for each pattern in one pass.
+///
+/// Dropping the handle cancels the search.
+pub struct Fill {
+    workers: Workers,
+    patterns: Vec<Pattern>,
+    wanted: Vec<usize>,
+    found: Vec<usize>,
+}
+
+impl Fill {
+    /// Start searching for `quotas[i]` matches of the i-th pattern of the matcher.
+    pub fn start(
+        num_threads: u8,
+        matcher: Matcher,
+        quotas: Vec<usize>,
+        timeout: Option<Duration>,
+    ) -> Result<Self, DatabaseError> {
+        let patterns = matcher.patterns().cloned().collect::<Vec<_>>();
+        if quotas.len() != patterns.len() {
+            return Err(DatabaseError::InvalidPattern(format!(
+                "{} quotas for {} patterns",
+                quotas.len(),
+                patterns.len()
+            )));
         }
-    });
 
-    // Close the sender to signal that no more keys will be sent
-    drop(sender);
+        // the rarest target dominates, the others fill along the way
+        let expected_attempts = patterns
+            .iter()
+            .zip(quotas.iter())
+            .map(|(pattern, quota)| matcher.pattern_probability(pattern).map(|p| *quota as f64 / p))
+            .try_fold(0.0, |max: f64, expected| expected.map(|e| max.max(e)));
+        let slots = (0..patterns.len()).collect();
+        let remaining = quotas.iter().map(|quota| AtomicUsize::new(*quota)).collect();
+        let workers = Workers::start(
+            num_threads,
+            matcher,
+            Quotas { remaining, slots },
+            expected_attempts,
+            timeout,
+        )?;
 
-    // Received the secret
-    receiver.recv().expect("Failed to receive keypair")
+        let found = vec![0; patterns.len()];
+        Ok(Fill { workers, patterns, wanted: quotas, found })
+    }
+
+    pub fn stats(&self) -> SearchStats {
+        self.workers.stats()
+    }
+
+    /// A token to cancel the search from elsewhere.
+    pub fn cancellation_token(&self) -> CancellationToken {
+        self.workers.token.clone()
+    }
+
+    /// The matches still missing for each pattern.
+    pub fn remaining(&self) -> Vec<(Pattern, usize)> {
+        let missing = self.wanted.iter().zip(self.found.iter()).map(|(w, f)| w.saturating_sub(*f));
+        self.patterns.iter().cloned().zip(missing).collect()
+    }
+
+    /// Whether every match has been received.
+    pub fn is_done(&self) -> bool {
+        self.wanted == self.found
+    }
+
+    /// Poll for the next match without blocking, `None` if there is none yet or the
+    /// search is done.
+    pub fn try_next(&mut self) -> Result<Option<(KeypairContext, Pattern)>, DatabaseError> {
+        if self.is_done() {
+            return Ok(None);
+        }
+        let Some((context, i, pattern)) = self.workers.try_recv()? else {
+            return Ok(None);
+        };
+        self.found[i] += 1;
+        Ok(Some((context, pattern)))
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
     use super::*;
+    use crate::{CaseMode, Chain};
 
     #[test]
+    #[cfg(feature = "solana")]
     fn test_keygen() {
         let num_threads = 4;
-        let target_suffix = "p";
-        let context: KeypairContext = keygen(num_threads, target_suffix, Chain::Solana);
+        let matcher = Matcher::parse(Chain::Solana, &["p"], CaseMode::Sensitive).unwrap();
+        let (context, _) = keygen(num_threads, &matcher).unwrap();
         let keypair = context.keypair();
-        println!("secret: {}", context.keypair().secret());
+        println!("secret: {}", context.keypair().secret().as_str());
         println!("pubkey: {}", context.keypair().pubkey());
-        assert!(keypair.pubkey().ends_with(target_suffix));
+        assert!(keypair.pubkey().ends_with('p'));
+    }
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_keygen_ethereum() {
+        let matcher =
+            Matcher::parse(Chain::Ethereum, &["a*", "*a"], CaseMode::Insensitive).unwrap();
+        let (context, pattern) = keygen(2, &matcher).unwrap();
+        assert_eq!(context.chain(), Chain::Ethereum);
+        let address = context.keypair().address().to_ascii_lowercase();
+        match pattern {
+            Pattern::Prefix(_) => assert!(address.starts_with("0xa")),
+            _ => assert!(address.ends_with('a')),
+        }
+    }
+
+    #[test]
+    #[cfg(feature = "bitcoin")]
+    fn test_keygen_bitcoin() {
+        let matcher = Matcher::parse(Chain::Bitcoin, &["*q"], CaseMode::Sensitive).unwrap();
+        let (context, _) = keygen(2, &matcher).unwrap();
+        assert_eq!(context.chain(), Chain::Bitcoin);
+        assert!(context.keypair().address().ends_with('q'));
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_search_cancel() {
+        // practically impossible to find
+        let matcher = Matcher::parse(Chain::Solana, &["zzzzzzzzzz"], CaseMode::Sensitive).unwrap();
+        let search = Search::start(2, matcher, None).unwrap();
+        std::thread::sleep(Duration::from_millis(50));
+        assert!(search.try_result().unwrap().is_none());
+
+        let stats = search.stats();
+        assert!(stats.attempts > 0);
+        assert!(stats.expected_time().unwrap() > Duration::from_secs(3600));
+
+        search.cancellation_token().cancel();
+        assert!(matches!(search.wait(), Err(DatabaseError::SearchCancelled)));
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_fill() {
+        let matcher =
+            Matcher::parse(Chain::Solana, &["a*", "*b", "*zzzzzzzzzz"], CaseMode::Sensitive)
+                .unwrap();
+        let mut fill = Fill::start(2, matcher, vec![3, 2, 0], None).unwrap();
+
+        let mut found = vec![];
+        while !fill.is_done() {
+            match fill.try_next().unwrap() {
+                Some((context, pattern)) => found.push((context.keypair().address(), pattern)),
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        assert_eq!(found.len(), 5);
+        for (address, pattern) in found.iter() {
+            match pattern {
+                Pattern::Prefix(_) => assert!(address.starts_with('a')),
+                _ => assert!(address.ends_with('b')),
+            }
+        }
+        assert!(fill.remaining().iter().all(|(_, missing)| *missing == 0));
+        assert!(fill.try_next().unwrap().is_none());
+
+        let matcher = Matcher::parse(Chain::Solana, &["a*"], CaseMode::Sensitive).unwrap();
+        assert!(Fill::start(2, matcher, vec![1, 1], None).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_fill_duplicate_targets() {
+        let matcher = Matcher::parse(Chain::Solana, &["*b", "*b"], CaseMode::Sensitive).unwrap();
+        let mut fill = Fill::start(2, matcher, vec![2, 1], None).unwrap();
+
+        let mut found = 0;
+        while !fill.is_done() {
+            match fill.try_next().unwrap() {
+                Some(_) => found += 1,
+                None => std::thread::sleep(Duration::from_millis(1)),
+            }
+        }
+        assert_eq!(found, 3);
+        assert!(fill.remaining().iter().all(|(_, missing)| *missing == 0));
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_search_timeout() {
+        let matcher = Matcher::parse(Chain::Solana, &["zzzzzzzzzz"], CaseMode::Sensitive).unwrap();
+        let timeout = Duration::from_millis(50);
+        let search = Search::start(2, matcher, Some(timeout)).unwrap();
+        assert!(matches!(search.wait(), Err(DatabaseError::SearchTimeout(t)) if t == timeout));
     }
 }
@@ -0,0 +1,206 @@
+//! Ethereum V3 keystore files (Web3 Secret Storage).
+//!
+//! See <https://ethereum.org/en/developers/docs/data-structures-and-encoding/web3-secret-storage/>.
+use aes::cipher::{KeyIvInit, StreamCipher};
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use sha3::{Digest, Keccak256};
+
+use crate::{DatabaseError, SecretBytes};
+
+type Aes128Ctr = ctr::Ctr128BE<aes::Aes128>;
+
+const CIPHER: &str = "aes-128-ctr";
+const DKLEN: usize = 32;
+
+/// The scrypt cost geth uses for new keystores, N = 2^18.
+pub const SCRYPT_LOG_N: u8 = 18;
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Keystore {
+    pub version: u32,
+    pub id: String,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub address: Option<String>,
+    #[serde(alias = "Crypto")]
+    pub crypto: Crypto,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Crypto {
+    pub cipher: String,
+    pub cipherparams: CipherParams,
+    pub ciphertext: String,
+    pub kdf: String,
+    pub kdfparams: KdfParams,
+    pub mac: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CipherParams {
+    pub iv: String,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(untagged)]
+pub enum KdfParams {
+    Scrypt { dklen: usize, n: u64, r: u32, p: u32, salt: String },
+    Pbkdf2 { dklen: usize, c: u32, prf: String, salt: String },
+}
+
+impl Keystore {
+    /// Encrypt a secret to the password with the standard scrypt cost.
+    pub fn encrypt(
+        secret: &[u8],
+        password: &str,
+        address: Option<String>,
+    ) -> Result<Self, DatabaseError> {
+        Self::encrypt_with_cost(secret, password, address, SCRYPT_LOG_N)
+    }
+
+    /// Encrypt a secret to the password with scrypt N = 2^log_n.
+    pub fn encrypt_with_cost(
+        secret: &[u8],
+        password: &str,
+        address: Option<String>,
+        log_n: u8,
+    ) -> Result<Self, DatabaseError> {
+        let mut salt = [0u8; 32];
+        let mut iv = [0u8; 16];
+        OsRng.fill_bytes(&mut salt);
+        OsRng.fill_bytes(&mut iv);
+
+        let kdfparams =
+            KdfParams::Scrypt { dklen: DKLEN, n: 1 << log_n, r: 8, p: 1, salt: hex::encode(salt) };
+        let key = derive_key(password, &kdfparams)?;
+        let mut ciphertext = secret.to_vec();
+        Aes128Ctr::new(key.as_slice()[..16].into(), &iv.into()).apply_keystream(&mut ciphertext);
+
+        Ok(Keystore {
+            version: 3,
+            id: uuid::Uuid::new_v4().to_string(),
+            address,
+            crypto: Crypto {
+                cipher: CIPHER.to_string(),
+                cipherparams: CipherParams { iv: hex::encode(iv) },
+                ciphertext: hex::encode(&ciphertext),
+                kdf: "scrypt".to_string(),
+                mac: hex::encode(mac(key.as_slice(), &ciphertext)),
+                kdfparams,
+            },
+        })
+    }
+
+    /// Decrypt the secret, failing on a wrong password.
+    pub fn decrypt(&self, password: &str) -> Result<SecretBytes, DatabaseError> {
+        if self.version != 3 || self.crypto.cipher != CIPHER {
+            return Err(invalid(format!(
+                "unsupported keystore version {} with {}",
+                self.version, self.crypto.cipher
+            )));
+        }
+        let key = derive_key(password, &self.crypto.kdfparams)?;
+        let mut secret = SecretBytes::new(hex::decode(&self.crypto.ciphertext)?);
+        // compared in constant time
+        let expected = SecretBytes::from_slice(&mac(key.as_slice(), secret.as_slice()));
+        if SecretBytes::new(hex::decode(&self.crypto.mac)?) != expected {
+            return Err(invalid("wrong password".to_string()));
+        }
+
+        let iv: [u8; 16] = hex::decode(&self.crypto.cipherparams.iv)?
+            .try_into()
+            .map_err(|_| invalid("the iv is not 16 bytes".to_string()))?;
+        Aes128Ctr::new(key.as_slice()[..16].into(), &iv.into())
+            .apply_keystream(secret.expose_mut());
+        Ok(secret)
+    }
+}
+
+fn derive_key(password: &str, params: &KdfParams) -> Result<SecretBytes, DatabaseError> {
+    match params {
+        KdfParams::Scrypt { dklen, n, r, p, salt } => {
+            if !n.is_power_of_two() || *dklen < DKLEN {
+                return Err(invalid(format!("scrypt n {n} with dklen {dklen}")));
+            }
+            let params = scrypt::Params::new(n.trailing_zeros() as u8, *r, *p, *dklen)
+                .map_err(|e| invalid(e.to_string()))?;
+            let mut key = SecretBytes::new(vec![0u8; *dklen]);
+            scrypt::scrypt(password.as_bytes(), &hex::decode(salt)?, &params, key.expose_mut())
+                .map_err(|e| invalid(e.to_string()))?;
+            Ok(key)
+        }
+        KdfParams::Pbkdf2 { dklen, c, prf, salt } => {
+            if prf != "hmac-sha256" || *dklen < DKLEN {
+                return Err(invalid(format!("pbkdf2 {prf} with dklen {dklen}")));
+            }
+            let mut key = SecretBytes::new(vec![0u8; *dklen]);
+            pbkdf2::pbkdf2_hmac::<Sha256>(
+                password.as_bytes(),
+                &hex::decode(salt)?,
+                *c,
+                key.expose_mut(),
+            );
+            Ok(key)
+        }
+    }
+}
+
+/// keccak256 of the second half of the derived key and the ciphertext.
+fn mac(key: &[u8], ciphertext: &[u8]) -> [u8; 32] {
+    let mut hasher = Keccak256::new();
+    hasher.update(&key[16..32]);
+    hasher.update(ciphertext);
+    hasher.finalize().into()
+}
+
+fn invalid(message: String) -> DatabaseError {
+    DatabaseError::SecretError(format!("keystore: {message}"))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    // the PBKDF2 test vector of the Web3 Secret Storage definition
+    const PBKDF2_KEYSTORE: &str = r#"{
+        "crypto": {
+            "cipher": "aes-128-ctr",
+            "cipherparams": {"iv": "6087dab2f9fdbbfaddc31a909735c1e6"},
+            "ciphertext": "5318b4d5bcd28de64ee5559e671353e16f075ecae9f99c7a79a38af5f869aa46",
+            "kdf": "pbkdf2",
+            "kdfparams": {
+                "c": 262144,
+                "dklen": 32,
+                "prf": "hmac-sha256",
+                "salt": "ae3cd4e7013836a3df6bd7241b12db061dbe2c6785853cce422d148a624ce0bd"
+            },
+            "mac": "517ead924a9d0dc3124507e3393d175ce3ff7c1e96529c6c555ce9e51205e9b2"
+        },
+        "id": "3198bc9c-6672-5ab3-d995-4942343ae5b6",
+        "version": 3
+    }"#;
+
+    #[test]
+    fn test_decrypt_pbkdf2() {
+        let keystore: Keystore = serde_json::from_str(PBKDF2_KEYSTORE).unwrap();
+        let secret = keystore.decrypt("testpassword").unwrap();
+        assert_eq!(
+            hex::encode(secret.as_slice()),
+            "7a28b5ba57c53603b0b07b56bba752f7784bf506fa95edc395f5cf6c7514fe9d"
+        );
+    }
+
+    #[test]
+    fn test_encrypt_scrypt() {
+        let secret = [7u8; 32];
+        let keystore = Keystore::encrypt_with_cost(&secret, "hunter2", None, 10).unwrap();
+        assert!(matches!(keystore.crypto.kdfparams, KdfParams::Scrypt { n: 1024, .. }));
+
+        let json = serde_json::to_string(&keystore).unwrap();
+        let keystore: Keystore = serde_json::from_str(&json).unwrap();
+        assert_eq!(keystore.decrypt("hunter2").unwrap().as_slice(), secret);
+        assert!(keystore.decrypt("hunter3").is_err());
+    }
+}
@@ -1,10 +1,44 @@
 extern crate strum;
 extern crate strum_macros;
 
+#[cfg(not(any(feature = "solana", feature = "ethereum", feature = "bitcoin")))]
+compile_error!("enable at least one of the `solana`, `ethereum` or `bitcoin` features");
+
+#[cfg(feature = "bitcoin")]
+pub use crate::bitcoin::BitcoinKeyPair;
 pub use crate::context::KeypairContext;
+#[cfg(feature = "ethereum")]
+pub use crate::ethereum::EthereumKeyPair;
+pub use crate::matcher::{CaseMode, Matcher, Pattern};
+pub use crate::registry::ChainSpec;
+#[cfg(feature = "solana")]
 pub use crate::solana::SolanaKeyPair;
-pub use r_storage::prelude::{Chain, DatabaseError, KeypairStrategy, NewKey};
+pub use crate::strategy::{
+    KeyWithSecretExt, KeypairStrategy, NewKeyExt, SignScheme, SignedMessage,
+};
+pub use r_storage::prelude::{
+    Chain, DatabaseError, KeyWithSecret, NewKey, SecretBytes, SecretString,
+};
 
+#[cfg(feature = "bitcoin")]
+pub mod bitcoin;
 pub mod context;
+#[cfg(feature = "ethereum")]
+pub mod eip712;
+#[cfg(feature = "ethereum")]
+pub mod ethereum;
+#[cfg(feature = "ethereum")]
+pub mod ethereum_tx;
+pub mod grind;
+pub mod hd;
+pub mod keyfile;
 pub mod keygen;
+pub mod keystore;
+pub mod matcher;
+pub mod registry;
+#[cfg(any(feature = "ethereum", feature = "bitcoin"))]
+mod secp256k1;
+pub mod shamir;
+#[cfg(feature = "solana")]
 pub mod solana;
+pub mod strategy;
@@ -0,0 +1,485 @@
+//! Vanity address patterns.
+//!
+//! A pattern is written as `abc*` (prefix), `*xyz` or `xyz` (suffix), `ab*yz`
+//! (prefix and suffix) or `/regex/`. Prefixes and regexes apply to the address
+//! without its fixed head, e.g. `0x` for Ethereum or `bc1q` for native segwit.
+use std::{fmt, str::FromStr};
+
+use regex::{Regex, RegexBuilder};
+use regex_syntax::ast::{self, AssertionKind, Ast, Visitor};
+use serde::Deserialize;
+
+use crate::{registry, Chain, DatabaseError, KeypairStrategy, NewKey, NewKeyExt};
+
+/// The longest pattern accepted, addresses are never longer.
+const MAX_PATTERN_LEN: usize = 64;
+
+/// Keep compiled regexes small, they run against every candidate.
+const REGEX_SIZE_LIMIT: usize = 1 << 16;
+
+/// How letters are compared.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaseMode {
+    #[default]
+    Sensitive,
+    Insensitive,
+}
+
+/// The characters an address body is made of.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Alphabet {
+    Base58,
+    Hex,
+    Bech32,
+}
+
+impl Alphabet {
+    pub fn chars(&self) -> &'static str {
+        match self {
+            Alphabet::Base58 => "123456789ABCDEFGHJKLMNPQRSTUVWXYZabcdefghijkmnopqrstuvwxyz",
+            // EIP-55 checksummed addresses are mixed case
+            Alphabet::Hex => "0123456789abcdefABCDEF",
+            Alphabet::Bech32 => "qpzry9x8gf2tvdw0s3jn54khce6mua7l",
+        }
+    }
+
+    /// Whether the character can appear in an address, in any case if insensitive.
+    pub fn contains(&self, c: char, case: CaseMode) -> bool {
+        let chars = self.chars();
+        match case {
+            CaseMode::Sensitive => chars.contains(c),
+            CaseMode::Insensitive => {
+                chars.contains(c.to_ascii_lowercase()) || chars.contains(c.to_ascii_uppercase())
+            }
+        }
+    }
+
+    /// The chance a random address has the character at a given position, assuming
+    /// uniformly distributed characters.
+    pub fn probability(&self, c: char, case: CaseMode) -> f64 {
+        let variants = match case {
+            CaseMode::Sensitive => vec![c],
+            CaseMode::Insensitive => vec![c.to_ascii_lowercase(), c.to_ascii_uppercase()],
+        };
+        let variants = variants.iter().filter(|c| self.contains(**c, CaseMode::Sensitive));
+        match self {
+            // a hex digit is checksummed to either case with even odds
+            Alphabet::Hex => variants
+                .map(|c| if c.is_ascii_digit() { 1.0 / 16.0 } else { 1.0 / 32.0 })
+                .sum::<f64>()
+                .min(1.0 / 16.0),
+            _ => {
+                let mut variants = variants.collect::<Vec<_>>();
+                variants.dedup();
+                variants.len() as f64 / self.chars().len() as f64
+            }
+        }
+    }
+}
+
+/// The alphabet and the fixed head of the addresses a chain generates.
+pub fn address_format(chain: Chain) -> Result<(Alphabet, String), DatabaseError> {
+    let spec = registry::get(chain)?;
+    Ok((spec.alphabet, spec.head.to_string()))
+}
+
+/// A vanity pattern.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub enum Pattern {
+    Prefix(String),
+    Suffix(String),
+    PrefixSuffix(String, String),
+    Regex(String),
+}
+
+impl Pattern {
+    /// The suffix the pattern requires, if any.
+    pub fn suffix(&self) -> Option<&str> {
+        match self {
+            Pattern::Suffix(suffix) | Pattern::PrefixSuffix(_, suffix) => Some(suffix.as_str()),
+            _ => None,
+        }
+    }
+
+    /// The literal characters the address must contain.
+    fn literals(&self) -> impl Iterator<Item = char> + '_ {
+        let (prefix, suffix) = match self {
+            Pattern::Prefix(prefix) => (prefix.as_str(), ""),
+            Pattern::Suffix(suffix) => ("", suffix.as_str()),
+            Pattern::PrefixSuffix(prefix, suffix) => (prefix.as_str(), suffix.as_str()),
+            Pattern::Regex(_) => ("", ""),
+        };
+        prefix.chars().chain(suffix.chars())
+    }
+}
+
+impl FromStr for Pattern {
+    type Err = DatabaseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let invalid = |reason: &str| DatabaseError::InvalidPattern(format!("{s}: {reason}"));
+
+        if s.is_empty() {
+            return Err(invalid("the pattern is empty"));
+        }
+        if s.len() > MAX_PATTERN_LEN {
+            return Err(invalid("the pattern is too long"));
+        }
+        if let Some(regex) = s.strip_prefix('/').and_then(|s| s.strip_suffix('/')) {
+            if regex.is_empty() {
+                return Err(invalid("the regex is empty"));
+            }
+            return Ok(Pattern::Regex(regex.to_string()));
+        }
+        match s.split_once('*') {
+            None => Ok(Pattern::Suffix(s.to_string())),
+            Some((_, suffix)) if suffix.contains('*') => Err(invalid("only one `*` is allowed")),
+            Some(("", "")) => Err(invalid("the pattern is empty")),
+            Some((prefix, "")) => Ok(Pattern::Prefix(prefix.to_string())),
+            Some(("", suffix)) => Ok(Pattern::Suffix(suffix.to_string())),
+            Some((prefix, suffix)) => {
+                Ok(Pattern::PrefixSuffix(prefix.to_string(), suffix.to_string()))
+            }
+        }
+    }
+}
+
+impl fmt::Display for Pattern {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Pattern::Prefix(prefix) => write!(f, "{prefix}*"),
+            Pattern::Suffix(suffix) => write!(f, "*{suffix}"),
+            Pattern::PrefixSuffix(prefix, suffix) => write!(f, "{prefix}*{suffix}"),
+            Pattern::Regex(regex) => write!(f, "/{regex}/"),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum Compiled {
+    Affix { prefix: String, suffix: String },
+    Regex(Regex),
+}
+
+/// Matches addresses of one chain against several candidate patterns.
+#[derive(Clone)]
+pub struct Matcher {
+    chain: Chain,
+    case: CaseMode,
+    alphabet: Alphabet,
+    head: String,
+    patterns: Vec<(Pattern, Compiled)>,
+}
+
+impl Matcher {
+    /// Validate the patterns against the chain's address alphabet and compile them.
+    pub fn new(
+        chain: Chain,
+        patterns: Vec<Pattern>,
+        case: CaseMode,
+    ) -> Result<Self, DatabaseError> {
+        let (alphabet, head) = address_format(chain)?;
+        if patterns.is_empty() {
+            return Err(DatabaseError::InvalidPattern("no pattern to search".to_string()));
+        }
+
+        let patterns = patterns
+            .into_iter()
+            .map(|pattern| {
+                let compiled = compile(&pattern, alphabet, case)?;
+                Ok((pattern, compiled))
+            })
+            .collect::<Result<Vec<_>, DatabaseError>>()?;
+
+        Ok(Matcher { chain, case, alphabet, head, patterns })
+    }
+
+    /// Parse and validate the patterns.
+    pub fn parse<S: AsRef<str>>(
+        chain: Chain,
+        patterns: &[S],
+        case: CaseMode,
+    ) -> Result<Self, DatabaseError> {
+        let patterns = patterns
+            .iter()
+            .map(|s| Pattern::from_str(s.as_ref()))
+            .collect::<Result<Vec<_>, _>>()?;
+        Self::new(chain, patterns, case)
+    }
+
+    pub fn chain(&self) -> Chain {
+        self.chain
+    }
+
+    pub fn patterns(&self) -> impl Iterator<Item = &Pattern> {
+        self.patterns.iter().map(|(pattern, _)| pattern)
+    }
+
+    /// The chance a random address matches one of the patterns, `None` if a regex
+    /// makes it unknown.
+    pub fn probability(&self) -> Option<f64> {
+        // log of the chance to miss every pattern, precise for tiny chances
+        let mut log_miss = 0.0;
+        for pattern in self.patterns() {
+            log_miss += (-self.pattern_probability(pattern)?).ln_1p();
+        }
+        Some(-log_miss.exp_m1())
+    }
+
+    /// The chance a random address matches the pattern, `None` for a regex.
+    pub fn pattern_probability(&self, pattern: &Pattern) -> Option<f64> {
+        if let Pattern::Regex(_) = pattern {
+            return None;
+        }
+        Some(pattern.literals().map(|c| self.alphabet.probability(c, self.case)).product())
+    }
+
+    /// The number of attempts a search needs on average.
+    pub fn expected_attempts(&self) -> Option<f64> {
+        self.probability().filter(|p| *p > 0.0).map(|p| 1.0 / p)
+    }
+
+    /// The first pattern the address satisfies.
+    pub fn find(&self, address: &str) -> Option<&Pattern> {
+        let (i, _) = self.matches(address).next()?;
+        self.patterns.get(i).map(|(pattern, _)| pattern)
+    }
+
+    /// Every pattern the address satisfies, with its index.
+    pub fn matches<'a>(&'a self, address: &'a str) -> impl Iterator<Item = (usize, &'a Pattern)> {
+        let body = address.strip_prefix(self.head.as_str()).unwrap_or(address);
+        let case = self.case;
+        let eq = move |a: &[u8], b: &[u8]| match case {
+            CaseMode::Sensitive => a == b,
+            CaseMode::Insensitive => a.eq_ignore_ascii_case(b),
+        };
+
+        // runs once per candidate key, so it must not allocate
+        self.patterns.iter().enumerate().filter_map(move |(i, (pattern, compiled))| {
+            let matched = match compiled {
+                Compiled::Affix { prefix, suffix } => {
+                    let (body, prefix, suffix) =
+                        (body.as_bytes(), prefix.as_bytes(), suffix.as_bytes());
+                    body.len() >= prefix.len() + suffix.len()
+                        && eq(&body[..prefix.len()], prefix)
+                        && eq(&body[body.len() - suffix.len()..], suffix)
+                }
+                Compiled::Regex(regex) => regex.is_match(body),
+            };
+            matched.then_some((i, pattern))
+        })
+    }
+}
+
+fn compile(
+    pattern: &Pattern,
+    alphabet: Alphabet,
+    case: CaseMode,
+) -> Result<Compiled, DatabaseError> {
+    if let Some(c) = pattern.literals().find(|c| !alphabet.contains(*c, case)) {
+        return Err(impossible(pattern, c, alphabet));
+    }
+
+    match pattern {
+        Pattern::Prefix(prefix) => {
+            Ok(Compiled::Affix { prefix: prefix.clone(), suffix: String::new() })
+        }
+        Pattern::Suffix(suffix) => {
+            Ok(Compiled::Affix { prefix: String::new(), suffix: suffix.clone() })
+        }
+        Pattern::PrefixSuffix(prefix, suffix) => {
+            Ok(Compiled::Affix { prefix: prefix.clone(), suffix: suffix.clone() })
+        }
+        Pattern::Regex(regex) => {
+            let invalid = |e: String| DatabaseError::InvalidPattern(format!("{pattern}: {e}"));
+            let parsed =
+                ast::parse::Parser::new().parse(regex).map_err(|e| invalid(e.to_string()))?;
+            ast::visit(&parsed, Restricted { pattern, alphabet, case })?;
+
+            RegexBuilder::new(regex)
+                .case_insensitive(case == CaseMode::Insensitive)
+                .size_limit(REGEX_SIZE_LIMIT)
+                .build()
+                .map(Compiled::Regex)
+                .map_err(|e| invalid(e.to_string()))
+        }
+    }
+}
+
+fn impossible(pattern: &Pattern, c: char, alphabet: Alphabet) -> DatabaseError {
+    DatabaseError::InvalidPattern(format!(
+        "{pattern}: `{c}` never appears in {alphabet:?} addresses"
+    ))
+}
+
+/// Only literals, classes, groups, repetitions and anchors are allowed in regexes,
+/// and literals must belong to the address alphabet.
+struct Restricted<'a> {
+    pattern: &'a Pattern,
+    alphabet: Alphabet,
+    case: CaseMode,
+}
+
+impl Visitor for Restricted<'_> {
+    type Output = ();
+    type Err = DatabaseError;
+
+    fn finish(self) -> Result<(), DatabaseError> {
+        Ok(())
+    }
+
+    fn visit_pre(&mut self, ast: &Ast) -> Result<(), DatabaseError> {
+        let unsupported = |what: &str| {
+            Err(DatabaseError::InvalidPattern(format!("{}: {what} is not supported", self.pattern)))
+        };
+        match ast {
+            Ast::Literal(literal) if !self.alphabet.contains(literal.c, self.case) => {
+                Err(impossible(self.pattern, literal.c, self.alphabet))
+            }
+            Ast::Assertion(assertion) => match assertion.kind {
+                AssertionKind::StartLine
+                | AssertionKind::EndLine
+                | AssertionKind::StartText
+                | AssertionKind::EndText => Ok(()),
+                _ => unsupported("a word boundary"),
+            },
+            Ast::Flags(_) => unsupported("an inline flag, use the case mode instead"),
+            Ast::Group(group) if group.flags().is_some() => {
+                unsupported("an inline flag, use the case mode instead")
+            }
+            Ast::ClassUnicode(_) | Ast::ClassPerl(_) => unsupported("a named class"),
+            _ => Ok(()),
+        }
+    }
+}
+
+/// The key to save for a keypair found with a pattern.
+///
+/// The suffix keeps the case of the address so it can be searched exactly.
+pub fn new_key(keypair: &dyn KeypairStrategy, pattern: &Pattern) -> NewKey {
+    let address = keypair.address();
+    let suffix = pattern.suffix().map(|s| address[address.len() - s.len()..].to_string());
+
+    let mut key = NewKey::from_keypair(keypair, suffix);
+    key.pattern = Some(pattern.to_string());
+    key
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_parse_pattern() {
+        assert_eq!("sol".parse::<Pattern>().unwrap(), Pattern::Suffix("sol".to_string()));
+        assert_eq!("*sol".parse::<Pattern>().unwrap(), Pattern::Suffix("sol".to_string()));
+        assert_eq!("ab*".parse::<Pattern>().unwrap(), Pattern::Prefix("ab".to_string()));
+        assert_eq!(
+            "ab*yz".parse::<Pattern>().unwrap(),
+            Pattern::PrefixSuffix("ab".to_string(), "yz".to_string())
+        );
+        assert_eq!("/^a.c$/".parse::<Pattern>().unwrap(), Pattern::Regex("^a.c$".to_string()));
+
+        for invalid in ["", "*", "a*b*c", "//"] {
+            assert!(invalid.parse::<Pattern>().is_err(), "{invalid}");
+        }
+        for pattern in ["*sol", "ab*", "ab*yz", "/^a.c$/"] {
+            assert_eq!(pattern.parse::<Pattern>().unwrap().to_string(), pattern);
+        }
+    }
+
+    #[test]
+    fn test_alphabet_validation() {
+        let sensitive = CaseMode::Sensitive;
+        #[cfg(feature = "solana")]
+        {
+            assert!(Matcher::parse(Chain::Solana, &["sol0"], sensitive).is_err());
+            assert!(Matcher::parse(Chain::Solana, &["soul"], sensitive).is_err());
+            assert!(Matcher::parse(Chain::Solana, &["soul"], CaseMode::Insensitive).is_ok());
+            assert!(Matcher::parse(Chain::Solana, &["/^I/"], sensitive).is_err());
+        }
+        #[cfg(feature = "ethereum")]
+        {
+            assert!(Matcher::parse(Chain::Ethereum, &["sol"], sensitive).is_err());
+            assert!(Matcher::parse(Chain::Ethereum, &["dead*beef"], sensitive).is_ok());
+        }
+        #[cfg(feature = "bitcoin")]
+        {
+            assert!(Matcher::parse(Chain::Bitcoin, &["B"], sensitive).is_err());
+            assert!(Matcher::parse(Chain::Bitcoin, &["b*"], sensitive).is_err());
+        }
+        assert!(Matcher::parse(Chain::Unknown, &["a"], sensitive).is_err());
+        assert!(Matcher::parse::<&str>(Chain::Solana, &[], sensitive).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_restricted_regex() {
+        let sensitive = CaseMode::Sensitive;
+        assert!(Matcher::parse(Chain::Solana, &["/^(ab|cd)[1-9]{2}$/"], sensitive).is_ok());
+        assert!(Matcher::parse(Chain::Solana, &["/(?i)ab/"], sensitive).is_err());
+        assert!(Matcher::parse(Chain::Solana, &["/\\bab/"], sensitive).is_err());
+        assert!(Matcher::parse(Chain::Solana, &["/\\w+/"], sensitive).is_err());
+        assert!(Matcher::parse(Chain::Solana, &["/a(b/"], sensitive).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "solana")]
+    fn test_probability() {
+        let matcher = Matcher::parse(Chain::Solana, &["ab"], CaseMode::Sensitive).unwrap();
+        assert_eq!(matcher.expected_attempts().unwrap().round(), 58.0 * 58.0);
+
+        // `o` has no uppercase twin in base58, `a` has both
+        let matcher = Matcher::parse(Chain::Solana, &["a", "o"], CaseMode::Insensitive).unwrap();
+        let expected = 1.0 - (1.0 - 2.0 / 58.0) * (1.0 - 1.0 / 58.0);
+        assert!((matcher.probability().unwrap() - expected).abs() < 1e-12);
+
+        let matcher = Matcher::parse(Chain::Solana, &["/^a/"], CaseMode::Sensitive).unwrap();
+        assert_eq!(matcher.expected_attempts(), None);
+    }
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_probability_mixed_case() {
+        let matcher = Matcher::parse(Chain::Ethereum, &["0a*A"], CaseMode::Sensitive).unwrap();
+        assert_eq!(matcher.expected_attempts().unwrap().round(), 16.0 * 32.0 * 32.0);
+        let matcher = Matcher::parse(Chain::Ethereum, &["0a*A"], CaseMode::Insensitive).unwrap();
+        assert_eq!(matcher.expected_attempts().unwrap().round(), 16.0 * 16.0 * 16.0);
+    }
+
+    #[test]
+    #[cfg(feature = "ethereum")]
+    fn test_find() {
+        let matcher =
+            Matcher::parse(Chain::Ethereum, &["dead*", "*BEEF"], CaseMode::Sensitive).unwrap();
+        let address = "0xdeAd00000000000000000000000000000000BEEF";
+        assert_eq!(matcher.find(address), Some(&Pattern::Suffix("BEEF".to_string())));
+        assert_eq!(
+            matcher.find("0xdead0000000000000000000000000000000000ef"),
+            matcher.patterns().next()
+        );
+        assert_eq!(matcher.find("0x0000000000000000000000000000000000000000"), None);
+
+        let matcher =
+            Matcher::parse(Chain::Ethereum, &["dead*", "/^0{4}/"], CaseMode::Insensitive).unwrap();
+        assert_eq!(matcher.find(address), matcher.patterns().next());
+        assert_eq!(
+            matcher.find("0x0000dddddddddddddddddddddddddddddddddddd"),
+            Some(&Pattern::Regex("^0{4}".to_string()))
+        );
+
+        let matcher =
+            Matcher::parse(Chain::Ethereum, &["*EF", "*BEEF"], CaseMode::Sensitive).unwrap();
+        assert_eq!(matcher.matches(address).map(|(i, _)| i).collect::<Vec<_>>(), vec![0, 1]);
+    }
+
+    #[test]
+    #[cfg(feature = "bitcoin")]
+    fn test_find_bech32() {
+        let matcher = Matcher::parse(Chain::Bitcoin, &["q*"], CaseMode::Sensitive).unwrap();
+        assert!(matcher.find("bc1qqr8te4kr609gcawutmrza0j4xv80jy8z306fyu").is_some());
+        assert!(matcher.find("bc1qcr8te4kr609gcawutmrza0j4xv80jy8z306fyu").is_none());
+    }
+}
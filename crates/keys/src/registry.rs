@@ -0,0 +1,140 @@
+//! The chains r-keys supports.
+//!
+//! Each chain module registers a [`ChainSpec`] with its keypair factory, curve, address
+//! encoding, signing schemes and vanity alphabet. The CLI, the API and the vanity search read
+//! the supported chains from here, adding a chain means adding its spec to [`register`].
+//! Each chain is behind the cargo feature of the same name, only the enabled ones register.
+use std::sync::OnceLock;
+
+use clap::builder::{PossibleValuesParser, TypedValueParser};
+use strum::Display;
+
+#[cfg(feature = "bitcoin")]
+use crate::bitcoin;
+#[cfg(feature = "ethereum")]
+use crate::ethereum;
+#[cfg(feature = "solana")]
+use crate::solana;
+use crate::{hd::Curve, matcher::Alphabet, Chain, DatabaseError, KeypairStrategy, SignScheme};
+
+/// How the addresses of a chain are encoded.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "kebab-case")]
+pub enum AddressEncoding {
+    /// The base58 public key.
+    Base58,
+    /// The base58check pubkey hash.
+    Base58Check,
+    /// The EIP-55 checksummed hex of the pubkey hash.
+    Eip55,
+    /// The bech32 segwit program.
+    Bech32,
+}
+
+/// A chain and everything r-keys needs to handle its keys.
+#[derive(Debug, Clone, Copy)]
+pub struct ChainSpec {
+    pub chain: Chain,
+    /// The name of the chain in the CLI and the API.
+    pub name: &'static str,
+    pub curve: Curve,
+    pub encoding: AddressEncoding,
+    /// The characters of the generated addresses, vanity patterns are checked against it.
+    pub alphabet: Alphabet,
+    /// The fixed head of the generated addresses.
+    pub head: &'static str,
+    /// The signing schemes, `Raw` first.
+    pub schemes: &'static [SignScheme],
+    pub(crate) factory: fn() -> Box<dyn KeypairStrategy>,
+}
+
+impl ChainSpec {
+    /// Create a random keypair of the chain.
+    pub fn keypair(&self) -> Box<dyn KeypairStrategy> {
+        (self.factory)()
+    }
+
+    /// Whether the chain signs with the scheme.
+    pub fn supports(&self, scheme: SignScheme) -> bool {
+        self.schemes.contains(&scheme)
+    }
+}
+
+fn register() -> Vec<ChainSpec> {
+    let chains: &[&[ChainSpec]] = &[
+        #[cfg(feature = "solana")]
+        &[solana::SPEC],
+        #[cfg(feature = "ethereum")]
+        &[ethereum::SPEC],
+        #[cfg(feature = "bitcoin")]
+        bitcoin::SPECS,
+    ];
+    chains.concat()
+}
+
+/// The supported chains.
+pub fn chains() -> &'static [ChainSpec] {
+    static REGISTRY: OnceLock<Vec<ChainSpec>> = OnceLock::new();
+    REGISTRY.get_or_init(register)
+}
+
+/// The spec of a chain, an error if it is not supported.
+pub fn get(chain: Chain) -> Result<&'static ChainSpec, DatabaseError> {
+    chains().iter().find(|spec| spec.chain == chain).ok_or(DatabaseError::UnsupportedChain(chain))
+}
+
+/// The name of the first registered chain, the default of the CLI.
+pub fn default_name() -> &'static str {
+    chains()[0].name
+}
+
+/// The spec of a chain by its name.
+pub fn by_name(name: &str) -> Option<&'static ChainSpec> {
+    chains().iter().find(|spec| spec.name == name)
+}
+
+/// A clap parser accepting the names of the supported chains.
+pub fn chain_parser() -> impl TypedValueParser<Value = Chain> {
+    PossibleValuesParser::new(chains().iter().map(|spec| spec.name))
+        .map(|name| by_name(&name).map(|spec| spec.chain).unwrap_or(Chain::Unknown))
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+    use crate::{CaseMode, KeypairContext};
+
+    #[test]
+    fn test_registry() {
+        for spec in chains() {
+            assert_eq!(get(spec.chain).unwrap().name, spec.name);
+            assert_eq!(by_name(spec.name).unwrap().chain, spec.chain);
+            assert_eq!(spec.schemes.first(), Some(&SignScheme::Raw), "{}", spec.name);
+
+            let mut keypair = spec.keypair();
+            keypair.generate();
+            assert_eq!(keypair.chain(), spec.chain);
+
+            let address = keypair.address();
+            let tail = address.strip_prefix(spec.head).unwrap();
+            assert!(
+                tail.chars().all(|c| spec.alphabet.contains(c, CaseMode::Sensitive)),
+                "{address}"
+            );
+        }
+        assert!(get(Chain::Unknown).is_err());
+        assert!(KeypairContext::from_chain(Chain::Unknown).is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "bitcoin")]
+    fn test_chain_parser() {
+        let command = clap::Command::new("anita")
+            .arg(clap::Arg::new("chain").long("chain").value_parser(chain_parser()));
+
+        let matches = command.clone().try_get_matches_from(["anita", "--chain", "bitcoin-testnet"]);
+        assert_eq!(matches.unwrap().get_one::<Chain>("chain"), Some(&Chain::BitcoinTestnet));
+        assert!(command.try_get_matches_from(["anita", "--chain", "unknown"]).is_err());
+    }
+}
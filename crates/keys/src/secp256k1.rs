@@ -0,0 +1,13 @@
+use libsecp256k1::SecretKey;
+use rand::{rngs::OsRng, RngCore};
+
+/// Draw secret keys until one is a valid secp256k1 scalar.
+pub fn random_secret() -> SecretKey {
+    loop {
+        let mut bytes = [0u8; 32];
+        OsRng.fill_bytes(&mut bytes);
+        if let Ok(secret) = SecretKey::parse(&bytes) {
+            return secret;
+        }
+    }
+}
@@ -0,0 +1,489 @@
+//! Shamir secret sharing of keypairs, for `anita key split` and `anita key combine`.
+//!
+//! The bytes of [`KeypairStrategy::to_vec`], or a database seed for `anita seed split` and
+//! `/sys/unseal`, are split over GF(256), the field of SLIP-39, so that any `threshold` of the
+//! shares rebuild them. Every share is base58check encoded and carries the chain (or `seed`),
+//! the threshold and an identifier, derived from the pubkey of keys and from a hash of seeds:
+//! shares of different secrets are refused before combining, and the rebuilt secret must match
+//! the identifier, which catches shares of different splits of the same secret.
+use std::{collections::BTreeMap, str::FromStr};
+
+use rand::{rngs::OsRng, RngCore};
+use sha2::{Digest, Sha256};
+
+use crate::{Chain, DatabaseError, KeypairContext, KeypairStrategy, SecretBytes, SecretString};
+
+/// The version of the share encoding.
+const VERSION: u8 = 1;
+
+/// The length of the pubkey identifier.
+const IDENTIFIER_LEN: usize = 4;
+
+/// The largest threshold of a split.
+pub const MAX_THRESHOLD: u8 = 16;
+
+/// Log and exp tables of GF(256) with the Rijndael polynomial, generated by 3.
+const TABLES: ([u8; 255], [u8; 256]) = {
+    let (mut exp, mut log) = ([0u8; 255], [0u8; 256]);
+    let mut x: u16 = 1;
+    let mut i = 0;
+    while i < 255 {
+        exp[i] = x as u8;
+        log[x as usize] = i as u8;
+        x ^= x << 1;
+        if x & 0x100 != 0 {
+            x ^= 0x11b;
+        }
+        i += 1;
+    }
+    (exp, log)
+};
+
+fn mul(a: u8, b: u8) -> u8 {
+    let (exp, log) = &TABLES;
+    match a == 0 || b == 0 {
+        true => 0,
+        false => exp[(log[a as usize] as usize + log[b as usize] as usize) % 255],
+    }
+}
+
+/// `a / b`, `b` is never zero.
+fn div(a: u8, b: u8) -> u8 {
+    let (exp, log) = &TABLES;
+    match a == 0 {
+        true => 0,
+        false => exp[(log[a as usize] as usize + 255 - log[b as usize] as usize) % 255],
+    }
+}
+
+/// The label of the shares of a database seed, the shares of keys are labelled by the chain.
+const SEED_LABEL: &str = "seed";
+
+/// The length of a database seed.
+const SEED_LEN: usize = 32;
+
+/// The identifier of a secret in its shares.
+fn identifier(data: &[&[u8]]) -> [u8; IDENTIFIER_LEN] {
+    let hash = data.iter().fold(Sha256::new(), |hash, data| hash.chain_update(data)).finalize();
+    let mut identifier = [0u8; IDENTIFIER_LEN];
+    identifier.copy_from_slice(&hash[..IDENTIFIER_LEN]);
+    identifier
+}
+
+/// Keys are identified by their pubkey.
+fn key_identifier(keypair: &dyn KeypairStrategy) -> [u8; IDENTIFIER_LEN] {
+    identifier(&[keypair.pubkey().as_bytes()])
+}
+
+/// Seeds have no pubkey, they are identified by a hash of the seed itself.
+fn seed_identifier(seed: &[u8]) -> [u8; IDENTIFIER_LEN] {
+    identifier(&[b"anita seed", seed])
+}
+
+fn invalid(message: impl Into<String>) -> DatabaseError {
+    DatabaseError::InvalidShare(message.into())
+}
+
+/// One share of a keypair or a seed.
+#[derive(Debug)]
+struct Share {
+    identifier: [u8; IDENTIFIER_LEN],
+    threshold: u8,
+    /// The x coordinate, never zero.
+    index: u8,
+    /// The chain of a keypair, or [`SEED_LABEL`].
+    label: String,
+    value: SecretBytes,
+}
+
+impl Share {
+    fn encode(&self) -> SecretString {
+        let mut bytes = SecretBytes::new(Vec::with_capacity(
+            IDENTIFIER_LEN + 4 + self.label.len() + self.value.as_slice().len(),
+        ));
+        let buf = bytes.expose_mut();
+        buf.push(VERSION);
+        buf.extend_from_slice(&self.identifier);
+        buf.extend_from_slice(&[self.threshold, self.index, self.label.len() as u8]);
+        buf.extend_from_slice(self.label.as_bytes());
+        buf.extend_from_slice(self.value.as_slice());
+        SecretString::new(bs58::encode(bytes.as_slice()).with_check().into_string())
+    }
+
+    fn decode(share: &str) -> Result<Self, DatabaseError> {
+        // name the share by its head only, it is still part of a secret
+        let head: String = share.trim().chars().take(8).collect();
+        let bytes = bs58::decode(share.trim())
+            .with_check(None)
+            .into_vec()
+            .map(SecretBytes::new)
+            .map_err(|e| invalid(format!("`{head}...`: {e}")))?;
+        let header = IDENTIFIER_LEN + 4;
+        let bytes = bytes.as_slice();
+        if bytes.len() < header || bytes[0] != VERSION {
+            return Err(invalid("unknown share version"));
+        }
+        let (threshold, index, label_len) =
+            (bytes[IDENTIFIER_LEN + 1], bytes[IDENTIFIER_LEN + 2], bytes[IDENTIFIER_LEN + 3]);
+        let label = bytes
+            .get(header..header + label_len as usize)
+            .and_then(|label| std::str::from_utf8(label).ok())
+            .ok_or_else(|| invalid("malformed share"))?;
+        let value = &bytes[header + label_len as usize..];
+        if index == 0 || !(2..=MAX_THRESHOLD).contains(&threshold) || value.is_empty() {
+            return Err(invalid("malformed share"));
+        }
+
+        let mut identifier = [0u8; IDENTIFIER_LEN];
+        identifier.copy_from_slice(&bytes[1..=IDENTIFIER_LEN]);
+        Ok(Share {
+            identifier,
+            threshold,
+            index,
+            label: label.to_string(),
+            value: SecretBytes::from_slice(value),
+        })
+    }
+}
+
+/// Split a secret into `count` shares, any `threshold` of them rebuild it.
+fn split_secret(
+    secret: &[u8],
+    identifier: [u8; IDENTIFIER_LEN],
+    label: &str,
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<SecretString>, DatabaseError> {
+    if threshold < 2 || threshold > count.min(MAX_THRESHOLD) {
+        return Err(invalid(format!(
+            "the threshold must be between 2 and the {} shares, got {threshold}",
+            count.min(MAX_THRESHOLD)
+        )));
+    }
+
+    // the random coefficients of every byte, the secret byte is the constant term
+    let degree = threshold as usize - 1;
+    let mut coefficients = SecretBytes::new(vec![0u8; secret.len() * degree]);
+    OsRng.fill_bytes(coefficients.expose_mut());
+
+    (1..=count)
+        .map(|index| {
+            let mut value = SecretBytes::new(vec![0u8; secret.len()]);
+            for (i, byte) in secret.iter().enumerate() {
+                let terms = &coefficients.as_slice()[i * degree..(i + 1) * degree];
+                let y = terms.iter().rev().fold(0, |y, term| mul(y, index) ^ term);
+                value.expose_mut()[i] = mul(y, index) ^ byte;
+            }
+            let label = label.to_string();
+            Ok(Share { identifier, threshold, index, label, value }.encode())
+        })
+        .collect()
+}
+
+/// Shares of one secret, collected one at a time.
+#[derive(Debug, Default)]
+pub struct ShareSet {
+    /// By index.
+    shares: BTreeMap<u8, Share>,
+}
+
+impl ShareSet {
+    /// Collect the shares of one secret.
+    pub fn from_shares<S: AsRef<str>>(shares: &[S]) -> Result<Self, DatabaseError> {
+        let mut set = ShareSet::default();
+        for share in shares {
+            set.add(share.as_ref())?;
+        }
+        Ok(set)
+    }
+
+    /// Add a share, refusing the shares of another secret.
+    pub fn add(&mut self, share: &str) -> Result<(), DatabaseError> {
+        self.insert(Share::decode(share)?)
+    }
+
+    /// Add a share of a database seed, refusing the shares of keys.
+    pub fn add_seed(&mut self, share: &str) -> Result<(), DatabaseError> {
+        let share = Share::decode(share)?;
+        if share.label != SEED_LABEL {
+            return Err(invalid(format!("`{}` shares are not shares of a seed", share.label)));
+        }
+        if share.value.as_slice().len() != SEED_LEN {
+            return Err(invalid(format!("the share is not of a {SEED_LEN}-byte seed")));
+        }
+        self.insert(share)
+    }
+
+    fn insert(&mut self, share: Share) -> Result<(), DatabaseError> {
+        if let Some(first) = self.shares.values().next() {
+            if share.identifier != first.identifier || share.label != first.label {
+                return Err(invalid("the shares are of different secrets"));
+            }
+            if share.threshold != first.threshold
+                || share.value.as_slice().len() != first.value.as_slice().len()
+            {
+                return Err(invalid("the shares are of different splits"));
+            }
+        }
+        match self.shares.get(&share.index) {
+            Some(known) if known.value != share.value => {
+                Err(invalid(format!("share {} is given twice", share.index)))
+            }
+            Some(_) => Ok(()),
+            None => {
+                self.shares.insert(share.index, share);
+                Ok(())
+            }
+        }
+    }
+
+    /// The number of distinct shares.
+    pub fn len(&self) -> usize {
+        self.shares.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.shares.is_empty()
+    }
+
+    /// The number of shares needed, known once a share is added.
+    pub fn threshold(&self) -> Option<u8> {
+        self.shares.values().next().map(|share| share.threshold)
+    }
+
+    /// Whether enough shares are collected to rebuild the secret.
+    pub fn is_complete(&self) -> bool {
+        self.threshold().is_some_and(|threshold| self.len() >= threshold as usize)
+    }
+
+    /// Rebuild the secret by Lagrange interpolation at zero over the first `threshold` shares,
+    /// with its label and identifier.
+    fn interpolate(&self) -> Result<(&Share, SecretBytes), DatabaseError> {
+        let first = self.shares.values().next().ok_or_else(|| invalid("no shares"))?;
+        if !self.is_complete() {
+            return Err(invalid(format!(
+                "{} shares are needed, got {}",
+                first.threshold,
+                self.len()
+            )));
+        }
+
+        let points: Vec<_> = self.shares.values().take(first.threshold as usize).collect();
+        let mut secret = SecretBytes::new(vec![0u8; first.value.as_slice().len()]);
+        for (i, share) in points.iter().enumerate() {
+            let basis =
+                points.iter().enumerate().filter(|(j, _)| *j != i).fold(1, |basis, (_, other)| {
+                    mul(basis, div(other.index, other.index ^ share.index))
+                });
+            for (byte, y) in secret.expose_mut().iter_mut().zip(share.value.as_slice()) {
+                *byte ^= mul(*y, basis);
+            }
+        }
+        Ok((first, secret))
+    }
+
+    /// Rebuild the keypair of the shares.
+    pub fn keypair(&self) -> Result<KeypairContext, DatabaseError> {
+        let (first, secret) = self.interpolate()?;
+        let chain = Chain::from_str(&first.label)
+            .map_err(|_| invalid(format!("`{}` shares are not shares of a key", first.label)))?;
+        let context = KeypairContext::from_bytes(chain, secret.as_slice())
+            .map_err(|_| invalid("the shares do not rebuild the key"))?;
+        if key_identifier(context.keypair()) != first.identifier {
+            return Err(invalid("the shares do not rebuild the key"));
+        }
+        Ok(context)
+    }
+
+    /// Rebuild the database seed of the shares.
+    pub fn seed(&self) -> Result<SecretBytes, DatabaseError> {
+        let (first, seed) = self.interpolate()?;
+        if first.label != SEED_LABEL {
+            return Err(invalid(format!("`{}` shares are not shares of a seed", first.label)));
+        }
+        if seed.as_slice().len() != SEED_LEN {
+            return Err(invalid(format!(
+                "the shares rebuild {} bytes, a seed has {SEED_LEN}",
+                seed.as_slice().len()
+            )));
+        }
+        if seed_identifier(seed.as_slice()) != first.identifier {
+            return Err(invalid("the shares do not rebuild the seed"));
+        }
+        Ok(seed)
+    }
+}
+
+/// Split a keypair into `count` shares, any `threshold` of them rebuild it.
+pub fn split(
+    keypair: &dyn KeypairStrategy,
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<SecretString>, DatabaseError> {
+    let label = keypair.chain().to_string();
+    split_secret(keypair.to_vec().as_slice(), key_identifier(keypair), &label, threshold, count)
+}
+
+/// Rebuild a keypair from at least `threshold` of its shares.
+pub fn combine<S: AsRef<str>>(shares: &[S]) -> Result<KeypairContext, DatabaseError> {
+    ShareSet::from_shares(shares)?.keypair()
+}
+
+/// Split a database seed into `count` shares, any `threshold` of them rebuild it.
+pub fn split_seed(
+    seed: &SecretBytes,
+    threshold: u8,
+    count: u8,
+) -> Result<Vec<SecretString>, DatabaseError> {
+    let identifier = seed_identifier(seed.as_slice());
+    split_secret(seed.as_slice(), identifier, SEED_LABEL, threshold, count)
+}
+
+/// Rebuild a database seed from at least `threshold` of its shares.
+pub fn combine_seed<S: AsRef<str>>(shares: &[S]) -> Result<SecretBytes, DatabaseError> {
+    ShareSet::from_shares(shares)?.seed()
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    fn combine_err(shares: &[&str]) -> DatabaseError {
+        combine(shares).err().expect("the shares combined")
+    }
+
+    /// Any chain built into this crate.
+    fn first_chain() -> Chain {
+        crate::registry::chains()[0].chain
+    }
+
+    #[test]
+    fn test_field() {
+        for a in 1..=255u8 {
+            for b in 1..=255u8 {
+                assert_eq!(div(mul(a, b), b), a);
+            }
+        }
+        // the SLIP-39 field, x^8 + x^4 + x^3 + x + 1
+        assert_eq!(mul(0x57, 0x83), 0xc1);
+        assert_eq!(mul(0, 7), 0);
+    }
+
+    #[test]
+    fn test_split_combine() {
+        for chain in crate::registry::chains().iter().map(|spec| spec.chain) {
+            let context = KeypairContext::from_chain(chain).unwrap();
+            let keypair = context.keypair();
+            let shares = split(keypair, 3, 5).unwrap();
+            assert_eq!(shares.len(), 5);
+
+            for subset in [[0, 1, 2], [4, 2, 0], [1, 3, 4]] {
+                let subset: Vec<_> = subset.iter().map(|i| shares[*i].as_str()).collect();
+                let combined = combine(&subset).unwrap();
+                assert_eq!(combined.keypair().address(), keypair.address());
+                assert_eq!(combined.keypair().to_vec(), keypair.to_vec());
+            }
+            let all: Vec<_> = shares.iter().map(|share| share.as_str()).collect();
+            assert_eq!(combine(&all).unwrap().keypair().pubkey(), keypair.pubkey());
+
+            let short = combine_err(&all[..2]);
+            assert!(short.to_string().contains("3 shares are needed"), "{short}");
+            assert!(combine(&[all[0], all[0], all[1]]).is_err());
+        }
+    }
+
+    #[test]
+    #[cfg(all(feature = "solana", feature = "ethereum"))]
+    fn test_mixed_shares() {
+        let context = KeypairContext::from_chain(Chain::Ethereum).unwrap();
+        let keypair = context.keypair();
+        let shares = split(keypair, 2, 3).unwrap();
+
+        let other = KeypairContext::from_chain(Chain::Ethereum).unwrap();
+        let others = split(other.keypair(), 2, 3).unwrap();
+        let error = combine_err(&[shares[0].as_str(), others[1].as_str()]);
+        assert!(error.to_string().contains("different secrets"), "{error}");
+
+        // another split of the same key has the same identifier
+        let again = split(keypair, 2, 3).unwrap();
+        let error = combine_err(&[shares[0].as_str(), again[1].as_str()]);
+        assert!(error.to_string().contains("do not rebuild"), "{error}");
+
+        let solana = KeypairContext::from_chain(Chain::Solana).unwrap();
+        let first = split(solana.keypair(), 2, 3).unwrap();
+        let second = split(solana.keypair(), 2, 3).unwrap();
+        assert!(combine(&[first[0].as_str(), second[1].as_str()]).is_err());
+    }
+
+    #[test]
+    fn test_corrupted_share() {
+        let context = KeypairContext::from_chain(first_chain()).unwrap();
+        let shares = split(context.keypair(), 2, 2).unwrap();
+        let mut corrupted = shares[1].as_str().to_string();
+        let last = corrupted.pop().unwrap();
+        corrupted.push(if last == 'a' { 'b' } else { 'a' });
+
+        let error = combine_err(&[shares[0].as_str(), corrupted.as_str()]);
+        assert!(matches!(error, DatabaseError::InvalidShare(_)));
+        assert!(split(context.keypair(), 1, 3).is_err());
+        assert!(split(context.keypair(), 4, 3).is_err());
+        assert!(split(context.keypair(), MAX_THRESHOLD + 1, 255).is_err());
+
+        // a share no split makes
+        let mut forged = Share::decode(shares[0].as_str()).unwrap();
+        forged.threshold = 255;
+        assert!(ShareSet::default().add(forged.encode().as_str()).is_err());
+        assert!(combine::<&str>(&[]).is_err());
+    }
+
+    #[test]
+    fn test_share_is_not_formatted() {
+        let context = KeypairContext::from_chain(first_chain()).unwrap();
+        let shares = split(context.keypair(), 2, 2).unwrap();
+        let share = Share::decode(shares[0].as_str()).unwrap();
+        let formatted = format!("{share:?} {:?}", shares[0]);
+        assert!(!formatted.contains(shares[0].as_str()), "{formatted}");
+        assert!(!formatted.contains(&format!("{:?}", share.value.expose())), "{formatted}");
+    }
+
+    #[test]
+    fn test_seed_shares() {
+        let seed = SecretBytes::new((0..32).collect());
+        let shares = split_seed(&seed, 3, 5).unwrap();
+
+        let mut set = ShareSet::default();
+        assert_eq!(set.threshold(), None);
+        for (i, share) in shares.iter().skip(1).take(3).enumerate() {
+            assert!(!set.is_complete());
+            set.add(share.as_str()).unwrap();
+            // the same share twice counts once
+            set.add(share.as_str()).unwrap();
+            assert_eq!((set.len(), set.threshold()), (i + 1, Some(3)));
+        }
+        assert!(set.is_complete());
+        assert_eq!(set.seed().unwrap(), seed);
+        assert!(set.keypair().is_err());
+
+        let other = split_seed(&SecretBytes::new(vec![7; 32]), 3, 5).unwrap();
+        assert!(set.add(other[0].as_str()).is_err());
+
+        let mut seeds = ShareSet::default();
+        seeds.add_seed(shares[0].as_str()).unwrap();
+        assert_eq!(seeds.len(), 1);
+
+        // the shares of a key are not the shares of a seed
+        let context = KeypairContext::from_chain(first_chain()).unwrap();
+        let keys = split(context.keypair(), 2, 2).unwrap();
+        let keys: Vec<_> = keys.iter().map(|share| share.as_str()).collect();
+        let error = combine_seed(&keys).err().unwrap();
+        assert!(error.to_string().contains("not shares of a seed"), "{error}");
+        assert!(ShareSet::default().add_seed(keys[0]).is_err());
+
+        let short = split_seed(&SecretBytes::new(vec![7; 16]), 2, 2).unwrap();
+        let short: Vec<_> = short.iter().map(|share| share.as_str()).collect();
+        let error = combine_seed(&short).err().unwrap();
+        assert!(matches!(error, DatabaseError::InvalidShare(_)), "{error}");
+        assert!(ShareSet::default().add_seed(short[0]).is_err());
+    }
+}
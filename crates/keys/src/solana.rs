@@ -1,81 +1,697 @@
-use solana_sdk::bs58;
-use solana_sdk::signature::{Keypair, Signer};
+use std::{borrow::Cow, fmt};
 
-use crate::{Chain, DatabaseError, KeypairStrategy};
+use ed25519_dalek::{
+    Keypair, PublicKey as Ed25519PublicKey, SecretKey as Ed25519SecretKey,
+    Signature as Ed25519Signature, Signer,
+};
+use rand::{rngs::OsRng, RngCore};
+
+use crate::{
+    hd::Curve,
+    matcher::Alphabet,
+    registry::{AddressEncoding, ChainSpec},
+    Chain, DatabaseError, KeypairStrategy, SecretBytes, SecretString, SignScheme, SignedMessage,
+};
+
+/// The prefix of every off-chain message.
+pub const OFFCHAIN_SIGNING_DOMAIN: &[u8] = b"\xffsolana offchain";
+
+/// The domain, version, format and length in front of an off-chain message.
+const OFFCHAIN_HEADER_LEN: usize = OFFCHAIN_SIGNING_DOMAIN.len() + 4;
+
+/// The longest off-chain message a Ledger can display, a 1232 byte packet less the header.
+const OFFCHAIN_MAX_LEDGER_LEN: usize = 1232 - OFFCHAIN_HEADER_LEN;
+
+/// The longest off-chain message.
+const OFFCHAIN_MAX_LEN: usize = u16::MAX as usize - OFFCHAIN_HEADER_LEN;
 
-#[derive(Debug)]
 pub struct SolanaKeyPair(Keypair);
 
 impl SolanaKeyPair {
     pub fn new() -> Self {
-        SolanaKeyPair(Keypair::new())
+        let mut bytes = SecretBytes::new(vec![0u8; 32]);
+        OsRng.fill_bytes(bytes.expose_mut());
+        // any 32 bytes are an ed25519 secret
+        let secret = Ed25519SecretKey::from_bytes(bytes.as_slice()).unwrap();
+        let public = Ed25519PublicKey::from(&secret);
+        SolanaKeyPair(Keypair { secret, public })
     }
 
-    pub fn from_secret(s: &str) -> Self {
-        SolanaKeyPair(Keypair::from_base58_string(s))
+    /// Recover the keypair from the base58 secret and public key.
+    pub fn from_secret(s: &str) -> Result<Self, DatabaseError> {
+        let bytes = bs58::decode(s)
+            .into_vec()
+            .map(SecretBytes::new)
+            .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        Self::from_bytes(bytes.as_slice())
+    }
+
+    /// Recover the keypair from the secret followed by the public key, they must match.
+    pub fn from_bytes(bytes: &[u8]) -> Result<Self, DatabaseError> {
+        let keypair =
+            Keypair::from_bytes(bytes).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+        if Ed25519PublicKey::from(&keypair.secret) != keypair.public {
+            return Err(DatabaseError::SecretError(
+                "the public key does not match the secret".to_string(),
+            ));
+        }
+        Ok(SolanaKeyPair(keypair))
     }
 }
 
+impl fmt::Debug for SolanaKeyPair {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("SolanaKeyPair").field(&self.pubkey()).finish()
+    }
+}
+
+/// Decode base58 into exactly `N` bytes.
+fn decode_base58<const N: usize>(what: &str, value: &str) -> Result<[u8; N], DatabaseError> {
+    let mut bytes = [0u8; N];
+    match bs58::decode(value).onto(&mut bytes) {
+        Ok(len) if len == N => Ok(bytes),
+        Ok(len) => Err(DatabaseError::InvalidSignature(format!(
+            "{what}`{value}`: {len} bytes, expected {N}"
+        ))),
+        Err(e) => Err(DatabaseError::InvalidSignature(format!("{what}`{value}`: {e}"))),
+    }
+}
+
+/// Parse a base58 pubkey and signature.
+fn parse_signed(public: &str, signature: &str) -> Result<([u8; 32], [u8; 64]), DatabaseError> {
+    Ok((decode_base58("pubkey ", public)?, decode_base58("", signature)?))
+}
+
+/// Strictly verify a signature, keys that are not valid curve points verify nothing.
+fn verify_strict(public: &[u8; 32], signature: &[u8; 64], message: &[u8]) -> bool {
+    let Ok(public) = Ed25519PublicKey::from_bytes(public) else {
+        return false;
+    };
+    Ed25519Signature::from_bytes(signature)
+        .is_ok_and(|signature| public.verify_strict(message, &signature).is_ok())
+}
+
+/// The off-chain message format of the text, checked against the limits of each format.
+fn offchain_format(message: &[u8]) -> Result<u8, &'static str> {
+    let printable = message.iter().all(|byte| (0x20..=0x7e).contains(byte));
+    let utf8 = std::str::from_utf8(message).is_ok();
+    match message.len() {
+        0 => Err("the message is empty"),
+        len if len <= OFFCHAIN_MAX_LEDGER_LEN && printable => Ok(0),
+        len if len <= OFFCHAIN_MAX_LEDGER_LEN && utf8 => Ok(1),
+        len if len <= OFFCHAIN_MAX_LEN && utf8 => Ok(2),
+        len if len <= OFFCHAIN_MAX_LEN => Err("the message is not UTF-8"),
+        _ => Err("the message is too long"),
+    }
+}
+
+/// Check the version, format and length of a wrapped message and return its format and text.
+fn parse_offchain(wrapped: &[u8]) -> Result<(u8, &[u8]), &'static str> {
+    let [version, format, len @ ..] = wrapped else {
+        return Err("the header is truncated");
+    };
+    if *version != 0 {
+        return Err("only version 0 is supported");
+    }
+    let (len, message) = match len {
+        [low, high, message @ ..] => (u16::from_le_bytes([*low, *high]) as usize, message),
+        _ => return Err("the header is truncated"),
+    };
+    if len != message.len() {
+        return Err("the length does not match the message");
+    }
+    // a message is valid under a wider format than the one it needs, not a narrower one
+    match offchain_format(message)? {
+        needed if needed <= *format && *format <= 2 => Ok((*format, message)),
+        _ => Err("the message does not fit its format"),
+    }
+}
+
+/// Wrap a message in the version 0 off-chain message envelope, or check one already wrapped.
+pub fn offchain_envelope(message: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+    let (format, text) = match message.strip_prefix(OFFCHAIN_SIGNING_DOMAIN) {
+        Some(wrapped) => parse_offchain(wrapped),
+        None => offchain_format(message).map(|format| (format, message)),
+    }
+    .map_err(|e| DatabaseError::InvalidMessage(format!("off-chain message: {e}")))?;
+
+    let mut envelope = Vec::with_capacity(OFFCHAIN_HEADER_LEN + text.len());
+    envelope.extend_from_slice(OFFCHAIN_SIGNING_DOMAIN);
+    envelope.extend_from_slice(&[0, format]);
+    envelope.extend_from_slice(&(text.len() as u16).to_le_bytes());
+    envelope.extend_from_slice(text);
+    Ok(envelope)
+}
+
+/// Reads the wire format of transactions.
+struct Wire<'a> {
+    bytes: &'a [u8],
+    at: usize,
+}
+
+impl<'a> Wire<'a> {
+    fn take(&mut self, len: usize) -> Option<&'a [u8]> {
+        let taken = self.bytes.get(self.at..self.at.checked_add(len)?)?;
+        self.at += len;
+        Some(taken)
+    }
+
+    fn byte(&mut self) -> Option<u8> {
+        self.take(1).map(|bytes| bytes[0])
+    }
+
+    /// A compact-u16 length, 7 bits per byte, rejecting the non-canonical encodings.
+    fn len(&mut self) -> Option<usize> {
+        let mut value = 0usize;
+        for i in 0..3 {
+            let byte = self.byte()?;
+            value |= ((byte & 0x7f) as usize) << (7 * i);
+            if byte & 0x80 == 0 {
+                return (i == 0 || byte != 0).then_some(value).filter(|v| *v <= u16::MAX as usize);
+            }
+        }
+        None
+    }
+
+    /// A length followed by as many items of `size` bytes.
+    fn items(&mut self, size: usize) -> Option<&'a [u8]> {
+        let len = self.len()?;
+        self.take(len.checked_mul(size)?)
+    }
+
+    fn done(&self) -> bool {
+        self.at == self.bytes.len()
+    }
+}
+
+/// Read a sanitized legacy or v0 message, returning its required signatures and static keys.
+fn read_message(wire: &mut Wire<'_>) -> Option<(usize, usize)> {
+    let mut prefix = wire.byte()?;
+    let versioned = prefix & 0x80 != 0;
+    if versioned {
+        // only v0 exists
+        if prefix != 0x80 {
+            return None;
+        }
+        prefix = wire.byte()?;
+    }
+    let (required, readonly_signed, readonly_unsigned) =
+        (prefix as usize, wire.byte()? as usize, wire.byte()? as usize);
+    let keys = wire.items(32)?.len() / 32;
+    wire.take(32)?;
+
+    let mut instructions = Vec::new();
+    for _ in 0..wire.len()? {
+        instructions.push((wire.byte()? as usize, wire.items(1)?));
+        wire.items(1)?;
+    }
+    let mut loaded = 0;
+    if versioned {
+        for _ in 0..wire.len()? {
+            wire.take(32)?;
+            let writable = wire.items(1)?.len();
+            let readonly = wire.items(1)?.len();
+            if writable + readonly == 0 {
+                return None;
+            }
+            loaded += writable + readonly;
+        }
+    }
+
+    let total = keys + loaded;
+    let sane = required + readonly_unsigned <= keys
+        && readonly_signed < required
+        && (!versioned || total <= 256)
+        && instructions.iter().all(|(program, accounts)| {
+            // the payer is never a program, and programs are never loaded from tables
+            *program > 0
+                && *program < keys
+                && accounts.iter().all(|account| (*account as usize) < total)
+        });
+    sane.then_some((required, keys))
+}
+
+/// Whether the bytes are a serialized transaction, or the message its signatures cover.
+pub fn is_transaction(bytes: &[u8]) -> bool {
+    let message = {
+        let mut wire = Wire { bytes, at: 0 };
+        read_message(&mut wire).is_some() && wire.done()
+    };
+    message || {
+        let mut wire = Wire { bytes, at: 0 };
+        wire.items(64).map(|signatures| signatures.len() / 64).is_some_and(|signatures| {
+            read_message(&mut wire).is_some_and(|(required, keys)| {
+                wire.done() && required == signatures && signatures <= keys
+            })
+        })
+    }
+}
+
+/// The bytes a signature covers under the scheme.
+fn signed_bytes(scheme: SignScheme, message: &[u8]) -> Result<Cow<'_, [u8]>, DatabaseError> {
+    match scheme {
+        SignScheme::Raw => Ok(Cow::Borrowed(message)),
+        SignScheme::OffChain => offchain_envelope(message).map(Cow::Owned),
+        _ => Err(DatabaseError::UnsupportedOperation(Chain::Solana, scheme.to_string())),
+    }
+}
+
+impl Default for SolanaKeyPair {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+pub const SPEC: ChainSpec = ChainSpec {
+    chain: Chain::Solana,
+    name: "solana",
+    curve: Curve::Ed25519,
+    encoding: AddressEncoding::Base58,
+    alphabet: Alphabet::Base58,
+    head: "",
+    schemes: &[SignScheme::Raw, SignScheme::OffChain],
+    factory: || Box::new(SolanaKeyPair::new()),
+};
+
 impl KeypairStrategy for SolanaKeyPair {
     fn chain(&self) -> Chain {
         Chain::Solana
     }
 
     fn generate(&mut self) {
-        self.0 = Keypair::new();
+        *self = Self::new();
     }
 
     fn recover_secret(&mut self, secret: &str) -> Result<(), DatabaseError> {
-        self.0 = Keypair::from_base58_string(secret);
+        *self = Self::from_secret(secret)?;
         Ok(())
     }
 
     fn recover_from_bytes(&mut self, bytes: &[u8]) -> Result<(), DatabaseError> {
-        let keypair =
-            Keypair::from_bytes(bytes).map_err(|e| DatabaseError::SecretError(e.to_string()))?;
-        self.0 = keypair;
+        *self = Self::from_bytes(bytes)?;
         Ok(())
     }
 
-    fn to_vec(&self) -> Vec<u8> {
-        self.0.to_bytes().to_vec()
+    fn to_vec(&self) -> SecretBytes {
+        SecretBytes::from_slice(&self.0.to_bytes())
     }
 
-    fn secret(&self) -> String {
-        self.0.to_base58_string()
+    fn secret(&self) -> SecretString {
+        SecretString::new(bs58::encode(self.to_vec().as_slice()).into_string())
     }
 
     fn pubkey(&self) -> String {
-        self.0.pubkey().to_string()
+        bs58::encode(self.0.public.as_bytes()).into_string()
     }
 
     fn address(&self) -> String {
         self.pubkey()
     }
 
-    /// sign message with hex secret u8a
+    /// sign raw bytes, refusing anything that would be a valid transaction signature
     fn sign(&self, message: &[u8]) -> Result<String, DatabaseError> {
-        let signature = self.0.sign_message(message);
-        let signature = bs58::encode(signature).into_string();
+        if is_transaction(message) {
+            return Err(DatabaseError::InvalidMessage(
+                "the payload is a transaction, sign it as a transaction".to_string(),
+            ));
+        }
+        let signature = self.0.sign(message);
+        let signature = bs58::encode(signature.to_bytes()).into_string();
         Ok(signature)
     }
+
+    /// `offchain` signs the message in the off-chain message envelope
+    fn sign_with(&self, scheme: SignScheme, message: &[u8]) -> Result<String, DatabaseError> {
+        match scheme {
+            SignScheme::Raw => self.sign(message),
+            _ => {
+                let signature = self.0.sign(&signed_bytes(scheme, message)?);
+                Ok(bs58::encode(signature.to_bytes()).into_string())
+            }
+        }
+    }
+
+    fn verify(&self, public: &str, message: &[u8], signature: &str) -> Result<bool, DatabaseError> {
+        let (pubkey, signature) = parse_signed(public, signature)?;
+        Ok(verify_strict(&pubkey, &signature, message))
+    }
+
+    fn verify_with(
+        &self,
+        scheme: SignScheme,
+        public: &str,
+        message: &[u8],
+        signature: &str,
+    ) -> Result<bool, DatabaseError> {
+        self.verify(public, &signed_bytes(scheme, message)?, signature)
+    }
+
+    /// verify the signatures in one ed25519 batch, checking them one by one only if it fails
+    fn verify_batch(
+        &self,
+        scheme: SignScheme,
+        signed: &[SignedMessage<'_>],
+    ) -> Result<Vec<bool>, DatabaseError> {
+        let messages = signed
+            .iter()
+            .map(|signed| signed_bytes(scheme, signed.message))
+            .collect::<Result<Vec<_>, _>>()?;
+        let parsed = signed
+            .iter()
+            .map(|signed| parse_signed(signed.public, signed.signature))
+            .collect::<Result<Vec<_>, _>>()?;
+
+        // keys that are not valid curve points fail the whole batch, leave them to the fallback
+        let batch = parsed
+            .iter()
+            .map(|(pubkey, signature)| {
+                let public = Ed25519PublicKey::from_bytes(pubkey).ok()?;
+                let signature = Ed25519Signature::from_bytes(signature).ok()?;
+                Some((public, signature))
+            })
+            .collect::<Option<Vec<_>>>();
+        if let Some(batch) = batch {
+            let (publics, signatures): (Vec<_>, Vec<_>) = batch.into_iter().unzip();
+            let messages: Vec<&[u8]> = messages.iter().map(|message| message.as_ref()).collect();
+            if ed25519_dalek::verify_batch(&messages, &signatures, &publics).is_ok() {
+                return Ok(vec![true; signed.len()]);
+            }
+        }
+
+        Ok(parsed
+            .iter()
+            .zip(&messages)
+            .map(|((pubkey, signature), message)| verify_strict(pubkey, signature, message))
+            .collect())
+    }
+
+    /// sign a bincode serialized legacy or versioned transaction in the slot of the pubkey
+    #[cfg(feature = "solana-tx")]
+    fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+        use solana_sdk::{pubkey::Pubkey, signature::Signature, transaction::VersionedTransaction};
+
+        let mut tx: VersionedTransaction = bincode::deserialize(transaction)
+            .map_err(|e| DatabaseError::TransactionError(e.to_string()))?;
+        let required = tx.message.header().num_required_signatures as usize;
+        let pubkey = Pubkey::from(self.0.public.to_bytes());
+        let index = tx
+            .message
+            .static_account_keys()
+            .iter()
+            .take(required)
+            .position(|key| *key == pubkey)
+            .ok_or_else(|| {
+                DatabaseError::TransactionError(format!("{pubkey} is not a required signer"))
+            })?;
+
+        if tx.signatures.len() != required {
+            tx.signatures.resize(required, Signature::default());
+        }
+        tx.signatures[index] = Signature::from(self.0.sign(&tx.message.serialize()).to_bytes());
+        bincode::serialize(&tx).map_err(|e| DatabaseError::TransactionError(e.to_string()))
+    }
 }
 
 #[cfg(test)]
 mod tests {
 
+    use std::str::FromStr;
+
+    use solana_sdk::{
+        hash::Hash,
+        instruction::{AccountMeta, Instruction},
+        message::{v0, Message, VersionedMessage},
+        offchain_message::OffchainMessage,
+        pubkey::Pubkey,
+        signature::{Keypair as SdkKeypair, Signature, Signer as _},
+        transaction::{Transaction, VersionedTransaction},
+    };
+
     use super::*;
 
+    fn transfer(signers: &[Pubkey]) -> Instruction {
+        let accounts = signers.iter().map(|key| AccountMeta::new(*key, true)).collect();
+        Instruction::new_with_bytes(Pubkey::new_unique(), &[1, 2, 3], accounts)
+    }
+
+    fn sdk_pubkey(keypair: &SolanaKeyPair) -> Pubkey {
+        Pubkey::from(keypair.0.public.to_bytes())
+    }
+
     #[test]
     fn test_generator() {
         let mut strategy = Box::new(SolanaKeyPair::new());
-        let secret: String = strategy.secret();
-        let pairs = Keypair::from_base58_string(secret.as_str());
+        let secret = strategy.secret();
+        let pairs = SdkKeypair::from_base58_string(secret.as_str());
 
         strategy.recover_from_bytes(pairs.to_bytes().as_slice()).unwrap();
 
         assert!(pairs.to_base58_string().eq_ignore_ascii_case(strategy.secret().as_str()));
         assert!(pairs.pubkey().to_string().eq_ignore_ascii_case(strategy.pubkey().as_str()));
         assert!(pairs.pubkey().to_string().eq_ignore_ascii_case(strategy.address().as_str()));
+
+        let signature = strategy.sign(b"hello").unwrap();
+        assert_eq!(signature, pairs.sign_message(b"hello").to_string());
+        assert!(!format!("{strategy:?}").contains(secret.as_str()));
+    }
+
+    #[test]
+    fn test_recover_invalid() {
+        let keypair = SolanaKeyPair::new();
+        let mut bytes = keypair.to_vec();
+        bytes.expose_mut()[63] ^= 1;
+        assert!(SolanaKeyPair::from_bytes(bytes.as_slice()).is_err());
+        assert!(SolanaKeyPair::from_bytes(&bytes.as_slice()[..32]).is_err());
+        assert!(SolanaKeyPair::from_secret("0OIl").is_err());
+    }
+
+    #[test]
+    #[cfg(feature = "solana-tx")]
+    fn test_sign_transaction_slots() {
+        let payer = SolanaKeyPair::new();
+        let cosigner = SolanaKeyPair::new();
+        let signers = [sdk_pubkey(&payer), sdk_pubkey(&cosigner)];
+        let message = Message::new(&[transfer(&signers)], Some(&signers[0]));
+        let tx = Transaction::new_unsigned(message);
+        let bytes = bincode::serialize(&tx).unwrap();
+
+        // co-signer first, the payer slot stays empty
+        let partial = cosigner.sign_transaction(&bytes).unwrap();
+        let tx: VersionedTransaction = bincode::deserialize(&partial).unwrap();
+        assert_eq!(tx.signatures.len(), 2);
+        assert_eq!(tx.signatures[0], Signature::default());
+        assert!(tx.signatures[1].verify(signers[1].as_ref(), &tx.message.serialize()));
+
+        let signed = payer.sign_transaction(&partial).unwrap();
+        let tx: VersionedTransaction = bincode::deserialize(&signed).unwrap();
+        assert!(tx.verify_with_results().into_iter().all(|ok| ok));
+    }
+
+    #[test]
+    #[cfg(feature = "solana-tx")]
+    fn test_sign_versioned_transaction() {
+        let payer = SolanaKeyPair::new();
+        let message = v0::Message::try_compile(
+            &sdk_pubkey(&payer),
+            &[transfer(&[sdk_pubkey(&payer)])],
+            &[],
+            Hash::default(),
+        )
+        .unwrap();
+        let tx =
+            VersionedTransaction { signatures: vec![], message: VersionedMessage::V0(message) };
+        let bytes = bincode::serialize(&tx).unwrap();
+
+        let signed = payer.sign_transaction(&bytes).unwrap();
+        let tx: VersionedTransaction = bincode::deserialize(&signed).unwrap();
+        assert!(tx.verify_with_results().into_iter().all(|ok| ok));
+    }
+
+    #[test]
+    #[cfg(feature = "solana-tx")]
+    fn test_sign_transaction_not_signer() {
+        let payer = SolanaKeyPair::new();
+        let message = Message::new(&[transfer(&[sdk_pubkey(&payer)])], Some(&sdk_pubkey(&payer)));
+        let bytes = bincode::serialize(&Transaction::new_unsigned(message)).unwrap();
+
+        let other = SolanaKeyPair::new();
+        assert!(matches!(other.sign_transaction(&bytes), Err(DatabaseError::TransactionError(_))));
+        assert!(matches!(
+            other.sign_transaction(b"not a transaction"),
+            Err(DatabaseError::TransactionError(_))
+        ));
+    }
+
+    #[test]
+    fn test_verify() {
+        let keypair = SolanaKeyPair::new();
+        let signature = keypair.sign(b"hello").unwrap();
+        let pubkey = keypair.pubkey();
+        assert!(keypair.verify(&pubkey, b"hello", &signature).unwrap());
+        assert!(!keypair.verify(&pubkey, b"world", &signature).unwrap());
+        assert!(!SolanaKeyPair::new().verify(&pubkey, b"world", &signature).unwrap());
+        assert!(keypair.verify("not a pubkey", b"hello", &signature).is_err());
+        assert!(keypair.verify(&pubkey, b"hello", "0OIl").is_err());
+    }
+
+    #[test]
+    fn test_verify_batch() {
+        let keypairs: Vec<_> = (0..8).map(|_| SolanaKeyPair::new()).collect();
+        let messages: Vec<_> = (0..8).map(|i| format!("message {i}")).collect();
+        let signed: Vec<_> = keypairs
+            .iter()
+            .zip(&messages)
+            .map(|(keypair, message)| (keypair.pubkey(), keypair.sign(message.as_bytes()).unwrap()))
+            .collect();
+        let mut batch: Vec<_> = signed
+            .iter()
+            .zip(&messages)
+            .map(|((public, signature), message)| SignedMessage {
+                public,
+                message: message.as_bytes(),
+                signature,
+            })
+            .collect();
+
+        let verifier = SolanaKeyPair::new();
+        assert_eq!(verifier.verify_batch(SignScheme::Raw, &batch).unwrap(), vec![true; 8]);
+
+        batch[3].message = b"tampered";
+        let results = verifier.verify_batch(SignScheme::Raw, &batch).unwrap();
+        assert_eq!(results.iter().filter(|ok| !**ok).count(), 1);
+        assert!(!results[3]);
+
+        assert!(verifier.verify_batch(SignScheme::Personal, &batch).is_err());
+    }
+
+    #[test]
+    fn test_refuse_transaction_payloads() {
+        let payer = SolanaKeyPair::new();
+        let message = Message::new(&[transfer(&[sdk_pubkey(&payer)])], Some(&sdk_pubkey(&payer)));
+        let tx = Transaction::new_unsigned(message.clone());
+
+        for payload in [message.serialize(), bincode::serialize(&tx).unwrap()] {
+            assert!(is_transaction(&payload));
+            assert!(matches!(payer.sign(&payload), Err(DatabaseError::InvalidMessage(_))));
+            // off-chain messages are text, binary payloads cannot be wrapped either
+            assert!(payer.sign_with(SignScheme::OffChain, &payload).is_err());
+        }
+        assert!(!is_transaction(b"hello"));
+        assert!(!is_transaction(&[&message.serialize()[..], b"trailing"].concat()));
+        assert!(payer.sign(b"hello").is_ok());
+    }
+
+    #[test]
+    fn test_is_transaction_matches_sdk() {
+        fn sdk(bytes: &[u8]) -> bool {
+            let exact =
+                |size: bincode::Result<u64>| size.is_ok_and(|size| size == bytes.len() as u64);
+            bincode::deserialize::<VersionedMessage>(bytes).is_ok_and(|message| {
+                exact(bincode::serialized_size(&message)) && message.sanitize().is_ok()
+            }) || bincode::deserialize::<VersionedTransaction>(bytes)
+                .is_ok_and(|tx| exact(bincode::serialized_size(&tx)) && tx.sanitize().is_ok())
+        }
+
+        let payer = sdk_pubkey(&SolanaKeyPair::new());
+        let cosigner = Pubkey::new_unique();
+        let legacy = Message::new(&[transfer(&[payer, cosigner])], Some(&payer));
+        let lookup = solana_sdk::address_lookup_table::AddressLookupTableAccount {
+            key: Pubkey::new_unique(),
+            addresses: vec![Pubkey::new_unique()],
+        };
+        let mut instruction = transfer(&[payer]);
+        instruction.accounts.push(AccountMeta::new(lookup.addresses[0], false));
+        let versioned = VersionedMessage::V0(
+            v0::Message::try_compile(&payer, &[instruction], &[lookup], Hash::new_unique())
+                .unwrap(),
+        );
+
+        let mut payloads = vec![legacy.serialize(), versioned.serialize()];
+        payloads.push(bincode::serialize(&Transaction::new_unsigned(legacy)).unwrap());
+        payloads.push(
+            bincode::serialize(&VersionedTransaction {
+                signatures: vec![Signature::default()],
+                message: versioned,
+            })
+            .unwrap(),
+        );
+        for payload in payloads {
+            assert!(is_transaction(&payload));
+            for len in 0..payload.len() {
+                assert_eq!(is_transaction(&payload[..len]), sdk(&payload[..len]), "{len}");
+            }
+            for at in 0..payload.len() {
+                let mut flipped = payload.clone();
+                flipped[at] ^= 0x81;
+                assert_eq!(is_transaction(&flipped), sdk(&flipped), "{at}");
+            }
+        }
+    }
+
+    #[test]
+    fn test_offchain_message() {
+        let keypair = SolanaKeyPair::new();
+        let pubkey = keypair.pubkey();
+        let signature = keypair.sign_with(SignScheme::OffChain, b"hello").unwrap();
+
+        let envelope = offchain_envelope(b"hello").unwrap();
+        assert!(envelope.starts_with(b"\xffsolana offchain"));
+        // version, format, little-endian length
+        assert_eq!(&envelope[16..20], &[0, 0, 5, 0]);
+        let expected = OffchainMessage::new(0, b"hello").unwrap();
+        assert!(expected
+            .verify(&sdk_pubkey(&keypair), &Signature::from_str(&signature).unwrap())
+            .unwrap());
+
+        assert!(keypair.verify_with(SignScheme::OffChain, &pubkey, b"hello", &signature).unwrap());
+        assert!(keypair.verify_with(SignScheme::OffChain, &pubkey, &envelope, &signature).unwrap());
+        assert!(keypair.verify(&pubkey, &envelope, &signature).unwrap());
+        assert!(!keypair.verify(&pubkey, b"hello", &signature).unwrap());
+
+        let signed = [SignedMessage { public: &pubkey, message: b"hello", signature: &signature }];
+        assert_eq!(keypair.verify_batch(SignScheme::OffChain, &signed).unwrap(), vec![true]);
+
+        assert!(keypair.sign_with(SignScheme::OffChain, b"").is_err());
+        assert!(keypair.sign_with(SignScheme::TypedData, b"hello").is_err());
+    }
+
+    #[test]
+    fn test_offchain_envelope_matches_sdk() {
+        let sdk = |message: &[u8]| {
+            match message.starts_with(OFFCHAIN_SIGNING_DOMAIN) {
+                true => OffchainMessage::deserialize(message),
+                false => OffchainMessage::new(0, message),
+            }
+            .and_then(|envelope| envelope.serialize())
+            .ok()
+        };
+
+        let mut messages = vec![
+            b"hello".to_vec(),
+            "h\u{e9}llo".as_bytes().to_vec(),
+            b"line\nbreak".to_vec(),
+            vec![b'a'; OFFCHAIN_MAX_LEDGER_LEN],
+            vec![b'a'; OFFCHAIN_MAX_LEDGER_LEN + 1],
+            vec![b'a'; OFFCHAIN_MAX_LEN],
+            vec![b'a'; OFFCHAIN_MAX_LEN + 1],
+            vec![0xff, 0xfe],
+            vec![],
+        ];
+        let wrapped: Vec<_> = messages.iter().filter_map(|message| sdk(message)).collect();
+        for envelope in wrapped {
+            // every format byte and version, and a wrong length
+            for (at, value) in [(16, 1), (17, 0), (17, 1), (17, 2), (17, 3), (18, 0)] {
+                let mut changed = envelope.clone();
+                changed[at] = value;
+                messages.push(changed);
+            }
+            messages.push(envelope[..envelope.len() - 1].to_vec());
+            messages.push(envelope);
+        }
+        messages.push(OFFCHAIN_SIGNING_DOMAIN.to_vec());
+
+        for message in messages {
+            assert_eq!(offchain_envelope(&message).ok(), sdk(&message), "{message:?}");
+        }
     }
 }
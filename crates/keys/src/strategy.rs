@@ -0,0 +1,206 @@
+//! The keypair interface every chain implements, and its use with the storage models.
+use std::str::FromStr;
+
+use serde::Deserialize;
+use strum::{Display, EnumString};
+use strum_macros::AsRefStr;
+
+use crate::{registry, Chain, DatabaseError, KeyWithSecret, NewKey, SecretBytes, SecretString};
+
+/// How a message is hashed before it is signed.
+#[derive(
+    AsRefStr,
+    clap::ValueEnum,
+    Clone,
+    Copy,
+    Debug,
+    Default,
+    Eq,
+    PartialEq,
+    EnumString,
+    Display,
+    Deserialize,
+)]
+#[strum(serialize_all = "kebab-case", ascii_case_insensitive)]
+#[serde(rename_all = "kebab-case")]
+pub enum SignScheme {
+    /// The chain's native message signing.
+    #[default]
+    Raw,
+    /// EIP-191 `personal_sign`.
+    #[strum(serialize = "personal", serialize = "eip191")]
+    #[serde(alias = "eip191")]
+    Personal,
+    /// EIP-712 typed data, the message is the JSON `domain`, `types`, `primaryType` and `message`.
+    #[strum(serialize = "typed-data", serialize = "eip712")]
+    #[serde(alias = "eip712")]
+    TypedData,
+    /// Solana off-chain message, the message is wrapped in the signing domain envelope.
+    #[strum(serialize = "off-chain", to_string = "offchain")]
+    #[serde(rename = "offchain", alias = "off-chain")]
+    #[value(name = "offchain")]
+    OffChain,
+}
+
+/// A signature to verify, with the pubkey or address of the signer.
+#[derive(Debug, Clone, Copy)]
+pub struct SignedMessage<'a> {
+    pub public: &'a str,
+    pub message: &'a [u8],
+    pub signature: &'a str,
+}
+
+/// Keypair interface.
+pub trait KeypairStrategy: Send {
+    /// Get the chain.
+    fn chain(&self) -> Chain;
+    /// Generate a new keypair.
+    fn generate(&mut self);
+    /// Recover a keypair from a secret string.
+    fn recover_secret(&mut self, secret: &str) -> Result<(), DatabaseError>;
+    /// Recover a keypair from a bytes.
+    fn recover_from_bytes(&mut self, bytes: &[u8]) -> Result<(), DatabaseError>;
+    /// Get the secret key.
+    fn to_vec(&self) -> SecretBytes;
+    /// Get the secret key.
+    fn secret(&self) -> SecretString;
+    /// Get the public key.
+    fn pubkey(&self) -> String;
+    /// Get the address key.
+    fn address(&self) -> String;
+    /// Sign a message with a external secret.
+    fn sign(&self, message: &[u8]) -> Result<String, DatabaseError>;
+    /// Sign a message with the given scheme, only `Raw` is supported by default.
+    fn sign_with(&self, scheme: SignScheme, message: &[u8]) -> Result<String, DatabaseError> {
+        match scheme {
+            SignScheme::Raw => self.sign(message),
+            _ => Err(DatabaseError::UnsupportedOperation(self.chain(), scheme.to_string())),
+        }
+    }
+    /// Verify a signature in the chain's encoding against a pubkey or an address.
+    /// Returns `Ok(false)` if the signature does not match, an error if it is malformed.
+    fn verify(&self, public: &str, message: &[u8], signature: &str) -> Result<bool, DatabaseError> {
+        let _ = (public, message, signature);
+        Err(DatabaseError::UnsupportedOperation(self.chain(), "verification".to_string()))
+    }
+    /// Verify a signature made with the given scheme, only `Raw` is supported by default.
+    fn verify_with(
+        &self,
+        scheme: SignScheme,
+        public: &str,
+        message: &[u8],
+        signature: &str,
+    ) -> Result<bool, DatabaseError> {
+        match scheme {
+            SignScheme::Raw => self.verify(public, message, signature),
+            _ => Err(DatabaseError::UnsupportedOperation(self.chain(), scheme.to_string())),
+        }
+    }
+    /// Verify several signatures, one result each.
+    fn verify_batch(
+        &self,
+        scheme: SignScheme,
+        signed: &[SignedMessage<'_>],
+    ) -> Result<Vec<bool>, DatabaseError> {
+        signed
+            .iter()
+            .map(|signed| self.verify_with(scheme, signed.public, signed.message, signed.signature))
+            .collect()
+    }
+    /// Sign a serialized transaction, return it with the signature in the signer's slot.
+    fn sign_transaction(&self, transaction: &[u8]) -> Result<Vec<u8>, DatabaseError> {
+        let _ = transaction;
+        Err(DatabaseError::UnsupportedOperation(self.chain(), "transaction signing".to_string()))
+    }
+}
+
+/// Build a storage key from a keypair.
+pub trait NewKeyExt {
+    /// A new key with the keypair's secret, the suffix defaults to the end of the address.
+    fn from_keypair(keypair: &dyn KeypairStrategy, suffix: Option<String>) -> NewKey;
+}
+
+impl NewKeyExt for NewKey {
+    fn from_keypair(keypair: &dyn KeypairStrategy, suffix: Option<String>) -> NewKey {
+        NewKey::new(keypair.chain(), keypair.to_vec(), keypair.pubkey(), keypair.address(), suffix)
+    }
+}
+
+/// Recover the keypair of a stored key.
+pub trait KeyWithSecretExt {
+    /// The keypair of the key's chain, the secret must be decrypted and belong to the pubkey.
+    fn keypair(&self) -> Result<Box<dyn KeypairStrategy>, DatabaseError>;
+    /// Check that a keypair recovered from the secret has the pubkey of the key.
+    fn check_keypair(&self, keypair: &dyn KeypairStrategy) -> Result<(), DatabaseError>;
+}
+
+impl KeyWithSecretExt for KeyWithSecret {
+    fn keypair(&self) -> Result<Box<dyn KeypairStrategy>, DatabaseError> {
+        let chain = Chain::from_str(self.key.chain.as_str())
+            .map_err(|_| DatabaseError::UnsupportedChain(Chain::Unknown))?;
+        let mut keypair = registry::get(chain)?.keypair();
+        keypair.recover_from_bytes(self.secret().as_slice())?;
+        self.check_keypair(keypair.as_ref())?;
+        Ok(keypair)
+    }
+
+    fn check_keypair(&self, keypair: &dyn KeypairStrategy) -> Result<(), DatabaseError> {
+        if keypair.pubkey() != self.key.pubkey {
+            return Err(DatabaseError::SecretError(format!(
+                "the secret does not belong to the key {}",
+                self.key.pubkey
+            )));
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_sign_scheme() {
+        assert_eq!(SignScheme::default(), SignScheme::Raw);
+        assert_eq!(SignScheme::try_from("eip191"), Ok(SignScheme::Personal));
+        assert_eq!(SignScheme::TypedData.to_string(), "typed-data");
+
+        let scheme: SignScheme = serde_json::from_str("\"eip712\"").expect("invalid scheme");
+        assert_eq!(scheme, SignScheme::TypedData);
+        let scheme: SignScheme = serde_json::from_str("\"personal\"").expect("invalid scheme");
+        assert_eq!(scheme, SignScheme::Personal);
+        let scheme: SignScheme = serde_json::from_str("\"offchain\"").expect("invalid scheme");
+        assert_eq!(scheme, SignScheme::OffChain);
+        assert_eq!(SignScheme::OffChain.to_string(), "offchain");
+    }
+
+    #[cfg(feature = "solana")]
+    #[test]
+    fn test_keypair_checks_pubkey() {
+        let spec = registry::get(Chain::Solana).expect("solana is registered");
+        let (mut owner, mut other) = (spec.keypair(), spec.keypair());
+        owner.generate();
+        other.generate();
+
+        let row = |pubkey: String| {
+            let key = serde_json::from_value(serde_json::json!({
+                "id": 1,
+                "chain": "solana",
+                "secret": [],
+                "pubkey": pubkey,
+                "address": owner.address(),
+                "suffix": "",
+                "usedAt": null,
+                "createdAt": null,
+                "pattern": null,
+            }))
+            .expect("invalid key");
+            KeyWithSecret::new(key, owner.to_vec())
+        };
+
+        let key = row(owner.pubkey());
+        assert_eq!(key.keypair().expect("the secret is the key's").pubkey(), owner.pubkey());
+        assert!(key.check_keypair(other.as_ref()).is_err());
+        assert!(matches!(row(other.pubkey()).keypair(), Err(DatabaseError::SecretError(_))));
+    }
+}
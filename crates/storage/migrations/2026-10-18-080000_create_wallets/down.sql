@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+-- DropIndex
+DROP INDEX IF EXISTS "wallets_chain_idx";
+
+-- DropTable
+DROP TABLE IF EXISTS "wallets";
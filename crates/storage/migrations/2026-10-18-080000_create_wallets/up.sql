@@ -0,0 +1,13 @@
+-- Your SQL goes here
+
+-- CreateTable
+CREATE TABLE IF NOT EXISTS "wallets" (
+    id SERIAL PRIMARY KEY,
+    chain VARCHAR NOT NULL,
+    seed BYTEA NOT NULL,
+    path VARCHAR NOT NULL,
+    created_at TIMESTAMP(3) DEFAULT CURRENT_TIMESTAMP
+);
+
+-- CreateIndex
+CREATE INDEX "wallets_chain_idx" ON "wallets"("chain");
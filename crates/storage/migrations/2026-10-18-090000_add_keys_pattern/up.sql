@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- AlterTable
+ALTER TABLE "keys" ADD COLUMN IF NOT EXISTS pattern VARCHAR;
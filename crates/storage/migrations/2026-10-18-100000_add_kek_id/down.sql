@@ -0,0 +1,8 @@
+-- This file should undo anything in `up.sql`
+
+-- DropIndex
+DROP INDEX IF EXISTS "keys_kek_id_idx";
+
+-- AlterTable
+ALTER TABLE "keys" DROP COLUMN IF EXISTS kek_id;
+ALTER TABLE "wallets" DROP COLUMN IF EXISTS kek_id;
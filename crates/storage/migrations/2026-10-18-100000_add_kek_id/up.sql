@@ -0,0 +1,8 @@
+-- Your SQL goes here
+
+-- AlterTable
+ALTER TABLE "keys" ADD COLUMN IF NOT EXISTS kek_id INTEGER;
+ALTER TABLE "wallets" ADD COLUMN IF NOT EXISTS kek_id INTEGER;
+
+-- CreateIndex
+CREATE INDEX IF NOT EXISTS "keys_kek_id_idx" ON "keys"("kek_id");
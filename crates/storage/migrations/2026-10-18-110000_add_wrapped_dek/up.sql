@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- AlterTable
+ALTER TABLE "keys" ADD COLUMN IF NOT EXISTS wrapped_dek BYTEA;
+ALTER TABLE "wallets" ADD COLUMN IF NOT EXISTS wrapped_dek BYTEA;
@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+-- AlterTable
+ALTER TABLE "keys" DROP COLUMN IF EXISTS key_version;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- AlterTable
+-- 0: the secret is not bound to its row, `anita db rotate-seed` binds it
+ALTER TABLE "keys" ADD COLUMN IF NOT EXISTS key_version INTEGER NOT NULL DEFAULT 0;
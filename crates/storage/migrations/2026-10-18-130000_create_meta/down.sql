@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+-- DropTable
+DROP TABLE IF EXISTS "meta";
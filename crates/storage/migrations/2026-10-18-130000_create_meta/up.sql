@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- CreateTable
+CREATE TABLE IF NOT EXISTS "meta" (
+    key VARCHAR PRIMARY KEY,
+    value BYTEA NOT NULL,
+    kek_id INTEGER,
+    updated_at TIMESTAMP(3) NOT NULL DEFAULT CURRENT_TIMESTAMP
+);
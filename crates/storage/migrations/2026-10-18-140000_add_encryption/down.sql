@@ -0,0 +1,5 @@
+-- This file should undo anything in `up.sql`
+
+-- AlterTable
+ALTER TABLE "keys" DROP COLUMN IF EXISTS encryption;
+ALTER TABLE "wallets" DROP COLUMN IF EXISTS encryption;
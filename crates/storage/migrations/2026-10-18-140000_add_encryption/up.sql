@@ -0,0 +1,9 @@
+-- Your SQL goes here
+
+-- AlterTable
+ALTER TABLE "keys" ADD COLUMN IF NOT EXISTS encryption VARCHAR;
+ALTER TABLE "wallets" ADD COLUMN IF NOT EXISTS encryption VARCHAR;
+
+-- The rows with a KEK id are encrypted, `anita db encrypt-existing` tells the others apart
+UPDATE "keys" SET encryption = 'encrypted' WHERE kek_id IS NOT NULL;
+UPDATE "wallets" SET encryption = 'encrypted' WHERE kek_id IS NOT NULL;
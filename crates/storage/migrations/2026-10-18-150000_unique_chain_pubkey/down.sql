@@ -0,0 +1,7 @@
+-- This file should undo anything in `up.sql`
+
+-- DropIndex
+DROP INDEX IF EXISTS "keys_chain_pubkey_key";
+
+-- CreateIndex
+CREATE UNIQUE INDEX IF NOT EXISTS "keys_secret" ON "keys"("secret");
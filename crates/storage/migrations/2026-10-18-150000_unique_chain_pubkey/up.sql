@@ -0,0 +1,27 @@
+-- Your SQL goes here
+
+-- The same keypair may have been saved twice, the ciphertexts of its secret differing
+DO $$
+DECLARE
+    duplicates TEXT;
+BEGIN
+    SELECT string_agg(format('%s %s (ids %s)', chain, pubkey, ids), ', ')
+    INTO duplicates
+    FROM (
+        SELECT chain, pubkey, string_agg(id::TEXT, ', ' ORDER BY id) AS ids
+        FROM "keys"
+        GROUP BY chain, pubkey
+        HAVING count(*) > 1
+    ) AS duplicated;
+
+    IF duplicates IS NOT NULL THEN
+        RAISE EXCEPTION 'keys saved more than once: %', duplicates
+            USING HINT = 'keep one row of each key, the used one if any, and run the migration again';
+    END IF;
+END $$;
+
+-- DropIndex
+DROP INDEX IF EXISTS "keys_secret";
+
+-- CreateIndex
+CREATE UNIQUE INDEX IF NOT EXISTS "keys_chain_pubkey_key" ON "keys"("chain", "pubkey");
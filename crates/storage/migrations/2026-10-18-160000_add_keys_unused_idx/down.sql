@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+-- DropIndex
+DROP INDEX IF EXISTS "keys_unused_chain_suffix_idx";
@@ -0,0 +1,4 @@
+-- Your SQL goes here
+
+-- CreateIndex
+CREATE INDEX IF NOT EXISTS "keys_unused_chain_suffix_idx" ON "keys"("chain", "suffix", "id") WHERE used_at IS NULL;
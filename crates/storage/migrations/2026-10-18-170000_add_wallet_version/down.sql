@@ -0,0 +1,4 @@
+-- This file should undo anything in `up.sql`
+
+-- AlterTable
+ALTER TABLE "wallets" DROP COLUMN IF EXISTS wallet_version;
@@ -0,0 +1,5 @@
+-- Your SQL goes here
+
+-- AlterTable
+-- 0: the seed is not bound to its row, `anita db rotate-seed` binds it
+ALTER TABLE "wallets" ADD COLUMN IF NOT EXISTS wallet_version INTEGER NOT NULL DEFAULT 0;
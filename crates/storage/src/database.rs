@@ -1,40 +1,156 @@
+use std::sync::{Arc, PoisonError, RwLock, RwLockReadGuard};
+
 use async_trait::async_trait;
 
 use crate::{
     handlers::{
-        keys::{create_key, get_key_by_suffix, get_secret_by_pubkey},
+        keys::{
+            count_keys_by_encryption, count_unrotated_keys, create_key, create_keys, encrypt_keys,
+            get_encrypted_key, get_key_by_suffix, get_secret_by_pubkey, rotate_keys,
+        },
+        meta::{get_meta, set_meta},
         users::{get_auth_by_email, get_user_by_id},
+        wallets::{
+            count_unrotated_wallets, count_wallets_by_encryption, create_wallet, encrypt_wallets,
+            get_encrypted_wallet, get_wallet_by_id, next_wallet_id, rotate_wallets,
+        },
     },
     init_db,
-    models::{Auth, Chain, Key, KeyWithSecret, NewKey, User},
+    models::{
+        Auth, Chain, Detected, DetectedBatch, EncryptionMode, EncryptionReport, Key, KeyWithSecret,
+        Meta, NewKey, NewWallet, PlaintextCheck, RotatedBatch, SecretBytes, SecretRow, SecretTable,
+        User, Wallet, CANARY, CANARY_KEY, ENCRYPTION_KEY,
+    },
     pg::DbPool,
-    tracing,
-    utils::encryption::{decrypt, encrypt, to_seed},
+    tracing::{self, warn},
+    utils::{
+        encryption::to_seed,
+        kek::{self, unwrap_secret, wrap_secret, Envelope, LocalKek, SharedKek},
+    },
     DatabaseError, DbConnection,
 };
 
-pub use crate::models::{KeyTrait, UserTrait};
+pub use crate::models::{
+    EncryptionTrait, KeyTrait, MetaTrait, RotationTrait, UserTrait, WalletTrait,
+};
+
+/// The key-encryption key the data keys of the secrets are wrapped with.
+#[derive(Default)]
+enum Kek {
+    /// No KEK, the secrets are stored as they are.
+    #[default]
+    Unset,
+    /// The seed is not known yet, the secrets can be neither read nor written.
+    Sealed,
+    Unsealed(SharedKek),
+}
 
 #[derive(Clone)]
 pub struct Database {
     pool: DbPool,
-    seed: Option<Vec<u8>>,
+    /// Shared by the clones, unsealing one unseals them all.
+    kek: Arc<RwLock<Kek>>,
+    /// The KEKs used before the current one, only to decrypt the secrets not rotated yet.
+    previous: Arc<[SharedKek]>,
 }
 
 impl Database {
     /// Create a new database connection pool with the given pool.
-    pub fn new_pool(pool: DbPool, seed: Option<Vec<u8>>) -> Self {
-        Self { pool, seed }
+    pub fn new_pool(pool: DbPool, kek: Option<SharedKek>) -> Self {
+        let kek = kek.map_or(Kek::Unset, Kek::Unsealed);
+        Self { pool, kek: Arc::new(RwLock::new(kek)), previous: Arc::new([]) }
+    }
+
+    /// Keep decrypting the secrets encrypted with older KEKs, until `anita db rotate-seed`
+    /// wraps their data keys with the current one.
+    pub fn with_previous_keks(mut self, keks: Vec<SharedKek>) -> Self {
+        self.previous = keks.into();
+        self
+    }
+
+    /// Start sealed, the secrets are refused until [`Database::unseal`] is given the seed.
+    pub fn sealed(self) -> Self {
+        *self.kek.write().unwrap_or_else(PoisonError::into_inner) = Kek::Sealed;
+        self
+    }
+
+    /// Whether the database is waiting for its seed.
+    pub fn is_sealed(&self) -> bool {
+        matches!(*self.kek(), Kek::Sealed)
+    }
+
+    /// An error if the database is sealed.
+    pub fn check_unsealed(&self) -> Result<(), DatabaseError> {
+        match self.is_sealed() {
+            true => Err(DatabaseError::Sealed),
+            false => Ok(()),
+        }
+    }
+
+    /// Set the seed of a sealed database, once it is checked against the canary.
+    pub async fn unseal(&self, seed: SecretBytes) -> Result<(), DatabaseError> {
+        if !self.is_sealed() {
+            return Err(DatabaseError::SecretError("the database is not sealed".to_string()));
+        }
+        let kek: SharedKek = Arc::new(LocalKek::from_seed(seed));
+        let canary = self.canary().await?.ok_or_else(missing_canary)?;
+        let keks: Vec<_> = std::iter::once(&kek).chain(self.previous.iter()).cloned().collect();
+        check_canary(&keks, &canary).await?;
+
+        let mut current = self.kek.write().unwrap_or_else(PoisonError::into_inner);
+        match *current {
+            Kek::Sealed => {
+                *current = Kek::Unsealed(kek);
+                Ok(())
+            }
+            _ => Err(DatabaseError::SecretError("the database is not sealed".to_string())),
+        }
     }
 
-    pub fn to_seed(seed: &str) -> Result<Vec<u8>, DatabaseError> {
+    fn kek(&self) -> RwLockReadGuard<'_, Kek> {
+        self.kek.read().unwrap_or_else(PoisonError::into_inner)
+    }
+
+    pub fn to_seed(seed: &str) -> Result<SecretBytes, DatabaseError> {
         to_seed(seed)
     }
 
-    /// Create a new database connection pool with the given URL.
-    pub async fn new_with_url(url: &str, seed: Option<Vec<u8>>) -> Self {
+    /// A KEK from a hex seed, `file:<path>`, `passphrase:<salt>` or `transit:<url>`,
+    /// see [`kek::from_spec`].
+    pub fn to_kek(spec: &str) -> Result<SharedKek, DatabaseError> {
+        kek::from_spec(spec)
+    }
+
+    /// Create a new database connection pool with the given URL, without checking the KEK.
+    pub async fn connect(url: &str, kek: Option<SharedKek>) -> Self {
         let db = init_db(url).await;
-        Self::new_pool(db, seed)
+        Self::new_pool(db, kek)
+    }
+
+    /// Create a new database connection pool with the given URL.
+    /// The KEK, or one of the previous KEKs, must be the one the database is initialized with.
+    pub async fn new_with_url(
+        url: &str,
+        kek: Option<SharedKek>,
+        previous: Vec<SharedKek>,
+    ) -> Result<Self, DatabaseError> {
+        let database = Self::connect(url, kek).await.with_previous_keks(previous);
+        database.check_seed().await?;
+        Ok(database)
+    }
+
+    /// Create a new database connection pool with the given URL, sealed.
+    /// The database must be initialized with a seed, which is checked when it is unsealed.
+    pub async fn new_sealed(url: &str, previous: Vec<SharedKek>) -> Result<Self, DatabaseError> {
+        let database = Self::connect(url, None).await.with_previous_keks(previous).sealed();
+        if database.encryption_mode().await? != Some(EncryptionMode::Encrypted) {
+            return Err(DatabaseError::SeedMismatch(
+                "a sealed database must be encrypted, run `anita db init-seed` with its seed"
+                    .to_string(),
+            ));
+        }
+        database.canary().await?.ok_or_else(missing_canary)?;
+        Ok(database)
     }
 
     /// Get a connection from the pool.
@@ -42,6 +158,141 @@ impl Database {
         let pool = self.pool.get().await?;
         Ok(pool)
     }
+
+    /// The current KEK first, then the previous ones.
+    fn keks(&self) -> Result<Vec<SharedKek>, DatabaseError> {
+        match &*self.kek() {
+            Kek::Unsealed(current) => {
+                Ok(std::iter::once(current).chain(self.previous.iter()).cloned().collect())
+            }
+            Kek::Sealed => Err(DatabaseError::Sealed),
+            Kek::Unset => Err(DatabaseError::SecretError("the seed is not set".to_string())),
+        }
+    }
+
+    /// Encrypt a secret with a new data key wrapped by the KEK, or return it as is if the KEK is
+    /// not set.
+    async fn encrypt_secret(
+        &self,
+        secret: &SecretBytes,
+        aad: &[u8],
+    ) -> Result<Envelope, DatabaseError> {
+        let kek = match &*self.kek() {
+            Kek::Unsealed(kek) => kek.clone(),
+            Kek::Sealed => return Err(DatabaseError::Sealed),
+            Kek::Unset => return Ok(Envelope::plain(secret)),
+        };
+        wrap_secret(kek.as_ref(), secret, aad).await
+    }
+
+    /// The canary wrapped by the KEK the database is initialized with.
+    async fn canary(&self) -> Result<Option<Vec<u8>>, DatabaseError> {
+        let mut conn = self.with_conn().await?;
+        let canary = get_meta(&mut conn, CANARY_KEY).await?;
+        Ok(canary.map(|canary| canary.value))
+    }
+
+    /// The first encrypted key and wallet, with their associated data.
+    async fn encrypted_samples(&self) -> Result<Vec<(Envelope, Vec<u8>)>, DatabaseError> {
+        let mut conn = self.with_conn().await?;
+        let mut samples = vec![];
+        if let Some(key) = get_encrypted_key(&mut conn).await? {
+            let (wrapped_dek, kek_id) = (key.key.wrapped_dek.clone(), key.key.kek_id);
+            samples.push((
+                Envelope { secret: key.secret().clone(), wrapped_dek, kek_id },
+                key.key.aad(),
+            ));
+        }
+        if let Some(wallet) = get_encrypted_wallet(&mut conn).await? {
+            let (wrapped_dek, kek_id) = (wallet.wrapped_dek.clone(), wallet.kek_id);
+            samples.push((
+                Envelope { secret: wallet.seed().clone(), wrapped_dek, kek_id },
+                wallet.aad(),
+            ));
+        }
+        Ok(samples)
+    }
+
+    /// Decrypt a secret with the KEK of `kek_id`, or return it as is if the KEK is not set.
+    async fn decrypt_secret(
+        &self,
+        secret: &SecretBytes,
+        wrapped_dek: Option<&[u8]>,
+        kek_id: Option<i32>,
+        aad: &[u8],
+    ) -> Result<SecretBytes, DatabaseError> {
+        if kek_id.is_none() && matches!(*self.kek(), Kek::Unset) {
+            return Ok(secret.clone());
+        }
+        decrypt_with(&self.keks()?, secret, wrapped_dek, kek_id, aad).await
+    }
+}
+
+/// An error unless one of the KEKs unwraps the canary.
+async fn check_canary(keks: &[SharedKek], canary: &[u8]) -> Result<(), DatabaseError> {
+    let expected = SecretBytes::new(CANARY.to_vec());
+    for kek in keks {
+        if kek.unwrap(canary).await.map_or(false, |original| original == expected) {
+            return Ok(());
+        }
+    }
+    Err(DatabaseError::SeedMismatch(
+        "the database is initialized with another seed or KEK".to_string(),
+    ))
+}
+
+fn missing_canary() -> DatabaseError {
+    DatabaseError::SeedMismatch("the canary is missing, run `anita db init-seed`".to_string())
+}
+
+fn find_kek(keks: &[SharedKek], id: i32) -> Result<&SharedKek, DatabaseError> {
+    keks.iter()
+        .find(|kek| kek.id() == id)
+        .ok_or_else(|| DatabaseError::SecretError(format!("no KEK with the id {}", id)))
+}
+
+/// Decrypt with the KEK of `kek_id`, secrets written before KEKs had ids are tried with
+/// each KEK in turn.
+async fn decrypt_with(
+    keks: &[SharedKek],
+    secret: &SecretBytes,
+    wrapped_dek: Option<&[u8]>,
+    kek_id: Option<i32>,
+    aad: &[u8],
+) -> Result<SecretBytes, DatabaseError> {
+    if let Some(id) = kek_id {
+        return unwrap_secret(find_kek(keks, id)?.as_ref(), secret, wrapped_dek, aad).await;
+    }
+    for kek in keks {
+        if let Ok(original) = unwrap_secret(kek.as_ref(), secret, wrapped_dek, aad).await {
+            return Ok(original);
+        }
+    }
+    Err(DatabaseError::SecretError("none of the KEKs decrypts the secret".to_string()))
+}
+
+/// Wrap the data key of a secret with the current KEK, the first one.
+/// The secrets encrypted before data keys, or bound to other associated data than `bound`, are
+/// encrypted again.
+async fn rewrap(
+    keks: &[SharedKek],
+    saved: Envelope,
+    aad: Vec<u8>,
+    bound: Vec<u8>,
+) -> Result<Envelope, DatabaseError> {
+    let current = keks[0].as_ref();
+    match (&saved.wrapped_dek, saved.kek_id) {
+        (Some(wrapped), Some(id)) if aad == bound => {
+            let dek = find_kek(keks, id)?.unwrap(wrapped).await?;
+            let wrapped_dek = Some(current.wrap(&dek).await?);
+            Ok(Envelope { secret: saved.secret, wrapped_dek, kek_id: Some(current.id()) })
+        }
+        (wrapped, kek_id) => {
+            let original =
+                decrypt_with(keks, &saved.secret, wrapped.as_deref(), kek_id, &aad).await?;
+            wrap_secret(current, &original, &bound).await
+        }
+    }
 }
 
 #[async_trait]
@@ -74,24 +325,38 @@ impl KeyTrait for Database {
     }
 
     /// Create a key.
-    /// If the seed is set, the secret will be encrypted with the seed.
+    /// If the KEK is set, the secret will be encrypted with a data key wrapped by it.
     /// Otherwise, the secret will be stored in plain text.
+    /// A key already saved is returned as it is.
     async fn create_key(&self, key: NewKey) -> Result<Key, DatabaseError> {
-        let mut conn = self.with_conn().await?;
         let mut key = key;
-        if let Some(seed) = self.seed.clone() {
-            let secret_bytes = key.get_secret();
-            let encrypted = encrypt(seed.as_slice(), secret_bytes.as_slice())
-                .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
-            key.set_secret(encrypted.as_slice());
-        }
-        let saved = create_key(&mut conn, key.clone()).await?;
+        let encrypted = self.encrypt_secret(key.get_secret(), &key.aad()).await?;
+        key.set_secret(encrypted);
+        let mut conn = self.with_conn().await?;
+        let saved = create_key(&mut conn, key).await?;
         Ok(saved)
     }
 
+    /// Create multiple keys in one insert.
+    /// The secrets are encrypted like in `create_key`, keys already saved are skipped.
+    async fn create_keys(&self, keys: Vec<NewKey>) -> Result<Vec<i32>, DatabaseError> {
+        let mut keys = keys;
+        for key in keys.iter_mut() {
+            let encrypted = self.encrypt_secret(key.get_secret(), &key.aad()).await?;
+            key.set_secret(encrypted);
+        }
+        if keys.is_empty() {
+            return Ok(vec![]);
+        }
+
+        let mut conn = self.with_conn().await?;
+        let inserted = create_keys(&mut conn, keys).await?;
+        Ok(inserted)
+    }
+
     /// Get a key by pubkey.
-    /// If the seed is set, the secret will be decrypted with the seed.
-    /// Otherwise, the secret will be returned as is.
+    /// If the KEK is set, the secret will be decrypted with its data key.
+    /// Otherwise, or if the row is marked as plaintext, the secret will be returned as is.
     async fn get_secret_by_pubkey(
         &self,
         chain: Chain,
@@ -99,13 +364,327 @@ impl KeyTrait for Database {
     ) -> Result<Option<KeyWithSecret>, DatabaseError> {
         let mut conn = self.with_conn().await?;
         let mut key = get_secret_by_pubkey(&mut conn, chain, pubkey.to_string()).await?;
-        if let Some(key) = key.as_mut() {
-            if let Some(seed) = self.seed.clone() {
-                let original = decrypt(seed.as_slice(), key.secret().as_slice())
-                    .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
-                key.set_secret(&original);
-            }
+        if let Some(key) = key.as_mut().filter(|key| !key.key.is_plaintext()) {
+            let (wrapped_dek, kek_id) = (key.key.wrapped_dek.as_deref(), key.key.kek_id);
+            let original =
+                self.decrypt_secret(key.secret(), wrapped_dek, kek_id, &key.key.aad()).await?;
+            key.set_secret(original);
         }
         Ok(key)
     }
 }
+
+#[async_trait]
+impl WalletTrait for Database {
+    /// Create a wallet.
+    /// If the KEK is set, the wallet seed will be encrypted like the secret of a key, bound to the
+    /// id reserved for the wallet.
+    async fn create_wallet(&self, wallet: NewWallet) -> Result<Wallet, DatabaseError> {
+        let mut wallet = wallet;
+        let mut conn = self.with_conn().await?;
+        let id = next_wallet_id(&mut conn).await?;
+        drop(conn);
+        let encrypted = self.encrypt_secret(wallet.get_seed(), &wallet.aad(id)).await?;
+        wallet.id = Some(id);
+        wallet.set_seed(encrypted);
+        let mut conn = self.with_conn().await?;
+        let saved = create_wallet(&mut conn, wallet).await?;
+        Ok(saved)
+    }
+
+    /// Get a wallet by id.
+    /// If the KEK is set, the wallet seed will be decrypted with its data key.
+    async fn get_wallet_by_id(&self, id: i32) -> Result<Option<Wallet>, DatabaseError> {
+        let mut conn = self.with_conn().await?;
+        let mut wallet = get_wallet_by_id(&mut conn, id).await?;
+        if let Some(wallet) = wallet.as_mut().filter(|wallet| !wallet.is_plaintext()) {
+            let original = self
+                .decrypt_secret(
+                    wallet.seed(),
+                    wallet.wrapped_dek.as_deref(),
+                    wallet.kek_id,
+                    &wallet.aad(),
+                )
+                .await?;
+            wallet.set_seed(original);
+        }
+        Ok(wallet)
+    }
+}
+
+#[async_trait]
+impl MetaTrait for Database {
+    async fn check_seed(&self) -> Result<(), DatabaseError> {
+        let Some(mode) = self.encryption_mode().await? else {
+            if !self.encrypted_samples().await?.is_empty() {
+                return Err(DatabaseError::SeedMismatch(
+                    "the secrets are encrypted but their seed is not recorded, run `anita db \
+                     init-seed` with it"
+                        .to_string(),
+                ));
+            }
+            warn!("the seed is not recorded, run `anita db init-seed` to check it at startup");
+            return Ok(());
+        };
+
+        match (mode, &*self.kek()) {
+            (EncryptionMode::Plaintext, Kek::Unset) => return Ok(()),
+            (EncryptionMode::Plaintext, _) => {
+                return Err(DatabaseError::SeedMismatch(
+                    "the secrets are stored in plaintext, start unsealed without a seed or KEK"
+                        .to_string(),
+                ))
+            }
+            (EncryptionMode::Encrypted, Kek::Unset) => {
+                return Err(DatabaseError::SeedMismatch(
+                    "the secrets are encrypted, start with their seed or KEK".to_string(),
+                ))
+            }
+            (EncryptionMode::Encrypted, Kek::Sealed) => return Ok(()),
+            (EncryptionMode::Encrypted, Kek::Unsealed(_)) => {}
+        }
+        let canary = self.canary().await?.ok_or_else(missing_canary)?;
+        check_canary(&self.keks()?, &canary).await
+    }
+
+    async fn encryption_mode(&self) -> Result<Option<EncryptionMode>, DatabaseError> {
+        let mut conn = self.with_conn().await?;
+        let Some(mode) = get_meta(&mut conn, ENCRYPTION_KEY).await? else {
+            return Ok(None);
+        };
+        let mode =
+            String::from_utf8_lossy(&mode.value).parse::<EncryptionMode>().map_err(|_| {
+                DatabaseError::SeedMismatch(
+                    "unknown encryption mode, run `anita db init-seed`".into(),
+                )
+            })?;
+        Ok(Some(mode))
+    }
+
+    async fn init_seed(&self, force: bool) -> Result<EncryptionMode, DatabaseError> {
+        let mut conn = self.with_conn().await?;
+        if !force && get_meta(&mut conn, ENCRYPTION_KEY).await?.is_some() {
+            return Err(DatabaseError::SeedMismatch(
+                "the seed is already recorded, use --force to replace it".to_string(),
+            ));
+        }
+        drop(conn);
+
+        let (mode, canary) = match &*self.kek() {
+            Kek::Unset => (EncryptionMode::Plaintext, None),
+            Kek::Sealed => return Err(DatabaseError::Sealed),
+            Kek::Unsealed(kek) => (EncryptionMode::Encrypted, Some(kek.clone())),
+        };
+        // the record is trusted from then on, so it must fit the secrets already stored
+        let samples = self.encrypted_samples().await?;
+        if canary.is_none() && !samples.is_empty() {
+            return Err(DatabaseError::SeedMismatch(
+                "the secrets are encrypted, record their seed or KEK".to_string(),
+            ));
+        }
+        if canary.is_some() {
+            let keks = self.keks()?;
+            for (saved, aad) in samples {
+                let wrapped_dek = saved.wrapped_dek.as_deref();
+                decrypt_with(&keks, &saved.secret, wrapped_dek, saved.kek_id, &aad).await.map_err(
+                    |_| {
+                        DatabaseError::SeedMismatch(
+                            "the seed or KEK does not decrypt the stored secrets".to_string(),
+                        )
+                    },
+                )?;
+            }
+        }
+        let mut values = vec![Meta {
+            key: ENCRYPTION_KEY.to_string(),
+            value: mode.to_string().into_bytes(),
+            kek_id: None,
+        }];
+        if let Some(kek) = canary {
+            let value = kek.wrap(&SecretBytes::new(CANARY.to_vec())).await?;
+            values.push(Meta { key: CANARY_KEY.to_string(), value, kek_id: Some(kek.id()) });
+        }
+
+        let mut conn = self.with_conn().await?;
+        set_meta(&mut conn, values).await?;
+        Ok(mode)
+    }
+}
+
+#[async_trait]
+impl EncryptionTrait for Database {
+    async fn count_by_encryption(
+        &self,
+        table: SecretTable,
+    ) -> Result<EncryptionReport, DatabaseError> {
+        let mut conn = self.with_conn().await?;
+        let counts = match table {
+            SecretTable::Keys => count_keys_by_encryption(&mut conn).await?,
+            SecretTable::Wallets => count_wallets_by_encryption(&mut conn).await?,
+        };
+        Ok(EncryptionReport::from_counts(counts))
+    }
+
+    /// The rows marked as plaintext, or found to be by `is_plaintext`, are encrypted with the
+    /// current KEK and bound to their row. The other unmarked rows are marked as encrypted if one
+    /// of the KEKs decrypts them, and reported otherwise.
+    async fn encrypt_batch(
+        &self,
+        table: SecretTable,
+        after: i32,
+        limit: i64,
+        is_plaintext: PlaintextCheck,
+        dry_run: bool,
+    ) -> Result<DetectedBatch, DatabaseError> {
+        let keks: Arc<[SharedKek]> = self.keks()?.into();
+        let detect = move |row: SecretRow| {
+            let (keks, is_plaintext) = (keks.clone(), is_plaintext.clone());
+            async move {
+                let marked = row.encryption.as_deref() == Some(EncryptionMode::Plaintext.as_ref());
+                if !marked && !is_plaintext(&row) {
+                    let SecretRow { saved, aad, .. } = row;
+                    let (wrapped_dek, kek_id) = (saved.wrapped_dek.as_deref(), saved.kek_id);
+                    return match decrypt_with(&keks, &saved.secret, wrapped_dek, kek_id, &aad).await
+                    {
+                        Ok(_) => Detected::Encrypted,
+                        Err(_) => Detected::Unknown,
+                    };
+                }
+                if dry_run {
+                    return Detected::Plaintext(row.saved);
+                }
+                match wrap_secret(keks[0].as_ref(), &row.saved.secret, &row.bound).await {
+                    Ok(envelope) => Detected::Plaintext(envelope),
+                    Err(e) => {
+                        warn!("could not encrypt a secret of {}: {}", table, e);
+                        Detected::Unknown
+                    }
+                }
+            }
+        };
+
+        let mut conn = self.with_conn().await?;
+        let batch = match table {
+            SecretTable::Keys => encrypt_keys(&mut conn, after, limit, dry_run, detect).await?,
+            SecretTable::Wallets => {
+                encrypt_wallets(&mut conn, after, limit, dry_run, detect).await?
+            }
+        };
+        Ok(batch)
+    }
+}
+
+#[async_trait]
+impl RotationTrait for Database {
+    async fn count_unrotated(&self, table: SecretTable) -> Result<i64, DatabaseError> {
+        let kek_id = self.keks()?[0].id();
+        let mut conn = self.with_conn().await?;
+        let count = match table {
+            SecretTable::Keys => count_unrotated_keys(&mut conn, kek_id).await?,
+            SecretTable::Wallets => count_unrotated_wallets(&mut conn, kek_id).await?,
+        };
+        Ok(count)
+    }
+
+    /// Unwrap the data keys with any of the KEKs and wrap them with the current one, the secrets
+    /// are bound to their row on the way.
+    /// The secrets none of them decrypts are reported in the batch and left as they are.
+    async fn rotate_batch(
+        &self,
+        table: SecretTable,
+        after: i32,
+        limit: i64,
+    ) -> Result<RotatedBatch, DatabaseError> {
+        let keks: Arc<[SharedKek]> = self.keks()?.into();
+        let kek_id = keks[0].id();
+        let reencrypt = move |saved, aad, bound| {
+            let keks = keks.clone();
+            async move {
+                rewrap(&keks, saved, aad, bound)
+                    .await
+                    .map_err(|e| warn!("could not re-encrypt a secret of {}: {}", table, e))
+                    .ok()
+            }
+        };
+
+        let mut conn = self.with_conn().await?;
+        let batch = match table {
+            SecretTable::Keys => rotate_keys(&mut conn, kek_id, after, limit, reencrypt).await?,
+            SecretTable::Wallets => {
+                rotate_wallets(&mut conn, kek_id, after, limit, reencrypt).await?
+            }
+        };
+        Ok(batch)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[tokio::test]
+    async fn test_check_canary() {
+        let kek: SharedKek = Arc::new(LocalKek::from_seed(SecretBytes::new(vec![1; 32])));
+        let other: SharedKek = Arc::new(LocalKek::from_seed(SecretBytes::new(vec![2; 32])));
+        let canary = kek.wrap(&SecretBytes::new(CANARY.to_vec())).await.expect("wrap failed");
+
+        assert!(check_canary(&[kek.clone()], &canary).await.is_ok());
+        assert!(check_canary(&[other.clone(), kek], &canary).await.is_ok());
+        assert!(matches!(
+            check_canary(&[other.clone()], &canary).await,
+            Err(DatabaseError::SeedMismatch(_))
+        ));
+
+        let wrong = other.wrap(&SecretBytes::new(b"not the canary".to_vec())).await.unwrap();
+        assert!(check_canary(&[other], &wrong).await.is_err());
+    }
+
+    #[tokio::test(flavor = "multi_thread", worker_threads = 4)]
+    #[ignore]
+    async fn test_concurrent_reservations() {
+        use diesel::prelude::*;
+        use diesel_async::RunQueryDsl;
+
+        use crate::schema::keys;
+
+        const N: usize = 10;
+        dotenvy::dotenv().ok();
+        let database_url = std::env::var("DATABASE_URL").expect("Expected DATABASE_URL to be set");
+        crate::pg::run_migrations(database_url.as_str()).await;
+        let database = Database::connect(database_url.as_str(), None).await;
+
+        // a suffix of its own, the keys already in the database are not reserved
+        let suffix = format!("test-{}", rand::random::<u32>());
+        let keys = (0..N)
+            .map(|i| {
+                let pubkey = format!("{}-{}", suffix, i);
+                let secret = SecretBytes::new(pubkey.as_bytes().to_vec());
+                NewKey::new(Chain::Solana, secret, pubkey.clone(), pubkey, Some(suffix.clone()))
+            })
+            .collect();
+        assert_eq!(database.create_keys(keys).await.expect("could not create keys").len(), N);
+
+        let reservations: Vec<_> = (0..N)
+            .map(|_| {
+                let (database, suffix) = (database.clone(), suffix.clone());
+                tokio::spawn(
+                    async move { database.get_key_by_suffix(Chain::Solana, &suffix).await },
+                )
+            })
+            .collect();
+        let mut ids = std::collections::HashSet::new();
+        for reservation in reservations {
+            let key = reservation.await.unwrap().expect("could not reserve a key");
+            ids.insert(key.expect("every reservation gets a key").id);
+        }
+        assert_eq!(ids.len(), N);
+        let left = database.get_key_by_suffix(Chain::Solana, &suffix).await.unwrap();
+        assert!(left.is_none());
+
+        let mut conn = database.with_conn().await.expect("could not get connection");
+        diesel::delete(keys::table.filter(keys::suffix.eq(&suffix)))
+            .execute(&mut conn)
+            .await
+            .expect("could not delete the keys");
+    }
+}
@@ -1,4 +1,7 @@
-use crate::pg::{DbError, DbRunError};
+use crate::{
+    models::Chain,
+    pg::{DbError, DbRunError},
+};
 
 // https://docs.rs/tracing-error/latest/tracing_error/
 #[derive(Debug, thiserror::Error)]
@@ -12,4 +15,30 @@ pub enum DatabaseError {
     HexError(#[from] hex::FromHexError),
     #[error("invalid secret `{0}`")]
     SecretError(String),
+    #[error("unsupported chain `{0:?}`")]
+    UnsupportedChain(Chain),
+    #[error("{1} is not supported on `{0:?}`")]
+    UnsupportedOperation(Chain, String),
+    #[error("invalid transaction: {0}")]
+    TransactionError(String),
+    #[error("invalid message: {0}")]
+    InvalidMessage(String),
+    #[error("invalid signature: {0}")]
+    InvalidSignature(String),
+    #[error("the database is sealed, unseal it with the shares of its seed")]
+    Sealed,
+    #[error("key encryption key: {0}")]
+    KekError(String),
+    #[error("{0}")]
+    SeedMismatch(String),
+    #[error("invalid share: {0}")]
+    InvalidShare(String),
+    #[error("invalid pattern {0}")]
+    InvalidPattern(String),
+    #[error("vanity search failed: {0}")]
+    SearchError(String),
+    #[error("vanity search cancelled")]
+    SearchCancelled,
+    #[error("vanity search timed out after {0:?}")]
+    SearchTimeout(std::time::Duration),
 }
@@ -1,60 +1,71 @@
-use chrono;
-use diesel::{insert_into, prelude::*, update};
+use std::future::Future;
+
+use diesel::{dsl::count_star, insert_into, prelude::*, update};
 use diesel_async::{AsyncConnection, RunQueryDsl};
 
 use crate::{
     models::Chain,
-    models::{Key, KeyWithSecret, NewKey},
+    models::{
+        key_aad, Detected, DetectedBatch, EncryptionMode, Key, KeyWithSecret, NewKey, RotatedBatch,
+        SecretBytes, SecretRow, KEY_VERSION,
+    },
     schema::keys,
-    tracing, DbConnection, DbError,
+    tracing,
+    utils::kek::Envelope,
+    DbConnection, DbError,
 };
 
+/// Reserve an unused key in one statement, the rows locked by concurrent reservations are
+/// skipped so each caller gets a different key.
 #[tracing::instrument(skip(conn))]
-pub async fn get_key_by_suffix<'a>(
-    conn: &mut DbConnection<'a>,
+pub async fn get_key_by_suffix(
+    conn: &mut DbConnection<'_>,
     chain: Chain,
     suffix: String,
 ) -> Result<Option<Key>, DbError> {
-    let chain_str = chain.to_string();
-    let result = conn
-        .transaction::<Option<Key>, DbError, _>(|conn| {
-            Box::pin(async move {
-                let key: Option<Key> = keys::table
-                    .filter(keys::used_at.is_null())
-                    .filter(keys::chain.eq(chain_str))
-                    .filter(keys::suffix.eq(suffix))
-                    .first::<Key>(conn)
-                    .await
-                    .optional()?;
-
-                if let Some(key) = key {
-                    let updated_key = update(keys::table)
-                        .filter(keys::id.eq(key.id))
-                        .filter(keys::used_at.is_null())
-                        .set(keys::used_at.eq(chrono::Utc::now().naive_utc()))
-                        .get_result::<Key>(conn)
-                        .await
-                        .optional()?;
-                    Ok(updated_key)
-                } else {
-                    Ok(None)
-                }
-            })
-        })
-        .await?;
-    Ok(result)
+    let unused_keys = diesel::alias!(keys as unused);
+    let unused = unused_keys
+        .filter(unused_keys.field(keys::used_at).is_null())
+        .filter(unused_keys.field(keys::chain).eq(chain.to_string()))
+        .filter(unused_keys.field(keys::suffix).eq(suffix))
+        .order(unused_keys.field(keys::id))
+        .limit(1)
+        .select(unused_keys.field(keys::id))
+        .for_update()
+        .skip_locked();
+
+    let key = update(keys::table)
+        .filter(keys::id.eq_any(unused))
+        .set(keys::used_at.eq(chrono::Utc::now().naive_utc()))
+        .returning(Key::as_returning())
+        .get_result(conn)
+        .await
+        .optional()?;
+    Ok(key)
 }
 
+/// Insert a key, or get the one with the same chain and pubkey.
 #[tracing::instrument(skip(conn, key))]
 pub async fn create_key(conn: &mut DbConnection<'_>, key: NewKey) -> Result<Key, DbError> {
-    let key = insert_into(keys::table)
+    let inserted = insert_into(keys::table)
         .values(&key)
-        .on_conflict(keys::secret)
+        .on_conflict((keys::chain, keys::pubkey))
         .do_nothing()
         .returning(Key::as_returning())
         .get_result(conn)
+        .await
+        .optional()?;
+    if let Some(inserted) = inserted {
+        return Ok(inserted);
+    }
+
+    let saved = keys::table
+        .filter(keys::chain.eq(key.chain))
+        .filter(keys::pubkey.eq(key.pubkey))
+        .select(Key::as_select())
+        .first(conn)
         .await?;
-    Ok(key)
+    Ok(saved)
 }
 
 #[tracing::instrument(skip(conn))]
@@ -64,7 +75,7 @@ pub async fn create_keys(
 ) -> Result<Vec<i32>, DbError> {
     let inserted = insert_into(keys::table)
         .values(&keys)
-        .on_conflict(keys::secret)
+        .on_conflict((keys::chain, keys::pubkey))
         .do_nothing()
         .returning(keys::id)
         .get_results(conn)
@@ -87,3 +98,202 @@ pub async fn get_secret_by_pubkey(
         .optional()?;
     Ok(key)
 }
+
+/// The first key encrypted with a KEK, to check a seed against.
+#[tracing::instrument(skip(conn))]
+pub async fn get_encrypted_key(
+    conn: &mut DbConnection<'_>,
+) -> Result<Option<KeyWithSecret>, DbError> {
+    let key = keys::table
+        .filter(keys::kek_id.is_not_null())
+        .filter(keys::encryption.eq(EncryptionMode::Encrypted.as_ref()))
+        .order(keys::id)
+        .select(KeyWithSecret::as_select())
+        .first::<KeyWithSecret>(conn)
+        .await
+        .optional()?;
+    Ok(key)
+}
+
+#[tracing::instrument(skip(conn))]
+pub async fn count_unrotated_keys(
+    conn: &mut DbConnection<'_>,
+    kek_id: i32,
+) -> Result<i64, DbError> {
+    let count = keys::table
+        .filter(keys::kek_id.is_distinct_from(kek_id).or(keys::key_version.lt(KEY_VERSION)))
+        .filter(keys::encryption.is_distinct_from(EncryptionMode::Plaintext.as_ref()))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
+/// The id, secret, wrapped data key, KEK id, chain, pubkey and key version of a key.
+type EncryptedKey = (i32, SecretBytes, Option<Vec<u8>>, Option<i32>, String, String, i32);
+
+/// Re-encrypt the secrets of a batch of keys not encrypted with the KEK of `kek_id`, or with an
+/// older key version, the rows are locked until the batch is saved. The plaintext ones are left to
+/// [`encrypt_keys`].
+/// `reencrypt` is given the saved secret, its associated data and the one to bind it to, it
+/// returns `None` for the secrets it cannot decrypt.
+#[tracing::instrument(skip(conn, reencrypt))]
+pub async fn rotate_keys<'a, F, Fut>(
+    conn: &mut DbConnection<'a>,
+    kek_id: i32,
+    after: i32,
+    limit: i64,
+    reencrypt: F,
+) -> Result<RotatedBatch, DbError>
+where
+    F: Fn(Envelope, Vec<u8>, Vec<u8>) -> Fut + Send + Sync + 'a,
+    Fut: Future<Output = Option<Envelope>> + Send + 'a,
+{
+    conn.transaction::<RotatedBatch, DbError, _>(move |conn| {
+        Box::pin(async move {
+            let rows: Vec<EncryptedKey> = keys::table
+                .filter(keys::id.gt(after))
+                .filter(keys::kek_id.is_distinct_from(kek_id).or(keys::key_version.lt(KEY_VERSION)))
+                .filter(keys::encryption.is_distinct_from(EncryptionMode::Plaintext.as_ref()))
+                .order(keys::id)
+                .limit(limit)
+                .select((
+                    keys::id,
+                    keys::secret,
+                    keys::wrapped_dek,
+                    keys::kek_id,
+                    keys::chain,
+                    keys::pubkey,
+                    keys::key_version,
+                ))
+                .for_update()
+                .load(conn)
+                .await?;
+
+            let mut batch = RotatedBatch::default();
+            for (id, secret, wrapped_dek, old, chain, pubkey, key_version) in rows {
+                batch.last_id = Some(id);
+                let saved = Envelope { secret, wrapped_dek, kek_id: old };
+                let aad = key_aad(&chain, &pubkey, key_version);
+                let bound = key_aad(&chain, &pubkey, KEY_VERSION);
+                let Some(envelope) = reencrypt(saved, aad, bound).await else {
+                    batch.failed.push(id);
+                    continue;
+                };
+                update(keys::table.find(id))
+                    .set((
+                        keys::secret.eq(envelope.secret),
+                        keys::wrapped_dek.eq(envelope.wrapped_dek),
+                        keys::kek_id.eq(envelope.kek_id),
+                        keys::key_version.eq(KEY_VERSION),
+                        keys::encryption.eq(EncryptionMode::Encrypted.as_ref()),
+                    ))
+                    .execute(conn)
+                    .await?;
+                batch.rotated += 1;
+            }
+            Ok(batch)
+        })
+    })
+    .await
+}
+
+/// The number of keys by encryption marker.
+#[tracing::instrument(skip(conn))]
+pub async fn count_keys_by_encryption(
+    conn: &mut DbConnection<'_>,
+) -> Result<Vec<(Option<String>, i64)>, DbError> {
+    let counts = keys::table
+        .group_by(keys::encryption)
+        .select((keys::encryption, count_star()))
+        .load(conn)
+        .await?;
+    Ok(counts)
+}
+
+/// The id, secret, wrapped data key, KEK id, chain, pubkey, key version and marker of a key.
+type UnencryptedKey =
+    (i32, SecretBytes, Option<Vec<u8>>, Option<i32>, String, String, i32, Option<String>);
+
+/// Encrypt the secrets of a batch of keys not marked as encrypted, the rows are locked until the
+/// batch is saved. `detect` tells the plaintext secrets from the encrypted ones, nothing is
+/// written with `dry_run`.
+#[tracing::instrument(skip(conn, detect))]
+pub async fn encrypt_keys<'a, F, Fut>(
+    conn: &mut DbConnection<'a>,
+    after: i32,
+    limit: i64,
+    dry_run: bool,
+    detect: F,
+) -> Result<DetectedBatch, DbError>
+where
+    F: Fn(SecretRow) -> Fut + Send + Sync + 'a,
+    Fut: Future<Output = Detected> + Send + 'a,
+{
+    conn.transaction::<DetectedBatch, DbError, _>(move |conn| {
+        Box::pin(async move {
+            let rows: Vec<UnencryptedKey> = keys::table
+                .filter(keys::id.gt(after))
+                .filter(keys::encryption.is_distinct_from(EncryptionMode::Encrypted.as_ref()))
+                .order(keys::id)
+                .limit(limit)
+                .select((
+                    keys::id,
+                    keys::secret,
+                    keys::wrapped_dek,
+                    keys::kek_id,
+                    keys::chain,
+                    keys::pubkey,
+                    keys::key_version,
+                    keys::encryption,
+                ))
+                .for_update()
+                .load(conn)
+                .await?;
+
+            let mut batch = DetectedBatch::default();
+            for (id, secret, wrapped_dek, kek_id, chain, pubkey, key_version, encryption) in rows {
+                batch.last_id = Some(id);
+                let row = SecretRow {
+                    aad: key_aad(&chain, &pubkey, key_version),
+                    bound: key_aad(&chain, &pubkey, KEY_VERSION),
+                    chain,
+                    pubkey: Some(pubkey),
+                    encryption,
+                    saved: Envelope { secret, wrapped_dek, kek_id },
+                };
+                match detect(row).await {
+                    Detected::Plaintext(envelope) => {
+                        batch.plaintext += 1;
+                        if dry_run {
+                            continue;
+                        }
+                        update(keys::table.find(id))
+                            .set((
+                                keys::secret.eq(envelope.secret),
+                                keys::wrapped_dek.eq(envelope.wrapped_dek),
+                                keys::kek_id.eq(envelope.kek_id),
+                                keys::key_version.eq(KEY_VERSION),
+                                keys::encryption.eq(EncryptionMode::Encrypted.as_ref()),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+                    Detected::Encrypted => {
+                        batch.encrypted += 1;
+                        if dry_run {
+                            continue;
+                        }
+                        update(keys::table.find(id))
+                            .set(keys::encryption.eq(EncryptionMode::Encrypted.as_ref()))
+                            .execute(conn)
+                            .await?;
+                    }
+                    Detected::Unknown => batch.unknown.push(id),
+                }
+            }
+            Ok(batch)
+        })
+    })
+    .await
+}
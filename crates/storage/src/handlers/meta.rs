@@ -0,0 +1,32 @@
+use diesel::{dsl::now, insert_into, prelude::*, upsert::excluded};
+use diesel_async::RunQueryDsl;
+
+use crate::{models::Meta, schema::meta, tracing, DbConnection, DbError};
+
+#[tracing::instrument(skip(conn))]
+pub async fn get_meta(conn: &mut DbConnection<'_>, key: &str) -> Result<Option<Meta>, DbError> {
+    let value = meta::table
+        .filter(meta::key.eq(key))
+        .select(Meta::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(value)
+}
+
+/// Insert the values, or replace the ones with the same key.
+#[tracing::instrument(skip(conn, values))]
+pub async fn set_meta(conn: &mut DbConnection<'_>, values: Vec<Meta>) -> Result<(), DbError> {
+    insert_into(meta::table)
+        .values(&values)
+        .on_conflict(meta::key)
+        .do_update()
+        .set((
+            meta::value.eq(excluded(meta::value)),
+            meta::kek_id.eq(excluded(meta::kek_id)),
+            meta::updated_at.eq(now),
+        ))
+        .execute(conn)
+        .await?;
+    Ok(())
+}
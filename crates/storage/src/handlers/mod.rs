@@ -1,2 +1,4 @@
 pub mod keys;
+pub mod meta;
 pub mod users;
+pub mod wallets;
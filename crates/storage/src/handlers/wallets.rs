@@ -0,0 +1,258 @@
+use std::future::Future;
+
+use diesel::{
+    dsl::{count_star, sql},
+    insert_into,
+    prelude::*,
+    sql_types::Integer,
+    update,
+};
+use diesel_async::{AsyncConnection, RunQueryDsl};
+
+use crate::{
+    models::{
+        wallet_aad, Detected, DetectedBatch, EncryptionMode, NewWallet, RotatedBatch, SecretBytes,
+        SecretRow, Wallet, WALLET_VERSION,
+    },
+    schema::wallets,
+    tracing,
+    utils::kek::Envelope,
+    DbConnection, DbError,
+};
+
+/// Reserve the id of a new wallet.
+#[tracing::instrument(skip(conn))]
+pub async fn next_wallet_id(conn: &mut DbConnection<'_>) -> Result<i32, DbError> {
+    let id = diesel::select(sql::<Integer>(
+        "CAST(nextval(pg_get_serial_sequence('wallets', 'id')) AS INTEGER)",
+    ))
+    .get_result(conn)
+    .await?;
+    Ok(id)
+}
+
+#[tracing::instrument(skip(conn, wallet))]
+pub async fn create_wallet(
+    conn: &mut DbConnection<'_>,
+    wallet: NewWallet,
+) -> Result<Wallet, DbError> {
+    let wallet = insert_into(wallets::table)
+        .values(&wallet)
+        .returning(Wallet::as_returning())
+        .get_result(conn)
+        .await?;
+    Ok(wallet)
+}
+
+#[tracing::instrument(skip(conn))]
+pub async fn get_wallet_by_id(
+    conn: &mut DbConnection<'_>,
+    id: i32,
+) -> Result<Option<Wallet>, DbError> {
+    let wallet = wallets::table
+        .filter(wallets::id.eq(id))
+        .select(Wallet::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(wallet)
+}
+
+/// The first wallet encrypted with a KEK, to check a seed against.
+#[tracing::instrument(skip(conn))]
+pub async fn get_encrypted_wallet(conn: &mut DbConnection<'_>) -> Result<Option<Wallet>, DbError> {
+    let wallet = wallets::table
+        .filter(wallets::kek_id.is_not_null())
+        .filter(wallets::encryption.eq(EncryptionMode::Encrypted.as_ref()))
+        .order(wallets::id)
+        .select(Wallet::as_select())
+        .first(conn)
+        .await
+        .optional()?;
+    Ok(wallet)
+}
+
+#[tracing::instrument(skip(conn))]
+pub async fn count_unrotated_wallets(
+    conn: &mut DbConnection<'_>,
+    kek_id: i32,
+) -> Result<i64, DbError> {
+    let count = wallets::table
+        .filter(
+            wallets::kek_id.is_distinct_from(kek_id).or(wallets::wallet_version.lt(WALLET_VERSION)),
+        )
+        .filter(wallets::encryption.is_distinct_from(EncryptionMode::Plaintext.as_ref()))
+        .count()
+        .get_result(conn)
+        .await?;
+    Ok(count)
+}
+
+/// The id, seed, wrapped data key, KEK id, chain, path and wallet version of a wallet.
+type EncryptedWallet = (i32, SecretBytes, Option<Vec<u8>>, Option<i32>, String, String, i32);
+
+/// Re-encrypt the seeds of a batch of wallets not encrypted with the KEK of `kek_id`, or with an
+/// older wallet version, like [`rotate_keys`](super::keys::rotate_keys).
+#[tracing::instrument(skip(conn, reencrypt))]
+pub async fn rotate_wallets<'a, F, Fut>(
+    conn: &mut DbConnection<'a>,
+    kek_id: i32,
+    after: i32,
+    limit: i64,
+    reencrypt: F,
+) -> Result<RotatedBatch, DbError>
+where
+    F: Fn(Envelope, Vec<u8>, Vec<u8>) -> Fut + Send + Sync + 'a,
+    Fut: Future<Output = Option<Envelope>> + Send + 'a,
+{
+    conn.transaction::<RotatedBatch, DbError, _>(move |conn| {
+        Box::pin(async move {
+            let rows: Vec<EncryptedWallet> = wallets::table
+                .filter(wallets::id.gt(after))
+                .filter(
+                    wallets::kek_id
+                        .is_distinct_from(kek_id)
+                        .or(wallets::wallet_version.lt(WALLET_VERSION)),
+                )
+                .filter(wallets::encryption.is_distinct_from(EncryptionMode::Plaintext.as_ref()))
+                .order(wallets::id)
+                .limit(limit)
+                .select((
+                    wallets::id,
+                    wallets::seed,
+                    wallets::wrapped_dek,
+                    wallets::kek_id,
+                    wallets::chain,
+                    wallets::path,
+                    wallets::wallet_version,
+                ))
+                .for_update()
+                .load(conn)
+                .await?;
+
+            let mut batch = RotatedBatch::default();
+            for (id, seed, wrapped_dek, old, chain, path, wallet_version) in rows {
+                batch.last_id = Some(id);
+                let saved = Envelope { secret: seed, wrapped_dek, kek_id: old };
+                let aad = wallet_aad(&chain, id, &path, wallet_version);
+                let bound = wallet_aad(&chain, id, &path, WALLET_VERSION);
+                let Some(envelope) = reencrypt(saved, aad, bound).await else {
+                    batch.failed.push(id);
+                    continue;
+                };
+                update(wallets::table.find(id))
+                    .set((
+                        wallets::seed.eq(envelope.secret),
+                        wallets::wrapped_dek.eq(envelope.wrapped_dek),
+                        wallets::kek_id.eq(envelope.kek_id),
+                        wallets::wallet_version.eq(WALLET_VERSION),
+                        wallets::encryption.eq(EncryptionMode::Encrypted.as_ref()),
+                    ))
+                    .execute(conn)
+                    .await?;
+                batch.rotated += 1;
+            }
+            Ok(batch)
+        })
+    })
+    .await
+}
+
+/// The number of wallets by encryption marker.
+#[tracing::instrument(skip(conn))]
+pub async fn count_wallets_by_encryption(
+    conn: &mut DbConnection<'_>,
+) -> Result<Vec<(Option<String>, i64)>, DbError> {
+    let counts = wallets::table
+        .group_by(wallets::encryption)
+        .select((wallets::encryption, count_star()))
+        .load(conn)
+        .await?;
+    Ok(counts)
+}
+
+/// The id, seed, wrapped data key, KEK id, chain, path, wallet version and marker of a wallet.
+type UnencryptedWallet =
+    (i32, SecretBytes, Option<Vec<u8>>, Option<i32>, String, String, i32, Option<String>);
+
+/// Encrypt the seeds of a batch of wallets not marked as encrypted, like
+/// [`encrypt_keys`](super::keys::encrypt_keys).
+#[tracing::instrument(skip(conn, detect))]
+pub async fn encrypt_wallets<'a, F, Fut>(
+    conn: &mut DbConnection<'a>,
+    after: i32,
+    limit: i64,
+    dry_run: bool,
+    detect: F,
+) -> Result<DetectedBatch, DbError>
+where
+    F: Fn(SecretRow) -> Fut + Send + Sync + 'a,
+    Fut: Future<Output = Detected> + Send + 'a,
+{
+    conn.transaction::<DetectedBatch, DbError, _>(move |conn| {
+        Box::pin(async move {
+            let rows: Vec<UnencryptedWallet> = wallets::table
+                .filter(wallets::id.gt(after))
+                .filter(wallets::encryption.is_distinct_from(EncryptionMode::Encrypted.as_ref()))
+                .order(wallets::id)
+                .limit(limit)
+                .select((
+                    wallets::id,
+                    wallets::seed,
+                    wallets::wrapped_dek,
+                    wallets::kek_id,
+                    wallets::chain,
+                    wallets::path,
+                    wallets::wallet_version,
+                    wallets::encryption,
+                ))
+                .for_update()
+                .load(conn)
+                .await?;
+
+            let mut batch = DetectedBatch::default();
+            for (id, seed, wrapped_dek, kek_id, chain, path, wallet_version, encryption) in rows {
+                batch.last_id = Some(id);
+                let row = SecretRow {
+                    aad: wallet_aad(&chain, id, &path, wallet_version),
+                    bound: wallet_aad(&chain, id, &path, WALLET_VERSION),
+                    chain,
+                    pubkey: None,
+                    encryption,
+                    saved: Envelope { secret: seed, wrapped_dek, kek_id },
+                };
+                match detect(row).await {
+                    Detected::Plaintext(envelope) => {
+                        batch.plaintext += 1;
+                        if dry_run {
+                            continue;
+                        }
+                        update(wallets::table.find(id))
+                            .set((
+                                wallets::seed.eq(envelope.secret),
+                                wallets::wrapped_dek.eq(envelope.wrapped_dek),
+                                wallets::kek_id.eq(envelope.kek_id),
+                                wallets::wallet_version.eq(WALLET_VERSION),
+                                wallets::encryption.eq(EncryptionMode::Encrypted.as_ref()),
+                            ))
+                            .execute(conn)
+                            .await?;
+                    }
+                    Detected::Encrypted => {
+                        batch.encrypted += 1;
+                        if dry_run {
+                            continue;
+                        }
+                        update(wallets::table.find(id))
+                            .set(wallets::encryption.eq(EncryptionMode::Encrypted.as_ref()))
+                            .execute(conn)
+                            .await?;
+                    }
+                    Detected::Unknown => batch.unknown.push(id),
+                }
+            }
+            Ok(batch)
+        })
+    })
+    .await
+}
@@ -2,11 +2,8 @@ use serde::Deserialize;
 use strum::{Display, EnumCount, EnumDiscriminants, EnumString};
 use strum_macros::{AsRefStr, EnumIs};
 
-use crate::DatabaseError;
-
 #[derive(
     AsRefStr,
-    clap::ValueEnum,
     Clone,
     Copy,
     Debug,
@@ -24,35 +21,34 @@ use crate::DatabaseError;
 pub enum Chain {
     /// Docs on red
     #[strum(ascii_case_insensitive, serialize = "solana", serialize = "sol")]
+    #[serde(alias = "sol")]
     Solana,
     #[strum(ascii_case_insensitive, serialize = "eth", to_string = "eth")]
+    #[serde(alias = "eth")]
     Ethereum,
+    #[strum(ascii_case_insensitive, serialize = "bitcoin", to_string = "btc")]
+    #[serde(alias = "btc")]
+    Bitcoin,
+    #[strum(ascii_case_insensitive, serialize = "bitcoin-testnet", to_string = "btc-testnet")]
+    #[serde(rename = "bitcoin-testnet", alias = "btc-testnet")]
+    BitcoinTestnet,
+    #[strum(ascii_case_insensitive, serialize = "bitcoin-signet", to_string = "btc-signet")]
+    #[serde(rename = "bitcoin-signet", alias = "btc-signet")]
+    BitcoinSignet,
+    #[strum(ascii_case_insensitive, serialize = "bitcoin-regtest", to_string = "btc-regtest")]
+    #[serde(rename = "bitcoin-regtest", alias = "btc-regtest")]
+    BitcoinRegtest,
+    #[strum(ascii_case_insensitive, serialize = "litecoin", to_string = "ltc")]
+    #[serde(alias = "ltc")]
+    Litecoin,
+    #[strum(ascii_case_insensitive, serialize = "dogecoin", to_string = "doge")]
+    #[serde(alias = "doge")]
+    Dogecoin,
     #[strum(disabled)]
+    #[serde(skip)]
     Unknown,
 }
 
-/// Keypair interface.
-pub trait KeypairStrategy: Send {
-    /// Get the chain.
-    fn chain(&self) -> Chain;
-    /// Generate a new keypair.
-    fn generate(&mut self);
-    /// Recover a keypair from a secret string.
-    fn recover_secret(&mut self, secret: &str) -> Result<(), DatabaseError>;
-    /// Recover a keypair from a bytes.
-    fn recover_from_bytes(&mut self, bytes: &[u8]) -> Result<(), DatabaseError>;
-    /// Get the secret key.
-    fn to_vec(&self) -> Vec<u8>;
-    /// Get the secret key.
-    fn secret(&self) -> String;
-    /// Get the public key.
-    fn pubkey(&self) -> String;
-    /// Get the address key.
-    fn address(&self) -> String;
-    /// Sign a message with a external secret.
-    fn sign(&self, message: &[u8]) -> Result<String, DatabaseError>;
-}
-
 #[cfg(test)]
 mod tests {
     use super::Chain;
@@ -64,5 +60,35 @@ mod tests {
 
         let chain = Chain::try_from("SOLANA").expect("invalid chain");
         assert_eq!(chain, Chain::Solana);
+
+        let chain = Chain::try_from("eth").expect("invalid chain");
+        assert_eq!(chain, Chain::Ethereum);
+        assert_eq!(chain.to_string(), "eth");
+
+        for chain in [
+            Chain::Bitcoin,
+            Chain::BitcoinTestnet,
+            Chain::BitcoinSignet,
+            Chain::BitcoinRegtest,
+            Chain::Litecoin,
+            Chain::Dogecoin,
+        ] {
+            assert_eq!(Chain::try_from(chain.to_string().as_str()), Ok(chain));
+        }
+        assert_eq!(Chain::try_from("bitcoin-regtest"), Ok(Chain::BitcoinRegtest));
+    }
+
+    #[test]
+    fn test_deserialize() {
+        let chain: Chain = serde_json::from_str("\"eth\"").expect("invalid chain");
+        assert_eq!(chain, Chain::Ethereum);
+
+        let chain: Chain = serde_json::from_str("\"ethereum\"").expect("invalid chain");
+        assert_eq!(chain, Chain::Ethereum);
+
+        let chain: Chain = serde_json::from_str("\"btc-testnet\"").expect("invalid chain");
+        assert_eq!(chain, Chain::BitcoinTestnet);
+
+        assert!(serde_json::from_str::<Chain>("\"unknown\"").is_err());
     }
 }
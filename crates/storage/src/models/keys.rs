@@ -1,14 +1,35 @@
 use async_trait::async_trait;
-use chrono;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
 use crate::{
-    models::chain::{Chain, KeypairStrategy},
+    models::{chain::Chain, secret::SecretBytes, EncryptionMode},
     schema::keys,
+    utils::kek::Envelope,
     DatabaseError,
 };
 
+/// The version of the encryption of new secrets, 1 binds the chain and the pubkey as associated
+/// data so a secret only decrypts in its own row.
+pub const KEY_VERSION: i32 = 1;
+
+/// The associated data of the secret of a key, none before version 1.
+pub fn key_aad(chain: &str, pubkey: &str, key_version: i32) -> Vec<u8> {
+    if key_version < 1 {
+        return vec![];
+    }
+    let mut aad = b"anita key\0".to_vec();
+    for field in [chain.as_bytes(), b"\0", pubkey.as_bytes(), b"\0"] {
+        aad.extend_from_slice(field);
+    }
+    aad.extend_from_slice(&key_version.to_be_bytes());
+    aad
+}
+
+fn current_key_version() -> i32 {
+    KEY_VERSION
+}
+
 /// Key details.
 #[derive(Queryable, Selectable, AsChangeset, PartialEq, Debug, Clone, Serialize, Deserialize)]
 #[diesel(table_name = keys)]
@@ -18,7 +39,7 @@ pub struct Key {
     #[serde(rename = "chain")]
     pub chain: String,
     #[serde(skip_serializing)]
-    secret: Vec<u8>,
+    secret: SecretBytes,
     #[serde(rename = "pubkey")]
     pub pubkey: String,
     #[serde(rename = "address")]
@@ -30,6 +51,32 @@ pub struct Key {
     #[serde(rename = "createdAt")]
     #[diesel(skip_insertion)]
     pub created_at: Option<chrono::NaiveDateTime>,
+    #[serde(rename = "pattern")]
+    pub pattern: Option<String>,
+    /// The id of the KEK the secret is encrypted with, `None` if written before KEKs had ids.
+    #[serde(skip)]
+    pub kek_id: Option<i32>,
+    /// The data key of the secret, wrapped by the KEK.
+    #[serde(skip)]
+    pub wrapped_dek: Option<Vec<u8>>,
+    /// The version of the encryption of the secret, see [`KEY_VERSION`].
+    #[serde(skip)]
+    pub key_version: i32,
+    /// Whether the secret is encrypted, `None` if written before rows were marked.
+    #[serde(skip)]
+    pub encryption: Option<String>,
+}
+
+impl Key {
+    /// Whether the secret is marked as stored in plaintext.
+    pub fn is_plaintext(&self) -> bool {
+        self.encryption.as_deref() == Some(EncryptionMode::Plaintext.as_ref())
+    }
+
+    /// The associated data the secret is encrypted with.
+    pub fn aad(&self) -> Vec<u8> {
+        key_aad(&self.chain, &self.pubkey, self.key_version)
+    }
 }
 
 /// Key details.
@@ -40,30 +87,23 @@ pub struct KeyWithSecret {
     #[diesel(embed)]
     #[serde(flatten)]
     pub key: Key,
-    secret: Vec<u8>, // hex string
+    secret: SecretBytes,
 }
 
 impl KeyWithSecret {
-    /// Get the secret key.
-    pub fn secret(&self) -> Vec<u8> {
-        self.secret.clone()
+    /// A key with its decrypted secret.
+    pub fn new(key: Key, secret: SecretBytes) -> Self {
+        Self { key, secret }
     }
 
-    /// set the secret key.
-    pub fn set_secret(&mut self, src: &[u8]) {
-        self.secret = src.to_vec();
+    /// Get the secret key.
+    pub fn secret(&self) -> &SecretBytes {
+        &self.secret
     }
 
-    /// Sign a message with the key pair sign method.
-    pub fn sign(
-        &self,
-        mut keypair: Box<dyn KeypairStrategy>,
-        message: &[u8],
-    ) -> Result<String, DatabaseError> {
-        keypair.recover_from_bytes(self.secret.as_slice())?;
-
-        let signature = keypair.sign(message)?;
-        Ok(signature)
+    /// set the secret key.
+    pub fn set_secret(&mut self, src: SecretBytes) {
+        self.secret = src;
     }
 }
 
@@ -75,7 +115,7 @@ pub struct NewKey {
     #[serde(rename = "chain")]
     pub chain: String,
     #[serde(rename = "secret")]
-    secret: Vec<u8>,
+    secret: SecretBytes,
     #[serde(rename = "pubkey")]
     pub pubkey: String,
     #[serde(rename = "address")]
@@ -84,33 +124,59 @@ pub struct NewKey {
     pub suffix: String,
     #[serde(rename = "usedAt")]
     pub used_at: Option<chrono::NaiveDateTime>,
+    #[serde(rename = "pattern")]
+    pub pattern: Option<String>,
+    #[serde(skip)]
+    pub kek_id: Option<i32>,
+    #[serde(skip)]
+    pub wrapped_dek: Option<Vec<u8>>,
+    #[serde(skip, default = "current_key_version")]
+    pub key_version: i32,
+    #[serde(skip)]
+    pub encryption: Option<String>,
 }
 
 impl NewKey {
-    pub fn from_keypair(keypair: &Box<dyn KeypairStrategy>, suffix: Option<String>) -> NewKey {
-        let address: String = keypair.address();
-        let secret = keypair.to_vec();
-        let suffix = suffix
-            .map(|f| f.to_ascii_lowercase())
-            .unwrap_or_else(|| address[address.len() - 4..].to_ascii_lowercase());
+    /// A key with its secret in plain, the suffix defaults to the last 4 characters of the address.
+    pub fn new(
+        chain: Chain,
+        secret: SecretBytes,
+        pubkey: String,
+        address: String,
+        suffix: Option<String>,
+    ) -> NewKey {
+        // base58 is case-sensitive, keep the suffix as it appears in the address
+        let suffix = suffix.unwrap_or_else(|| address[address.len() - 4..].to_string());
         NewKey {
-            chain: keypair.chain().to_string(),
+            chain: chain.to_string(),
             secret,
-            pubkey: keypair.pubkey(),
+            pubkey,
             address,
             suffix,
             used_at: None,
+            pattern: None,
+            kek_id: None,
+            wrapped_dek: None,
+            key_version: KEY_VERSION,
+            encryption: None,
         }
     }
 
-    pub fn get_secret(&self) -> Vec<u8> {
-        self.secret.clone()
+    pub fn get_secret(&self) -> &SecretBytes {
+        &self.secret
     }
 
-    /// Get the secret key.
-    /// The secret key is encrypted with the seed.
-    pub fn set_secret(&mut self, encrypted: &[u8]) {
-        self.secret = encrypted.to_vec();
+    /// The associated data to encrypt the secret with.
+    pub fn aad(&self) -> Vec<u8> {
+        key_aad(&self.chain, &self.pubkey, self.key_version)
+    }
+
+    /// Set the secret key, encrypted with its data key.
+    pub fn set_secret(&mut self, encrypted: Envelope) {
+        self.encryption = Some(EncryptionMode::of(&encrypted).to_string());
+        self.secret = encrypted.secret;
+        self.kek_id = encrypted.kek_id;
+        self.wrapped_dek = encrypted.wrapped_dek;
     }
 }
 
@@ -123,11 +189,12 @@ pub trait KeyTrait {
         chain: Chain,
         suffix: &str,
     ) -> Result<Option<Key>, DatabaseError>;
-    /// Create a key.
+    /// Create a key, or get the saved key with the same chain and pubkey.
     async fn create_key(&self, key: NewKey) -> Result<Key, DatabaseError>;
 
-    // /// Create multiple keys.
-    // async fn create_keys(keys: Vec<NewKey>) -> Result<Vec<i32>, DatabaseError>;
+    /// Create multiple keys in one insert, returns the ids of the inserted keys, the ones already
+    /// saved are skipped.
+    async fn create_keys(&self, keys: Vec<NewKey>) -> Result<Vec<i32>, DatabaseError>;
 
     async fn get_secret_by_pubkey(
         &self,
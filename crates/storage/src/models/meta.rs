@@ -0,0 +1,57 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use strum::{Display, EnumString};
+use strum_macros::AsRefStr;
+
+use crate::{schema::meta, utils::kek::Envelope, DatabaseError};
+
+/// The key of the encryption mode in the `meta` table.
+pub const ENCRYPTION_KEY: &str = "encryption";
+/// The key of the canary in the `meta` table.
+pub const CANARY_KEY: &str = "canary";
+/// The plaintext of the canary, wrapped by the KEK the database is initialized with.
+pub const CANARY: &[u8] = b"anita canary";
+
+/// Whether the secrets are encrypted, of the database or of one row.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, AsRefStr, Display, EnumString)]
+#[strum(serialize_all = "lowercase")]
+pub enum EncryptionMode {
+    /// No seed or KEK, the secrets are stored as they are.
+    Plaintext,
+    Encrypted,
+}
+
+impl EncryptionMode {
+    /// The mode of a secret about to be saved, encrypted once it has a KEK id.
+    pub fn of(envelope: &Envelope) -> Self {
+        match envelope.kek_id {
+            Some(_) => EncryptionMode::Encrypted,
+            None => EncryptionMode::Plaintext,
+        }
+    }
+}
+
+/// A value the database keeps about itself.
+#[derive(Queryable, Selectable, Insertable, PartialEq, Debug, Clone)]
+#[diesel(table_name = meta)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Meta {
+    pub key: String,
+    pub value: Vec<u8>,
+    /// The id of the KEK the value is wrapped with.
+    pub kek_id: Option<i32>,
+}
+
+#[async_trait]
+pub trait MetaTrait {
+    /// Check the seed or KEK against the encryption mode and the canary of the database.
+    /// A sealed database is checked when it is unsealed.
+    async fn check_seed(&self) -> Result<(), DatabaseError>;
+
+    /// The encryption mode recorded by `init_seed`.
+    async fn encryption_mode(&self) -> Result<Option<EncryptionMode>, DatabaseError>;
+
+    /// Record the encryption mode of the seed or KEK, and a canary wrapped by it.
+    /// An existing record is kept unless `force`.
+    async fn init_seed(&self, force: bool) -> Result<EncryptionMode, DatabaseError>;
+}
@@ -1,9 +1,17 @@
 mod chain;
 mod keys;
+mod meta;
+mod rotation;
+mod secret;
 mod users;
 mod version;
+mod wallets;
 
 pub use chain::*;
 pub use keys::*;
+pub use meta::*;
+pub use rotation::*;
+pub use secret::*;
 pub use users::*;
 pub use version::*;
+pub use wallets::*;
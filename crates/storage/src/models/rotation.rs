@@ -0,0 +1,151 @@
+use std::sync::Arc;
+
+use async_trait::async_trait;
+use strum::Display;
+
+use crate::{models::EncryptionMode, utils::kek::Envelope, DatabaseError};
+
+/// The tables with secrets encrypted with the database seed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Display)]
+#[strum(serialize_all = "lowercase")]
+pub enum SecretTable {
+    Keys,
+    Wallets,
+}
+
+impl SecretTable {
+    pub const ALL: [SecretTable; 2] = [SecretTable::Keys, SecretTable::Wallets];
+}
+
+/// The rows of one batch of a seed rotation.
+#[derive(Debug, Default, PartialEq)]
+pub struct RotatedBatch {
+    /// The number of rows encrypted with the current seed.
+    pub rotated: usize,
+    /// The ids of the rows none of the seeds could decrypt, left as they are.
+    pub failed: Vec<i32>,
+    /// The last id of the batch, the next batch starts after it, `None` once there are no rows left.
+    pub last_id: Option<i32>,
+}
+
+/// The rows of a table by their encryption marker.
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub struct EncryptionReport {
+    pub encrypted: i64,
+    pub plaintext: i64,
+    /// The rows written before rows were marked, encrypted or not.
+    pub unmarked: i64,
+}
+
+impl EncryptionReport {
+    /// Sum the number of rows of each marker.
+    pub fn from_counts(counts: impl IntoIterator<Item = (Option<String>, i64)>) -> Self {
+        let mut report = EncryptionReport::default();
+        for (encryption, count) in counts {
+            match encryption.and_then(|encryption| encryption.parse().ok()) {
+                Some(EncryptionMode::Encrypted) => report.encrypted += count,
+                Some(EncryptionMode::Plaintext) => report.plaintext += count,
+                None => report.unmarked += count,
+            }
+        }
+        report
+    }
+}
+
+/// A secret not marked as encrypted.
+#[derive(Debug)]
+pub struct SecretRow {
+    pub chain: String,
+    /// The pubkey of a key, `None` for the seed of a wallet.
+    pub pubkey: Option<String>,
+    /// The marker of the row, `None` if the secret may or may not be encrypted.
+    pub encryption: Option<String>,
+    pub saved: Envelope,
+    /// The associated data the secret is encrypted with if it is, and the one to bind it to.
+    pub aad: Vec<u8>,
+    pub bound: Vec<u8>,
+}
+
+/// Whether an unmarked secret is in plaintext, like a keypair with the pubkey of its row.
+pub type PlaintextCheck = Arc<dyn Fn(&SecretRow) -> bool + Send + Sync>;
+
+/// What a secret not marked as encrypted turned out to be.
+#[derive(Debug)]
+pub enum Detected {
+    /// In plaintext, with the secret encrypted with the current KEK.
+    Plaintext(Envelope),
+    /// Already encrypted with one of the KEKs.
+    Encrypted,
+    /// Neither in plaintext nor decrypted by the KEKs, left unmarked.
+    Unknown,
+}
+
+/// The rows of one batch of `anita db encrypt-existing`.
+#[derive(Debug, Default, PartialEq)]
+pub struct DetectedBatch {
+    /// The number of plaintext rows, encrypted unless it is a dry run.
+    pub plaintext: usize,
+    /// The number of unmarked rows found to be encrypted.
+    pub encrypted: usize,
+    /// The ids of the rows that are neither, left as they are.
+    pub unknown: Vec<i32>,
+    /// The last id of the batch, `None` once there are no rows left.
+    pub last_id: Option<i32>,
+}
+
+/// Encrypt the secrets stored in plaintext.
+#[async_trait]
+pub trait EncryptionTrait {
+    /// The number of rows of `table` by their encryption marker.
+    async fn count_by_encryption(
+        &self,
+        table: SecretTable,
+    ) -> Result<EncryptionReport, DatabaseError>;
+
+    /// Encrypt up to `limit` rows of `table` with an id above `after` that are not marked as
+    /// encrypted, in one transaction. The unmarked rows are told apart with `is_plaintext`, or
+    /// marked as encrypted if one of the KEKs decrypts them. Nothing is written with `dry_run`.
+    async fn encrypt_batch(
+        &self,
+        table: SecretTable,
+        after: i32,
+        limit: i64,
+        is_plaintext: PlaintextCheck,
+        dry_run: bool,
+    ) -> Result<DetectedBatch, DatabaseError>;
+}
+
+/// Re-encrypt the secrets written with an older seed with the current one.
+#[async_trait]
+pub trait RotationTrait {
+    /// The number of rows of `table` not encrypted with the current seed, or not bound to their
+    /// row yet.
+    async fn count_unrotated(&self, table: SecretTable) -> Result<i64, DatabaseError>;
+
+    /// Re-encrypt up to `limit` rows of `table` with an id above `after`, in one transaction.
+    /// Rows already encrypted with the current seed, and bound to their row, are skipped, so an
+    /// interrupted rotation resumes where it stopped.
+    async fn rotate_batch(
+        &self,
+        table: SecretTable,
+        after: i32,
+        limit: i64,
+    ) -> Result<RotatedBatch, DatabaseError>;
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_encryption_report() {
+        let report = EncryptionReport::from_counts([
+            (Some("encrypted".to_string()), 3),
+            (Some("plaintext".to_string()), 2),
+            (None, 4),
+            (Some("unknown".to_string()), 1),
+        ]);
+        assert_eq!(report, EncryptionReport { encrypted: 3, plaintext: 2, unmarked: 5 });
+        assert_eq!(EncryptionReport::from_counts([]), EncryptionReport::default());
+    }
+}
@@ -0,0 +1,130 @@
+use std::fmt;
+
+use diesel::{
+    deserialize::{self, FromSql, FromSqlRow},
+    expression::AsExpression,
+    pg::{Pg, PgValue},
+    serialize::{self, Output, ToSql},
+    sql_types::Binary,
+};
+use serde::{Deserialize, Deserializer};
+use zeroize::Zeroize;
+
+/// A secret that is wiped from memory on drop.
+///
+/// It has no `Display`, and its `Debug` never shows the contents, read it with [`Secret::expose`].
+#[derive(Clone, Default, AsExpression, FromSqlRow)]
+#[diesel(sql_type = Binary)]
+pub struct Secret<T: Zeroize>(T);
+
+/// Secret bytes, a private key, a BIP-39 seed or the database seed.
+pub type SecretBytes = Secret<Vec<u8>>;
+
+/// A secret in the text encoding of its chain, base58, hex or WIF.
+pub type SecretString = Secret<String>;
+
+impl<T: Zeroize> Secret<T> {
+    pub fn new(secret: T) -> Self {
+        Secret(secret)
+    }
+
+    /// Borrow the secret.
+    pub fn expose(&self) -> &T {
+        &self.0
+    }
+
+    /// Borrow the secret mutably, to fill it in place.
+    pub fn expose_mut(&mut self) -> &mut T {
+        &mut self.0
+    }
+}
+
+impl SecretBytes {
+    /// Copy the secret bytes.
+    pub fn from_slice(secret: &[u8]) -> Self {
+        Secret(secret.to_vec())
+    }
+
+    pub fn as_slice(&self) -> &[u8] {
+        self.0.as_slice()
+    }
+}
+
+impl SecretString {
+    pub fn as_str(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+impl<T: Zeroize> From<T> for Secret<T> {
+    fn from(secret: T) -> Self {
+        Secret(secret)
+    }
+}
+
+impl<T: Zeroize> Drop for Secret<T> {
+    fn drop(&mut self) {
+        self.0.zeroize();
+    }
+}
+
+impl<T: Zeroize> fmt::Debug for Secret<T> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("Secret([REDACTED])")
+    }
+}
+
+/// Compare in constant time for secrets of the same length.
+impl<T: Zeroize + AsRef<[u8]>> PartialEq for Secret<T> {
+    fn eq(&self, other: &Self) -> bool {
+        let (a, b) = (self.0.as_ref(), other.0.as_ref());
+        a.len() == b.len() && a.iter().zip(b).fold(0u8, |acc, (x, y)| acc | (x ^ y)) == 0
+    }
+}
+
+impl<T: Zeroize + AsRef<[u8]>> Eq for Secret<T> {}
+
+impl<'de, T: Zeroize + Deserialize<'de>> Deserialize<'de> for Secret<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        T::deserialize(deserializer).map(Secret)
+    }
+}
+
+impl ToSql<Binary, Pg> for SecretBytes {
+    fn to_sql<'b>(&'b self, out: &mut Output<'b, '_, Pg>) -> serialize::Result {
+        <Vec<u8> as ToSql<Binary, Pg>>::to_sql(&self.0, out)
+    }
+}
+
+impl FromSql<Binary, Pg> for SecretBytes {
+    fn from_sql(bytes: PgValue<'_>) -> deserialize::Result<Self> {
+        <Vec<u8> as FromSql<Binary, Pg>>::from_sql(bytes).map(Secret)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+
+    use super::*;
+
+    #[test]
+    fn test_secret_is_not_formatted() {
+        let secret =
+            SecretString::new("5JYkZjmN7PVMjJUfJWfRFwtuXTGB439XV6faajeHPAM9Z2PT2R3".into());
+        let bytes = SecretBytes::from_slice(&[0xab; 32]);
+
+        for formatted in [format!("{:?}", secret), format!("{:#?}", bytes)] {
+            assert!(!formatted.contains("5JYk"), "{formatted}");
+            assert!(!formatted.contains("171"), "{formatted}");
+            assert!(!formatted.contains("ab"), "{formatted}");
+        }
+        assert_eq!(secret.as_str(), "5JYkZjmN7PVMjJUfJWfRFwtuXTGB439XV6faajeHPAM9Z2PT2R3");
+    }
+
+    #[test]
+    fn test_secret_eq() {
+        assert_eq!(SecretBytes::from_slice(b"seed"), SecretBytes::from_slice(b"seed"));
+        assert_ne!(SecretBytes::from_slice(b"seed"), SecretBytes::from_slice(b"seeds"));
+        assert_ne!(SecretBytes::from_slice(b"seed"), SecretBytes::from_slice(b"deed"));
+    }
+}
@@ -1,5 +1,4 @@
 use async_trait::async_trait;
-use chrono;
 use diesel::prelude::*;
 use serde::{Deserialize, Serialize};
 
@@ -0,0 +1,147 @@
+use async_trait::async_trait;
+use diesel::prelude::*;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{secret::SecretBytes, EncryptionMode},
+    schema::wallets,
+    utils::kek::Envelope,
+    DatabaseError,
+};
+
+/// The version of the encryption of new wallet seeds, 1 binds the chain, the id and the path as
+/// associated data so a seed only decrypts in its own row.
+pub const WALLET_VERSION: i32 = 1;
+
+/// The associated data of the seed of a wallet, none before version 1.
+pub fn wallet_aad(chain: &str, id: i32, path: &str, wallet_version: i32) -> Vec<u8> {
+    if wallet_version < 1 {
+        return vec![];
+    }
+    let mut aad = b"anita wallet\0".to_vec();
+    for field in [chain.as_bytes(), b"\0", &id.to_be_bytes(), path.as_bytes(), b"\0"] {
+        aad.extend_from_slice(field);
+    }
+    aad.extend_from_slice(&wallet_version.to_be_bytes());
+    aad
+}
+
+fn current_wallet_version() -> i32 {
+    WALLET_VERSION
+}
+
+/// The root of a hierarchical deterministic wallet.
+#[derive(Queryable, Selectable, PartialEq, Debug, Clone, Serialize, Deserialize)]
+#[diesel(table_name = wallets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct Wallet {
+    pub id: i32,
+    #[serde(rename = "chain")]
+    pub chain: String,
+    #[serde(skip_serializing)]
+    seed: SecretBytes,
+    /// The derivation path template, `{index}` is replaced by the child index.
+    #[serde(rename = "path")]
+    pub path: String,
+    #[serde(rename = "createdAt")]
+    pub created_at: Option<chrono::NaiveDateTime>,
+    /// The id of the KEK the wallet seed is encrypted with.
+    #[serde(skip)]
+    pub kek_id: Option<i32>,
+    /// The data key of the wallet seed, wrapped by the KEK.
+    #[serde(skip)]
+    pub wrapped_dek: Option<Vec<u8>>,
+    /// Whether the wallet seed is encrypted, `None` if written before rows were marked.
+    #[serde(skip)]
+    pub encryption: Option<String>,
+    /// The version of the encryption of the wallet seed, see [`WALLET_VERSION`].
+    #[serde(skip)]
+    pub wallet_version: i32,
+}
+
+impl Wallet {
+    /// Whether the wallet seed is marked as stored in plaintext.
+    pub fn is_plaintext(&self) -> bool {
+        self.encryption.as_deref() == Some(EncryptionMode::Plaintext.as_ref())
+    }
+
+    /// The associated data the wallet seed is encrypted with.
+    pub fn aad(&self) -> Vec<u8> {
+        wallet_aad(&self.chain, self.id, &self.path, self.wallet_version)
+    }
+
+    /// Get the BIP-39 seed.
+    pub fn seed(&self) -> &SecretBytes {
+        &self.seed
+    }
+
+    /// set the BIP-39 seed.
+    pub fn set_seed(&mut self, src: SecretBytes) {
+        self.seed = src;
+    }
+}
+
+/// New wallet details.
+#[derive(Insertable, PartialEq, Debug, Clone, Deserialize)]
+#[diesel(table_name = wallets)]
+#[diesel(check_for_backend(diesel::pg::Pg))]
+pub struct NewWallet {
+    /// Reserved before the seed is encrypted, as the seed is bound to it.
+    #[serde(skip)]
+    pub id: Option<i32>,
+    #[serde(rename = "chain")]
+    pub chain: String,
+    #[serde(rename = "seed")]
+    seed: SecretBytes,
+    #[serde(rename = "path")]
+    pub path: String,
+    #[serde(skip)]
+    pub kek_id: Option<i32>,
+    #[serde(skip)]
+    pub wrapped_dek: Option<Vec<u8>>,
+    #[serde(skip)]
+    pub encryption: Option<String>,
+    #[serde(skip, default = "current_wallet_version")]
+    pub wallet_version: i32,
+}
+
+impl NewWallet {
+    pub fn new(chain: String, seed: SecretBytes, path: String) -> NewWallet {
+        NewWallet {
+            id: None,
+            chain,
+            seed,
+            path,
+            kek_id: None,
+            wrapped_dek: None,
+            encryption: None,
+            wallet_version: WALLET_VERSION,
+        }
+    }
+
+    pub fn get_seed(&self) -> &SecretBytes {
+        &self.seed
+    }
+
+    /// The associated data to encrypt the seed with, once the id is reserved.
+    pub fn aad(&self, id: i32) -> Vec<u8> {
+        wallet_aad(&self.chain, id, &self.path, self.wallet_version)
+    }
+
+    /// Set the seed, encrypted with its data key.
+    pub fn set_seed(&mut self, encrypted: Envelope) {
+        self.encryption = Some(EncryptionMode::of(&encrypted).to_string());
+        self.seed = encrypted.secret;
+        self.kek_id = encrypted.kek_id;
+        self.wrapped_dek = encrypted.wrapped_dek;
+    }
+}
+
+#[async_trait]
+pub trait WalletTrait {
+    /// Create a wallet.
+    async fn create_wallet(&self, wallet: NewWallet) -> Result<Wallet, DatabaseError>;
+
+    /// Get a wallet by id, with the seed in plain.
+    async fn get_wallet_by_id(&self, id: i32) -> Result<Option<Wallet>, DatabaseError>;
+}
@@ -10,6 +10,20 @@ diesel::table! {
         suffix -> Varchar,
         used_at -> Nullable<Timestamp>,
         created_at -> Nullable<Timestamp>,
+        pattern -> Nullable<Varchar>,
+        kek_id -> Nullable<Int4>,
+        wrapped_dek -> Nullable<Bytea>,
+        key_version -> Int4,
+        encryption -> Nullable<Varchar>,
+    }
+}
+
+diesel::table! {
+    meta (key) {
+        key -> Varchar,
+        value -> Bytea,
+        kek_id -> Nullable<Int4>,
+        updated_at -> Timestamp,
     }
 }
 
@@ -23,7 +37,23 @@ diesel::table! {
     }
 }
 
+diesel::table! {
+    wallets (id) {
+        id -> Int4,
+        chain -> Varchar,
+        seed -> Bytea,
+        path -> Varchar,
+        created_at -> Nullable<Timestamp>,
+        kek_id -> Nullable<Int4>,
+        wrapped_dek -> Nullable<Bytea>,
+        encryption -> Nullable<Varchar>,
+        wallet_version -> Int4,
+    }
+}
+
 diesel::allow_tables_to_appear_in_same_query!(
     keys,
+    meta,
     users,
+    wallets,
 );
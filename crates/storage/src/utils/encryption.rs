@@ -1,9 +1,9 @@
 use aes_gcm::{
-    aead::{Aead, AeadCore, KeyInit, OsRng},
+    aead::{Aead, AeadCore, KeyInit, OsRng, Payload},
     Aes256Gcm, Error, Key, Nonce,
 };
 
-use crate::DatabaseError;
+use crate::{models::SecretBytes, DatabaseError};
 
 /// This function generates a random AES-256 key.
 #[allow(dead_code)]
@@ -12,10 +12,16 @@ pub fn generate_key() -> Key<Aes256Gcm> {
 }
 
 /// This function checks if a given string is a valid AES-256 key.
-pub fn to_seed(key: &str) -> Result<Vec<u8>, DatabaseError> {
-    let vec = hex::decode(key)?;
+pub fn to_seed(key: &str) -> Result<SecretBytes, DatabaseError> {
+    let vec = SecretBytes::new(hex::decode(key)?);
+    if vec.as_slice().len() != 32 {
+        return Err(DatabaseError::KekError(format!(
+            "the seed must hold 32 bytes in hex, got {}",
+            vec.as_slice().len()
+        )));
+    }
     let key = Key::<Aes256Gcm>::from_slice(vec.as_slice());
-    Ok(key.to_vec())
+    Ok(SecretBytes::from_slice(key.as_slice()))
 }
 
 /// This function encrypts a plaintext message using AES-256 in CBC mode with the provided initialization vector and secret key.
@@ -37,10 +43,15 @@ pub fn to_seed(key: &str) -> Result<Vec<u8>, DatabaseError> {
 /// println!("{:?}", ciphertext);
 /// ```
 pub fn encrypt(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
+    encrypt_with_aad(key, plaintext, &[])
+}
+
+/// Encrypt like [`encrypt`], the ciphertext only decrypts with the same associated data.
+pub fn encrypt_with_aad(key: &[u8], plaintext: &[u8], aad: &[u8]) -> Result<Vec<u8>, Error> {
     let key = Key::<Aes256Gcm>::from_slice(key);
     let nonce = Aes256Gcm::generate_nonce(&mut OsRng); // 96-bits; unique per message
-    let cipher = Aes256Gcm::new(&key);
-    let encrypted = cipher.encrypt(&nonce, plaintext)?;
+    let cipher = Aes256Gcm::new(key);
+    let encrypted = cipher.encrypt(&nonce, Payload { msg: plaintext, aad })?;
 
     // combining nonce and encrypted data together
     // for storage purpose
@@ -66,14 +77,26 @@ pub fn encrypt(key: &[u8], plaintext: &[u8]) -> Result<Vec<u8>, Error> {
 /// let plaintext = decrypt(key, &ciphertext).unwrap();
 /// println!("{:?}", ciphertext);
 /// ```
-pub fn decrypt(key: &[u8], encryptedtext: &[u8]) -> Result<Vec<u8>, Error> {
+pub fn decrypt(key: &[u8], encryptedtext: &[u8]) -> Result<SecretBytes, Error> {
+    decrypt_with_aad(key, encryptedtext, &[])
+}
+
+/// Decrypt a message of [`encrypt_with_aad`] with its associated data.
+pub fn decrypt_with_aad(
+    key: &[u8],
+    encryptedtext: &[u8],
+    aad: &[u8],
+) -> Result<SecretBytes, Error> {
     let key = Key::<Aes256Gcm>::from_slice(key);
+    if encryptedtext.len() < 12 {
+        return Err(Error);
+    }
     let (nonce_arr, ciphertext) = encryptedtext.split_at(12);
     let nonce = Nonce::from_slice(nonce_arr);
 
     let cipher = Aes256Gcm::new(key);
-    let decrypted = cipher.decrypt(nonce, ciphertext)?;
-    Ok(decrypted)
+    let decrypted = cipher.decrypt(nonce, Payload { msg: ciphertext, aad })?;
+    Ok(SecretBytes::new(decrypted))
 }
 
 #[cfg(test)]
@@ -86,13 +109,29 @@ mod tests {
         let key = b"12345678123456781234567812345678"; // 32 bytes
         let text = b"Hello, world!";
 
-        let encryptedtext = encrypt(&key.to_vec(), text).expect("failed to encrypt");
-        let decrypted = decrypt(&key.to_vec(), &encryptedtext[..]).expect("failed to decrypt");
+        let encryptedtext = encrypt(key, text).expect("failed to encrypt");
+        let decrypted = decrypt(key, &encryptedtext[..]).expect("failed to decrypt");
 
-        assert_eq!(text.to_vec(), decrypted);
+        assert_eq!(text, decrypted.as_slice());
         assert_eq!("Hello, world!".to_string(), String::from_utf8(text.to_vec()).unwrap());
     }
 
+    #[test]
+    fn test_encrypt_decrypt_with_aad() {
+        let key = b"12345678123456781234567812345678";
+        let text = b"Hello, world!";
+
+        let encrypted = encrypt_with_aad(key, text, b"row 1").expect("failed to encrypt");
+        let decrypted = decrypt_with_aad(key, &encrypted, b"row 1").expect("failed to decrypt");
+        assert_eq!(text, decrypted.as_slice());
+        assert!(decrypt_with_aad(key, &encrypted, b"row 2").is_err());
+        assert!(decrypt(key, &encrypted).is_err());
+
+        // no associated data is the same as empty associated data
+        let encrypted = encrypt(key, text).expect("failed to encrypt");
+        assert_eq!(text, decrypt_with_aad(key, &encrypted, &[]).unwrap().as_slice());
+    }
+
     #[test]
     fn test_generate_key() {
         let key = generate_key();
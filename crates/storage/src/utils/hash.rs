@@ -5,7 +5,7 @@ use argon2::{
 
 #[allow(dead_code)]
 pub fn verify_password(password: &str, hashed: &str) -> bool {
-    let parsed_hash = PasswordHash::new(&hashed).expect("Failed to parse password hash");
+    let parsed_hash = PasswordHash::new(hashed).expect("Failed to parse password hash");
     Argon2::default().verify_password(password.as_bytes(), &parsed_hash).is_ok()
 }
 
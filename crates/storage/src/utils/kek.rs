@@ -0,0 +1,415 @@
+//! Envelope encryption: every secret is encrypted with a data key of its own, and the data key
+//! is wrapped by a key-encryption key (KEK) from a [`KekProvider`].
+
+use std::{path::Path, sync::Arc};
+
+use async_trait::async_trait;
+use base64::{engine::general_purpose::STANDARD, Engine};
+use openssl::sha::Sha256;
+use rand::{rngs::OsRng, RngCore};
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    models::{SecretBytes, SecretString},
+    utils::encryption::{decrypt, decrypt_with_aad, encrypt, encrypt_with_aad, to_seed},
+    DatabaseError,
+};
+
+/// Wraps and unwraps the data keys.
+#[async_trait]
+pub trait KekProvider: Send + Sync {
+    /// The id saved with the data keys it wraps, the same KEK always has the same id.
+    fn id(&self) -> i32;
+
+    /// Encrypt a data key.
+    async fn wrap(&self, dek: &SecretBytes) -> Result<Vec<u8>, DatabaseError>;
+
+    /// Decrypt a data key.
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<SecretBytes, DatabaseError>;
+}
+
+pub type SharedKek = Arc<dyn KekProvider>;
+
+/// A secret encrypted with its data key, as it is saved.
+#[derive(Debug)]
+pub struct Envelope {
+    pub secret: SecretBytes,
+    /// `None` if the secret is in plain, or encrypted with the KEK itself before data keys.
+    pub wrapped_dek: Option<Vec<u8>>,
+    pub kek_id: Option<i32>,
+}
+
+impl Envelope {
+    /// A secret saved as it is, when the database has no seed.
+    pub fn plain(secret: &SecretBytes) -> Self {
+        Envelope { secret: secret.clone(), wrapped_dek: None, kek_id: None }
+    }
+}
+
+/// Encrypt a secret with a new data key wrapped by `kek`, bound to the associated data.
+pub async fn wrap_secret(
+    kek: &dyn KekProvider,
+    secret: &SecretBytes,
+    aad: &[u8],
+) -> Result<Envelope, DatabaseError> {
+    let mut dek = SecretBytes::new(vec![0; 32]);
+    OsRng.fill_bytes(dek.expose_mut());
+    let encrypted = encrypt_with_aad(dek.as_slice(), secret.as_slice(), aad)
+        .map_err(|e| DatabaseError::SecretError(e.to_string()))?;
+    Ok(Envelope {
+        secret: SecretBytes::new(encrypted),
+        wrapped_dek: Some(kek.wrap(&dek).await?),
+        kek_id: Some(kek.id()),
+    })
+}
+
+/// Decrypt a secret, those without a data key were encrypted with `kek` itself and are not
+/// bound to associated data.
+pub async fn unwrap_secret(
+    kek: &dyn KekProvider,
+    secret: &SecretBytes,
+    wrapped_dek: Option<&[u8]>,
+    aad: &[u8],
+) -> Result<SecretBytes, DatabaseError> {
+    match wrapped_dek {
+        Some(wrapped) => {
+            let dek = kek.unwrap(wrapped).await?;
+            decrypt_with_aad(dek.as_slice(), secret.as_slice(), aad)
+                .map_err(|e| DatabaseError::SecretError(e.to_string()))
+        }
+        None => kek.unwrap(secret.as_slice()).await,
+    }
+}
+
+/// The first 4 bytes of the SHA-256 of what identifies a KEK, with a prefix of its own.
+pub fn kek_id(kek: &[u8]) -> i32 {
+    let mut hasher = Sha256::new();
+    hasher.update(b"anita kek");
+    hasher.update(kek);
+    let hash = hasher.finish();
+    i32::from_be_bytes([hash[0], hash[1], hash[2], hash[3]])
+}
+
+/// A KEK from the command line:
+/// - a hex seed,
+/// - `file:<path>`, a file with the 32 bytes of the key, raw or in hex,
+/// - `passphrase:<salt>`, derived from the passphrase in `KEK_PASSPHRASE`,
+/// - `transit:<address>/<mount>/<key>`, a Vault transit key, with the token in `VAULT_TOKEN`.
+pub fn from_spec(spec: &str) -> Result<SharedKek, DatabaseError> {
+    let env = |name: &str| {
+        std::env::var(name)
+            .map(SecretString::new)
+            .map_err(|_| DatabaseError::KekError(format!("{} is not set", name)))
+    };
+    let kek: SharedKek = match spec.split_once(':') {
+        Some(("file", path)) => Arc::new(LocalKek::from_file(Path::new(path))?),
+        Some(("passphrase", salt)) => {
+            Arc::new(LocalKek::from_passphrase(env("KEK_PASSPHRASE")?.as_str(), salt)?)
+        }
+        Some(("transit", url)) => Arc::new(TransitKek::from_url(url, env("VAULT_TOKEN")?)?),
+        _ => Arc::new(LocalKek::from_seed(to_seed(spec)?)),
+    };
+    Ok(kek)
+}
+
+/// An AES-256 key held by the process.
+pub struct LocalKek {
+    id: i32,
+    key: SecretBytes,
+}
+
+impl LocalKek {
+    /// The database seed.
+    pub fn from_seed(seed: SecretBytes) -> Self {
+        LocalKek { id: kek_id(seed.as_slice()), key: seed }
+    }
+
+    /// A key file with the 32 bytes of the key, raw or in hex.
+    pub fn from_file(path: &Path) -> Result<Self, DatabaseError> {
+        let contents = SecretBytes::new(
+            std::fs::read(path)
+                .map_err(|e| DatabaseError::KekError(format!("{}: {}", path.display(), e)))?,
+        );
+        let key = match contents.as_slice() {
+            raw if raw.len() == 32 => SecretBytes::from_slice(raw),
+            text => match std::str::from_utf8(text).map(str::trim) {
+                Ok(hex) if hex.len() == 64 => SecretBytes::new(hex::decode(hex)?),
+                _ => {
+                    return Err(DatabaseError::KekError(format!(
+                        "{} must hold 32 bytes, raw or in hex",
+                        path.display()
+                    )))
+                }
+            },
+        };
+        Ok(Self::from_seed(key))
+    }
+
+    /// A key derived from a passphrase with Argon2id, the salt is not secret but must not change.
+    pub fn from_passphrase(passphrase: &str, salt: &str) -> Result<Self, DatabaseError> {
+        let mut key = SecretBytes::new(vec![0; 32]);
+        argon2::Argon2::default()
+            .hash_password_into(passphrase.as_bytes(), salt.as_bytes(), key.expose_mut())
+            .map_err(|e| DatabaseError::KekError(e.to_string()))?;
+        Ok(Self::from_seed(key))
+    }
+}
+
+#[async_trait]
+impl KekProvider for LocalKek {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    async fn wrap(&self, dek: &SecretBytes) -> Result<Vec<u8>, DatabaseError> {
+        encrypt(self.key.as_slice(), dek.as_slice())
+            .map_err(|e| DatabaseError::SecretError(e.to_string()))
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<SecretBytes, DatabaseError> {
+        decrypt(self.key.as_slice(), wrapped).map_err(|e| DatabaseError::SecretError(e.to_string()))
+    }
+}
+
+/// A key of the Vault transit secrets engine, or of any server with the same API.
+pub struct TransitKek {
+    id: i32,
+    client: reqwest::Client,
+    /// `<address>/v1/<mount>`
+    endpoint: String,
+    key: String,
+    token: SecretString,
+}
+
+#[derive(Serialize)]
+struct TransitRequest<'a> {
+    #[serde(skip_serializing_if = "Option::is_none")]
+    plaintext: Option<&'a str>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    ciphertext: Option<&'a str>,
+}
+
+#[derive(Deserialize)]
+struct TransitResponse {
+    data: TransitData,
+}
+
+#[derive(Deserialize)]
+struct TransitData {
+    plaintext: Option<SecretString>,
+    ciphertext: Option<String>,
+}
+
+impl TransitKek {
+    pub fn new(address: &str, mount: &str, key: &str, token: SecretString) -> Self {
+        let address = address.trim_end_matches('/');
+        let mount = mount.trim_matches('/');
+        TransitKek {
+            id: kek_id(format!("transit:{}/{}/{}", address, mount, key).as_bytes()),
+            client: reqwest::Client::new(),
+            endpoint: format!("{}/v1/{}", address, mount),
+            key: key.to_string(),
+            token,
+        }
+    }
+
+    /// `<address>/<mount>/<key>`, like `https://vault:8200/transit/anita`.
+    pub fn from_url(url: &str, token: SecretString) -> Result<Self, DatabaseError> {
+        let invalid =
+            || DatabaseError::KekError(format!("`{}` is not <address>/<mount>/<key>", url));
+        let url = reqwest::Url::parse(url).map_err(|_| invalid())?;
+        let (mount, key) = url.path().trim_matches('/').rsplit_once('/').ok_or_else(invalid)?;
+        if mount.is_empty() || key.is_empty() {
+            return Err(invalid());
+        }
+        Ok(Self::new(url.origin().ascii_serialization().as_str(), mount, key, token))
+    }
+
+    async fn call(&self, op: &str, body: TransitRequest<'_>) -> Result<TransitData, DatabaseError> {
+        let url = format!("{}/{}/{}", self.endpoint, op, self.key);
+        let response = self
+            .client
+            .post(url.as_str())
+            .header("X-Vault-Token", self.token.as_str())
+            .json(&body)
+            .send()
+            .await
+            .and_then(|response| response.error_for_status())
+            .map_err(|e| DatabaseError::KekError(format!("transit {}: {}", op, e)))?;
+        let response: TransitResponse = response
+            .json()
+            .await
+            .map_err(|e| DatabaseError::KekError(format!("transit {}: {}", op, e)))?;
+        Ok(response.data)
+    }
+}
+
+#[async_trait]
+impl KekProvider for TransitKek {
+    fn id(&self) -> i32 {
+        self.id
+    }
+
+    async fn wrap(&self, dek: &SecretBytes) -> Result<Vec<u8>, DatabaseError> {
+        let plaintext = SecretString::new(STANDARD.encode(dek.as_slice()));
+        let body = TransitRequest { plaintext: Some(plaintext.as_str()), ciphertext: None };
+        let data = self.call("encrypt", body).await?;
+        let ciphertext = data
+            .ciphertext
+            .ok_or_else(|| DatabaseError::KekError("transit encrypt: no ciphertext".to_string()))?;
+        Ok(ciphertext.into_bytes())
+    }
+
+    async fn unwrap(&self, wrapped: &[u8]) -> Result<SecretBytes, DatabaseError> {
+        let ciphertext = std::str::from_utf8(wrapped)
+            .map_err(|_| DatabaseError::SecretError("not a transit ciphertext".to_string()))?;
+        let body = TransitRequest { plaintext: None, ciphertext: Some(ciphertext) };
+        let data = self.call("decrypt", body).await?;
+        let plaintext = data
+            .plaintext
+            .ok_or_else(|| DatabaseError::KekError("transit decrypt: no plaintext".to_string()))?;
+        STANDARD
+            .decode(plaintext.as_str())
+            .map(SecretBytes::new)
+            .map_err(|e| DatabaseError::KekError(format!("transit decrypt: {}", e)))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use actix_web::{post, web, App, HttpRequest, HttpResponse, HttpServer};
+    use serde_json::{json, Value};
+
+    use super::*;
+
+    const SEED: &[u8; 32] = b"12345678123456781234567812345678";
+
+    /// A stand-in for the transit engine, with one key and the token `root`.
+    #[post("/v1/transit/{op}/anita")]
+    async fn transit(
+        req: HttpRequest,
+        op: web::Path<String>,
+        body: web::Json<Value>,
+    ) -> HttpResponse {
+        if req.headers().get("X-Vault-Token").map_or(true, |token| token != "root") {
+            return HttpResponse::Forbidden().finish();
+        }
+        let field = |name: &str| body[name].as_str().unwrap_or_default().to_string();
+        match op.as_str() {
+            "encrypt" => {
+                let plaintext = STANDARD.decode(field("plaintext")).unwrap();
+                let encrypted = encrypt(SEED, &plaintext).unwrap();
+                let ciphertext = format!("vault:v1:{}", STANDARD.encode(encrypted));
+                HttpResponse::Ok().json(json!({ "data": { "ciphertext": ciphertext } }))
+            }
+            "decrypt" => {
+                let ciphertext = field("ciphertext");
+                let encrypted = ciphertext.strip_prefix("vault:v1:").map(|c| STANDARD.decode(c));
+                match encrypted.map(|e| decrypt(SEED, &e.unwrap())) {
+                    Some(Ok(plaintext)) => HttpResponse::Ok().json(
+                        json!({ "data": { "plaintext": STANDARD.encode(plaintext.as_slice()) } }),
+                    ),
+                    _ => HttpResponse::BadRequest().finish(),
+                }
+            }
+            _ => HttpResponse::NotFound().finish(),
+        }
+    }
+
+    #[tokio::test]
+    async fn test_local_kek() {
+        let kek = LocalKek::from_seed(SecretBytes::from_slice(SEED));
+        let other =
+            LocalKek::from_seed(SecretBytes::from_slice(b"87654321876543218765432187654321"));
+        assert_eq!(kek.id(), kek_id(SEED));
+        assert_ne!(kek.id(), other.id());
+
+        let secret = SecretBytes::from_slice(b"Hello, world!");
+        let envelope = wrap_secret(&kek, &secret, b"row").await.unwrap();
+        assert_eq!(envelope.kek_id, Some(kek.id()));
+        let wrapped = envelope.wrapped_dek.as_deref();
+        assert_eq!(unwrap_secret(&kek, &envelope.secret, wrapped, b"row").await.unwrap(), secret);
+        assert!(unwrap_secret(&kek, &envelope.secret, wrapped, b"other row").await.is_err());
+        assert!(unwrap_secret(&other, &envelope.secret, wrapped, b"row").await.is_err());
+
+        // secrets from before data keys are encrypted with the seed itself
+        let direct = SecretBytes::new(encrypt(SEED, secret.as_slice()).unwrap());
+        assert_eq!(unwrap_secret(&kek, &direct, None, &[]).await.unwrap(), secret);
+        assert!(kek.unwrap(&[1, 2]).await.is_err());
+    }
+
+    #[test]
+    fn test_from_file() {
+        let dir = std::env::temp_dir().join(format!("anita-kek-{}", std::process::id()));
+        std::fs::create_dir_all(&dir).unwrap();
+        let (raw, hex, short) = (dir.join("raw"), dir.join("hex"), dir.join("short"));
+        std::fs::write(&raw, SEED).unwrap();
+        std::fs::write(&hex, format!("{}\n", hex::encode(SEED))).unwrap();
+        std::fs::write(&short, b"too short").unwrap();
+
+        assert_eq!(LocalKek::from_file(&raw).unwrap().id(), kek_id(SEED));
+        assert_eq!(LocalKek::from_file(&hex).unwrap().id(), kek_id(SEED));
+        assert!(LocalKek::from_file(&short).is_err());
+        assert!(LocalKek::from_file(&dir.join("missing")).is_err());
+        std::fs::remove_dir_all(dir).unwrap();
+    }
+
+    #[test]
+    fn test_from_passphrase() {
+        let kek = LocalKek::from_passphrase("correct horse", "anita-salt").unwrap();
+        assert_eq!(
+            kek.id(),
+            LocalKek::from_passphrase("correct horse", "anita-salt").unwrap().id()
+        );
+        assert_ne!(
+            kek.id(),
+            LocalKek::from_passphrase("correct horse", "other-salt").unwrap().id()
+        );
+        assert_ne!(
+            kek.id(),
+            LocalKek::from_passphrase("battery staple", "anita-salt").unwrap().id()
+        );
+        assert!(LocalKek::from_passphrase("correct horse", "salt").is_err());
+    }
+
+    #[test]
+    fn test_from_spec() {
+        assert_eq!(from_spec(&hex::encode(SEED)).unwrap().id(), kek_id(SEED));
+        assert!(from_spec("not hex").is_err());
+        let short = from_spec(&hex::encode(&SEED[..16])).err().unwrap();
+        assert!(matches!(short, DatabaseError::KekError(_)), "{short}");
+
+        let token = || SecretString::new("root".to_string());
+        let kek = TransitKek::from_url("http://127.0.0.1:8200/transit/anita", token()).unwrap();
+        assert_eq!(kek.endpoint, "http://127.0.0.1:8200/v1/transit");
+        assert_eq!(kek.key, "anita");
+        assert!(TransitKek::from_url("http://127.0.0.1:8200/anita", token()).is_err());
+        assert!(TransitKek::from_url("127.0.0.1", token()).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_transit_kek() {
+        let server =
+            HttpServer::new(|| App::new().service(transit)).workers(1).bind("127.0.0.1:0").unwrap();
+        let address = format!("http://{}", server.addrs()[0]);
+        let server = server.run();
+        let handle = server.handle();
+        tokio::spawn(server);
+
+        let kek = TransitKek::new(&address, "transit", "anita", SecretString::new("root".into()));
+        let secret = SecretBytes::from_slice(b"Hello, world!");
+        let envelope = wrap_secret(&kek, &secret, &[]).await.unwrap();
+        let wrapped = envelope.wrapped_dek.as_deref();
+        assert!(wrapped.unwrap().starts_with(b"vault:v1:"));
+        assert_eq!(unwrap_secret(&kek, &envelope.secret, wrapped, &[]).await.unwrap(), secret);
+
+        let forbidden =
+            TransitKek::new(&address, "transit", "anita", SecretString::new("guest".into()));
+        assert!(matches!(
+            unwrap_secret(&forbidden, &envelope.secret, wrapped, &[]).await,
+            Err(DatabaseError::KekError(_))
+        ));
+        assert!(kek.unwrap(b"vault:v1:AAAA").await.is_err());
+        handle.stop(false).await;
+    }
+}